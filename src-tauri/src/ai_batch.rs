@@ -0,0 +1,271 @@
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+use tokio::sync::Semaphore;
+use tokio::time::Instant;
+
+use crate::ai_client::AiActionRequest;
+use crate::ai_service::{self, AiContext};
+use crate::db::{ClipKind, DerivedClip};
+use crate::runtime_config::AiBatchPolicy;
+
+/// Runs one action over many clips. The action fields are the same as for
+/// `perform_ai_action`; `input` and `sourceClipId` are filled per clip.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AiBatchRequest {
+    /// Chosen by the caller so the batch can be cancelled while it runs.
+    pub batch_id: String,
+    pub clip_ids: Vec<i64>,
+    #[serde(flatten)]
+    pub action: AiActionRequest,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct AiBatchProgress {
+    pub batch_id: String,
+    pub clip_id: i64,
+    pub completed: usize,
+    pub total: usize,
+    pub ok: bool,
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct AiBatchItemResult {
+    pub clip_id: i64,
+    pub ok: bool,
+    pub result: Option<String>,
+    /// Same shape as `saved_clip` in an `AiActionResponse`.
+    pub saved_clip: Option<DerivedClip>,
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct AiBatchReport {
+    pub batch_id: String,
+    pub cancelled: bool,
+    pub succeeded: usize,
+    pub failed: usize,
+    pub items: Vec<AiBatchItemResult>,
+}
+
+/// Cancellation flags of the batches that are currently running.
+#[derive(Default)]
+pub struct AiBatchRegistry(Mutex<HashMap<String, Arc<AtomicBool>>>);
+
+impl AiBatchRegistry {
+    fn start<'a>(&'a self, batch_id: &'a str) -> anyhow::Result<RunningBatch<'a>> {
+        let mut batches = self
+            .0
+            .lock()
+            .map_err(|_| anyhow::anyhow!("batch registry poisoned"))?;
+        if batches.contains_key(batch_id) {
+            anyhow::bail!("batch {} is already running", batch_id);
+        }
+        let cancelled = Arc::new(AtomicBool::new(false));
+        batches.insert(batch_id.to_string(), cancelled.clone());
+        Ok(RunningBatch {
+            registry: self,
+            batch_id,
+            cancelled,
+        })
+    }
+
+    fn finish(&self, batch_id: &str) {
+        if let Ok(mut batches) = self.0.lock() {
+            batches.remove(batch_id);
+        }
+    }
+
+    /// Returns `false` when no batch with this id is running.
+    pub fn cancel(&self, batch_id: &str) -> bool {
+        match self.0.lock() {
+            Ok(batches) => match batches.get(batch_id) {
+                Some(flag) => {
+                    flag.store(true, Ordering::SeqCst);
+                    true
+                }
+                None => false,
+            },
+            Err(_) => false,
+        }
+    }
+}
+
+/// Registry entry of a running batch. Dropping it, including when the caller
+/// drops the batch future, removes the entry and stops items that have not
+/// started yet.
+struct RunningBatch<'a> {
+    registry: &'a AiBatchRegistry,
+    batch_id: &'a str,
+    cancelled: Arc<AtomicBool>,
+}
+
+impl Drop for RunningBatch<'_> {
+    fn drop(&mut self) {
+        self.cancelled.store(true, Ordering::SeqCst);
+        self.registry.finish(self.batch_id);
+    }
+}
+
+/// Spaces request starts evenly so a batch stays under the configured
+/// requests per minute.
+struct RateLimiter {
+    interval: Option<Duration>,
+    next: tokio::sync::Mutex<Instant>,
+}
+
+impl RateLimiter {
+    fn new(requests_per_minute: u32) -> Self {
+        Self {
+            interval: (requests_per_minute > 0)
+                .then(|| Duration::from_secs(60) / requests_per_minute),
+            next: tokio::sync::Mutex::new(Instant::now()),
+        }
+    }
+
+    async fn wait(&self) {
+        let Some(interval) = self.interval else {
+            return;
+        };
+        let slot = {
+            let mut next = self.next.lock().await;
+            let slot = (*next).max(Instant::now());
+            *next = slot + interval;
+            slot
+        };
+        tokio::time::sleep_until(slot).await;
+    }
+}
+
+/// Processes the clips with at most `policy.concurrency` requests in flight.
+/// Every result is saved as a clip derived from its source. Items that had
+/// not started when the batch was cancelled are reported as failed.
+pub async fn run(
+    ctx: &AiContext,
+    registry: &AiBatchRegistry,
+    request: AiBatchRequest,
+    policy: &AiBatchPolicy,
+    on_progress: impl Fn(AiBatchProgress) + Send + Sync + 'static,
+) -> anyhow::Result<AiBatchReport> {
+    if request.clip_ids.is_empty() {
+        anyhow::bail!("请至少选择一条剪贴板记录");
+    }
+    let running = registry.start(&request.batch_id)?;
+    let items = run_items(
+        ctx,
+        &request,
+        policy,
+        running.cancelled.clone(),
+        on_progress,
+    )
+    .await?;
+    let cancelled = running.cancelled.load(Ordering::SeqCst);
+    drop(running);
+
+    let succeeded = items.iter().filter(|item| item.ok).count();
+    Ok(AiBatchReport {
+        batch_id: request.batch_id,
+        cancelled,
+        succeeded,
+        failed: items.len() - succeeded,
+        items,
+    })
+}
+
+async fn run_items(
+    ctx: &AiContext,
+    request: &AiBatchRequest,
+    policy: &AiBatchPolicy,
+    cancelled: Arc<AtomicBool>,
+    on_progress: impl Fn(AiBatchProgress) + Send + Sync + 'static,
+) -> anyhow::Result<Vec<AiBatchItemResult>> {
+    let semaphore = Arc::new(Semaphore::new(policy.concurrency.max(1)));
+    let limiter = Arc::new(RateLimiter::new(policy.requests_per_minute));
+    let on_progress = Arc::new(on_progress);
+    let completed = Arc::new(AtomicUsize::new(0));
+    let total = request.clip_ids.len();
+
+    let mut handles = Vec::with_capacity(total);
+    for &clip_id in &request.clip_ids {
+        let ctx = ctx.clone();
+        let template = request.action.clone();
+        let batch_id = request.batch_id.clone();
+        let semaphore = semaphore.clone();
+        let limiter = limiter.clone();
+        let cancelled = cancelled.clone();
+        let on_progress = on_progress.clone();
+        let completed = completed.clone();
+        handles.push(tauri::async_runtime::spawn(async move {
+            let outcome = async {
+                let _permit = semaphore.acquire_owned().await?;
+                if cancelled.load(Ordering::SeqCst) {
+                    anyhow::bail!("已取消");
+                }
+                limiter.wait().await;
+                if cancelled.load(Ordering::SeqCst) {
+                    anyhow::bail!("已取消");
+                }
+                run_item(&ctx, template, clip_id).await
+            }
+            .await;
+
+            let item = match outcome {
+                Ok((result, saved_clip)) => AiBatchItemResult {
+                    clip_id,
+                    ok: true,
+                    result: Some(result),
+                    saved_clip,
+                    error: None,
+                },
+                Err(err) => AiBatchItemResult {
+                    clip_id,
+                    ok: false,
+                    result: None,
+                    saved_clip: None,
+                    error: Some(format!("{err:#}")),
+                },
+            };
+            on_progress(AiBatchProgress {
+                batch_id,
+                clip_id,
+                completed: completed.fetch_add(1, Ordering::SeqCst) + 1,
+                total,
+                ok: item.ok,
+                error: item.error.clone(),
+            });
+            item
+        }));
+    }
+
+    let mut items = Vec::with_capacity(total);
+    for handle in handles {
+        items.push(handle.await?);
+    }
+    Ok(items)
+}
+
+async fn run_item(
+    ctx: &AiContext,
+    mut request: AiActionRequest,
+    clip_id: i64,
+) -> anyhow::Result<(String, Option<DerivedClip>)> {
+    let db = ctx.db.clone_for_thread();
+    let clip = tauri::async_runtime::spawn_blocking(move || db.get(clip_id))
+        .await??
+        .ok_or_else(|| anyhow::anyhow!("剪贴板记录 {} 不存在", clip_id))?;
+    request.input = match clip.kind {
+        ClipKind::Image if request.action.is_vision() => String::new(),
+        ClipKind::Image => anyhow::bail!("图片记录不支持该操作"),
+        _ if request.action.is_vision() => anyhow::bail!("该操作仅支持图片记录"),
+        _ => clip.content,
+    };
+    request.source_clip_id = Some(clip_id);
+    request.save_result = true;
+    let response = ai_service::run(ctx, request).await?;
+    Ok((response.result, response.saved_clip))
+}
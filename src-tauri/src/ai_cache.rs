@@ -0,0 +1,172 @@
+use blake3::Hasher;
+use chrono::{Duration, Utc};
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::{Deserialize, Serialize};
+
+use crate::ai_client::{self, AiActionKind, AiActionRequest, AiActionResponse, AiRoute};
+use crate::db::{datetime_to_timestamp, DbState};
+use crate::json_output;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum AiCacheStatus {
+    Hit,
+    Miss,
+    Bypassed,
+    Disabled,
+}
+
+#[derive(Debug, Clone)]
+pub struct CachedAiResult {
+    pub result: String,
+    pub used_prompt: String,
+    pub model: String,
+}
+
+pub(crate) fn migrate(conn: &Connection) -> anyhow::Result<()> {
+    conn.execute_batch(
+        r#"
+        CREATE TABLE IF NOT EXISTS ai_cache (
+            cache_key TEXT PRIMARY KEY,
+            result TEXT NOT NULL,
+            used_prompt TEXT NOT NULL,
+            model TEXT NOT NULL,
+            created_at INTEGER NOT NULL,
+            last_hit_at INTEGER NOT NULL,
+            hit_count INTEGER NOT NULL DEFAULT 0
+        );
+        CREATE INDEX IF NOT EXISTS idx_ai_cache_last_hit ON ai_cache(last_hit_at DESC);
+        "#,
+    )?;
+    Ok(())
+}
+
+/// Hash of everything that influences the model output: provider, model,
+/// action, language, prompts, temperature and input.
+pub fn cache_key(request: &AiActionRequest) -> String {
+    let (system_prompt, user_prompt) = ai_client::build_prompts(request);
+    let provider = request.base_url.trim().trim_end_matches('/');
+    let model = ai_client::resolve_model(request);
+    let language = request.language.as_deref().unwrap_or_default().trim();
+    let temperature = ai_client::resolve_temperature(request)
+        .to_bits()
+        .to_le_bytes();
+    let mut hasher = Hasher::new();
+    for part in [
+        provider.as_bytes(),
+        model.as_bytes(),
+        request.action.as_str().as_bytes(),
+        language.as_bytes(),
+        system_prompt.as_bytes(),
+        user_prompt.as_bytes(),
+        &temperature,
+        request.input.as_bytes(),
+        request
+            .image_base64
+            .as_deref()
+            .unwrap_or_default()
+            .as_bytes(),
+    ] {
+        // Length-prefix each part so adjacent fields cannot bleed into each other.
+        hasher.update(&(part.len() as u64).to_le_bytes());
+        hasher.update(part);
+    }
+    hasher.finalize().to_hex().to_string()
+}
+
+impl CachedAiResult {
+    pub fn into_response(self, request: &AiActionRequest) -> AiActionResponse {
+        let mut response = AiActionResponse {
+            result: self.result,
+            used_prompt: self.used_prompt,
+            model: self.model,
+            finished_at: Utc::now(),
+            saved_clip: None,
+            cache: AiCacheStatus::Hit,
+            json_valid: None,
+            json_errors: Vec::new(),
+            route: AiRoute::Cache,
+            usage: None,
+            cost_usd: None,
+        };
+        if let AiActionKind::Jsonify = request.action {
+            let check = json_output::check(&response.result, request.json_schema.as_ref());
+            response.apply_json_check(&check);
+        }
+        response
+    }
+}
+
+impl DbState {
+    /// Looks up a cached result. Entries older than `max_age` are ignored;
+    /// pass `None` to accept stale entries (used while offline).
+    pub fn cached_ai_result(
+        &self,
+        key: &str,
+        max_age: Option<Duration>,
+    ) -> anyhow::Result<Option<CachedAiResult>> {
+        let now = Utc::now();
+        let min_created_at = max_age
+            .map(|age| datetime_to_timestamp(now - age))
+            .unwrap_or(i64::MIN);
+        let cached = self.read(|conn| {
+            Ok(conn
+                .prepare_cached(
+                    "SELECT result, used_prompt, model FROM ai_cache WHERE cache_key = ?1 AND created_at >= ?2",
+                )?
+                .query_row(params![key, min_created_at], |row| {
+                    Ok(CachedAiResult {
+                        result: row.get(0)?,
+                        used_prompt: row.get(1)?,
+                        model: row.get(2)?,
+                    })
+                })
+                .optional()?)
+        })?;
+        if cached.is_some() {
+            let key = key.to_owned();
+            self.write(move |conn| {
+                conn.prepare_cached(
+                    "UPDATE ai_cache SET last_hit_at = ?1, hit_count = hit_count + 1 WHERE cache_key = ?2",
+                )?
+                .execute(params![datetime_to_timestamp(now), key])?;
+                Ok(())
+            })?;
+        }
+        Ok(cached)
+    }
+
+    /// Stores a fresh result and evicts the least recently used entries
+    /// beyond `max_entries`.
+    pub fn store_ai_result(
+        &self,
+        key: &str,
+        response: &AiActionResponse,
+        max_entries: usize,
+    ) -> anyhow::Result<()> {
+        let key = key.to_owned();
+        let (result, used_prompt, model) = (
+            response.result.clone(),
+            response.used_prompt.clone(),
+            response.model.clone(),
+        );
+        self.write(move |conn| {
+            let now = datetime_to_timestamp(Utc::now());
+            let tx = conn.transaction()?;
+            tx.execute(
+                "INSERT OR REPLACE INTO ai_cache (cache_key, result, used_prompt, model, created_at, last_hit_at, hit_count) VALUES (?1, ?2, ?3, ?4, ?5, ?5, 0)",
+                params![key, result, used_prompt, model, now],
+            )?;
+            tx.execute(
+                "DELETE FROM ai_cache WHERE cache_key NOT IN (SELECT cache_key FROM ai_cache ORDER BY last_hit_at DESC LIMIT ?1)",
+                params![max_entries as i64],
+            )?;
+            tx.commit()?;
+            Ok(())
+        })
+    }
+
+    pub fn clear_ai_cache(&self) -> anyhow::Result<usize> {
+        self.write(move |conn| Ok(conn.execute("DELETE FROM ai_cache", [])?))
+    }
+}
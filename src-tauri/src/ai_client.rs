@@ -2,40 +2,189 @@ use anyhow::{Context, Result};
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Clone, Deserialize)]
+use crate::ai_cache::AiCacheStatus;
+use crate::db::DerivedClip;
+use crate::json_output::{self, JsonCheck};
+use crate::prompt_templates::RenderedPrompt;
+
+#[derive(Debug, Clone, Default, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct AiActionRequest {
     pub action: AiActionKind,
+    #[serde(default)]
     pub input: String,
     pub language: Option<String>,
     pub custom_prompt: Option<String>,
+    /// Stored provider profile; fills in the base URL, key and model when
+    /// they are left empty.
+    #[serde(default)]
+    pub provider_id: Option<i64>,
+    #[serde(default)]
     pub api_key: String,
+    #[serde(default)]
     pub base_url: String,
     pub model: Option<String>,
     #[serde(default)]
     pub temperature: Option<f32>,
+    /// Clip the input was taken from, recorded as the origin of a saved result.
+    #[serde(default)]
+    pub source_clip_id: Option<i64>,
+    /// Persist the result as a new clip linked to `source_clip_id`.
+    #[serde(default)]
+    pub save_result: bool,
+    /// Skip the response cache lookup; the fresh result still refreshes it.
+    #[serde(default)]
+    pub bypass_cache: bool,
+    /// Stored prompt template to use instead of the built-in prompts.
+    #[serde(default)]
+    pub template_id: Option<i64>,
+    /// JSON Schema the Jsonify result is validated against.
+    #[serde(default)]
+    pub json_schema: Option<serde_json::Value>,
+    /// `template_id` rendered by the backend before the request is sent.
+    #[serde(skip)]
+    pub rendered_template: Option<RenderedPrompt>,
+    /// Base64 PNG of the source image clip, loaded by the backend for vision actions.
+    #[serde(skip)]
+    pub image_base64: Option<String>,
+}
+
+impl AiActionRequest {
+    /// Label recorded in provenance and usage data for this request.
+    pub fn action_label(&self) -> String {
+        match &self.rendered_template {
+            Some(template) => format!("template:{}", template.template_name),
+            None => self.action.as_str().to_string(),
+        }
+    }
 }
 
 #[derive(Debug, Clone, Serialize)]
 pub struct AiActionResponse {
     pub result: String,
     pub used_prompt: String,
+    pub model: String,
     pub finished_at: DateTime<Utc>,
+    pub saved_clip: Option<DerivedClip>,
+    pub cache: AiCacheStatus,
+    /// Whether the Jsonify result parsed and matched the schema; `None` for other actions.
+    pub json_valid: Option<bool>,
+    pub json_errors: Vec<String>,
+    pub route: AiRoute,
+    /// Token counts reported by the provider, summed over retries.
+    pub usage: Option<TokenUsage>,
+    pub cost_usd: Option<f64>,
+}
+
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+pub struct TokenUsage {
+    pub prompt_tokens: u64,
+    pub completion_tokens: u64,
+}
+
+impl TokenUsage {
+    /// Reads the usage block of OpenAI (`usage.prompt_tokens`), Anthropic
+    /// (`usage.input_tokens`) and Gemini (`usageMetadata.promptTokenCount`)
+    /// shaped responses.
+    pub fn from_response(body: &serde_json::Value) -> Option<Self> {
+        let count = |value: &serde_json::Value, keys: &[&str]| {
+            keys.iter()
+                .find_map(|key| value.get(*key).and_then(|count| count.as_u64()))
+        };
+        if let Some(usage) = body.get("usage") {
+            let prompt = count(usage, &["prompt_tokens", "input_tokens"]);
+            let completion = count(usage, &["completion_tokens", "output_tokens"]);
+            if prompt.is_some() || completion.is_some() {
+                return Some(Self {
+                    prompt_tokens: prompt.unwrap_or_default(),
+                    completion_tokens: completion.unwrap_or_default(),
+                });
+            }
+        }
+        let metadata = body.get("usageMetadata")?;
+        Some(Self {
+            prompt_tokens: count(metadata, &["promptTokenCount"]).unwrap_or_default(),
+            completion_tokens: count(metadata, &["candidatesTokenCount"]).unwrap_or_default(),
+        })
+    }
+
+    pub fn combine(a: Option<Self>, b: Option<Self>) -> Option<Self> {
+        match (a, b) {
+            (Some(a), Some(b)) => Some(Self {
+                prompt_tokens: a.prompt_tokens + b.prompt_tokens,
+                completion_tokens: a.completion_tokens + b.completion_tokens,
+            }),
+            (a, b) => a.or(b),
+        }
+    }
+}
+
+/// Where a request was answered. Local requests never leave the machine and
+/// cached answers are not sent anywhere.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum AiRoute {
+    Remote,
+    Local,
+    Cache,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct LocalEndpointProbe {
+    pub reachable: bool,
+    pub base_url: String,
+    pub latency_ms: u64,
+    pub models: Vec<String>,
+    pub error: Option<String>,
 }
 
-#[derive(Debug, Clone, Deserialize, Serialize)]
+impl AiActionResponse {
+    /// Validates a Jsonify result and replaces it with the normalized JSON.
+    pub fn apply_json_check(&mut self, check: &JsonCheck) {
+        if let Some(normalized) = check.normalized() {
+            self.result = normalized;
+        }
+        self.json_valid = Some(check.is_valid());
+        self.json_errors = check.errors.clone();
+    }
+}
+
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
 #[serde(rename_all = "camelCase")]
 pub enum AiActionKind {
     Translate,
     Summarize,
     Polish,
     Jsonify,
+    #[default]
     Custom,
+    Ocr,
+    Describe,
 }
 
-pub async fn perform(request: AiActionRequest) -> Result<AiActionResponse> {
+impl AiActionKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AiActionKind::Translate => "translate",
+            AiActionKind::Summarize => "summarize",
+            AiActionKind::Polish => "polish",
+            AiActionKind::Jsonify => "jsonify",
+            AiActionKind::Custom => "custom",
+            AiActionKind::Ocr => "ocr",
+            AiActionKind::Describe => "describe",
+        }
+    }
+
+    /// Actions that operate on an image clip instead of text input.
+    pub fn is_vision(&self) -> bool {
+        matches!(self, AiActionKind::Ocr | AiActionKind::Describe)
+    }
+}
+
+pub async fn perform(request: AiActionRequest, route: AiRoute) -> Result<AiActionResponse> {
     let api_key = request.api_key.trim().to_string();
-    if api_key.is_empty() {
+    // Local servers such as Ollama usually run without authentication.
+    if api_key.is_empty() && route == AiRoute::Remote {
         anyhow::bail!("OpenAI compatible API key is not configured");
     }
     let base_url = request.base_url.trim().trim_end_matches('/');
@@ -43,31 +192,101 @@ pub async fn perform(request: AiActionRequest) -> Result<AiActionResponse> {
         anyhow::bail!("OpenAI compatible Base URL is missing");
     }
 
-    let model = request
-        .model
-        .clone()
-        .unwrap_or_else(|| "gpt-4o-mini".to_string());
+    let schema = match request.action {
+        AiActionKind::Jsonify => request.json_schema.as_ref(),
+        _ => None,
+    };
+    if let Some(schema) = schema {
+        json_output::ensure_valid_schema(schema)?;
+    }
+
+    let model = resolve_model(&request);
+    let temperature = resolve_temperature(&request);
     let (system_prompt, user_prompt) = build_prompts(&request);
+    let user_content = match &request.image_base64 {
+        Some(image) => serde_json::json!([
+            {"type": "text", "text": user_prompt},
+            {"type": "image_url", "image_url": {"url": format!("data:image/png;base64,{}", image.trim())}}
+        ]),
+        None => serde_json::Value::String(user_prompt.clone()),
+    };
+    let mut messages = vec![
+        serde_json::json!({"role": "system", "content": system_prompt}),
+        serde_json::json!({"role": "user", "content": user_content}),
+    ];
+
+    let client = match route {
+        AiRoute::Local => local_client(base_url)?,
+        _ => reqwest::Client::new(),
+    };
+    let url = endpoint_url(base_url, "chat/completions");
+    let first = complete_chat(&client, &url, &api_key, &model, &messages, temperature).await?;
+    let mut usage = first.usage;
+    let mut result = first.content;
+
+    let mut json_check = None;
+    if let AiActionKind::Jsonify = request.action {
+        let mut check = json_output::check(&result, schema);
+        if !check.is_valid() {
+            // Feed the parse or schema errors back once and let the model fix its output.
+            messages.push(serde_json::json!({"role": "assistant", "content": result}));
+            messages.push(serde_json::json!({"role": "user", "content": check.retry_prompt()}));
+            match complete_chat(&client, &url, &api_key, &model, &messages, temperature).await {
+                Ok(retry) => {
+                    usage = TokenUsage::combine(usage, retry.usage);
+                    result = retry.content;
+                    check = json_output::check(&result, schema);
+                }
+                // Return the first answer with its errors rather than failing,
+                // so the tokens it used are still recorded.
+                Err(err) => tracing::warn!("jsonify retry failed: {err:#}"),
+            }
+        }
+        json_check = Some(check);
+    }
+
+    let mut response = AiActionResponse {
+        result,
+        used_prompt: user_prompt,
+        model,
+        finished_at: Utc::now(),
+        saved_clip: None,
+        cache: AiCacheStatus::Miss,
+        json_valid: None,
+        json_errors: Vec::new(),
+        route,
+        usage,
+        cost_usd: None,
+    };
+    if let Some(check) = json_check {
+        response.apply_json_check(&check);
+    }
+    Ok(response)
+}
+
+struct ChatCompletion {
+    content: String,
+    usage: Option<TokenUsage>,
+}
+
+async fn complete_chat(
+    client: &reqwest::Client,
+    url: &str,
+    api_key: &str,
+    model: &str,
+    messages: &[serde_json::Value],
+    temperature: f32,
+) -> Result<ChatCompletion> {
     let payload = serde_json::json!({
         "model": model,
-        "messages": [
-            {"role": "system", "content": system_prompt},
-            {"role": "user", "content": user_prompt}
-        ],
-        "temperature": request.temperature.unwrap_or(0.3_f32)
+        "messages": messages,
+        "temperature": temperature
     });
-
-    let client = reqwest::Client::new();
-    // Handle base URLs that already include /v1 (e.g., Aliyun DashScope)
-    let url = if base_url.ends_with("/v1") {
-        format!("{}/chat/completions", base_url)
-    } else {
-        format!("{}/v1/chat/completions", base_url)
-    };
-    let response = client
-        .post(url)
-        .bearer_auth(api_key)
-        .json(&payload)
+    let mut builder = client.post(url).json(&payload);
+    if !api_key.is_empty() {
+        builder = builder.bearer_auth(api_key);
+    }
+    let response = builder
         .send()
         .await
         .context("failed to call OpenAI compatible endpoint")?;
@@ -90,21 +309,135 @@ pub async fn perform(request: AiActionRequest) -> Result<AiActionResponse> {
         .and_then(|msg| msg.get("content"))
         .and_then(|content| content.as_str())
         .context("AI response does not include assistant message")?;
-
-    Ok(AiActionResponse {
-        result: message.trim().to_string(),
-        used_prompt: user_prompt,
-        finished_at: Utc::now(),
+    Ok(ChatCompletion {
+        content: message.trim().to_string(),
+        usage: TokenUsage::from_response(&body),
     })
 }
 
-fn build_prompts(request: &AiActionRequest) -> (String, String) {
-    let language = request
+/// Checks that a local server answers on its OpenAI-compatible model list.
+pub async fn probe_local_endpoint(base_url: &str, api_key: Option<&str>) -> LocalEndpointProbe {
+    let base_url = base_url.trim().trim_end_matches('/').to_string();
+    let started = std::time::Instant::now();
+    let outcome = async {
+        let client = local_client(&base_url)?;
+        let mut builder = client
+            .get(endpoint_url(&base_url, "models"))
+            .timeout(std::time::Duration::from_secs(3));
+        if let Some(key) = api_key.map(str::trim).filter(|key| !key.is_empty()) {
+            builder = builder.bearer_auth(key);
+        }
+        let response = builder
+            .send()
+            .await
+            .context("local AI endpoint is unreachable")?;
+        if !response.status().is_success() {
+            anyhow::bail!("local AI endpoint responded with {}", response.status());
+        }
+        let body: serde_json::Value = response
+            .json()
+            .await
+            .context("failed to decode model list")?;
+        let models = body
+            .get("data")
+            .and_then(|data| data.as_array())
+            .map(|models| {
+                models
+                    .iter()
+                    .filter_map(|model| model.get("id").and_then(|id| id.as_str()))
+                    .map(|id| id.to_string())
+                    .collect()
+            })
+            .unwrap_or_default();
+        Ok::<Vec<String>, anyhow::Error>(models)
+    }
+    .await;
+    let latency_ms = started.elapsed().as_millis() as u64;
+    match outcome {
+        Ok(models) => LocalEndpointProbe {
+            reachable: true,
+            base_url,
+            latency_ms,
+            models,
+            error: None,
+        },
+        Err(err) => LocalEndpointProbe {
+            reachable: false,
+            base_url,
+            latency_ms,
+            models: Vec::new(),
+            error: Some(format!("{err:#}")),
+        },
+    }
+}
+
+/// Client for the offline route: loopback hosts only and no redirects, so a
+/// local proxy cannot bounce the request to a remote server.
+fn local_client(base_url: &str) -> Result<reqwest::Client> {
+    ensure_loopback(base_url)?;
+    reqwest::Client::builder()
+        .redirect(reqwest::redirect::Policy::none())
+        .no_proxy()
+        .build()
+        .context("failed to build local AI client")
+}
+
+pub fn ensure_loopback(base_url: &str) -> Result<()> {
+    let url = reqwest::Url::parse(base_url.trim())
+        .with_context(|| format!("invalid local AI endpoint: {base_url}"))?;
+    let host = url
+        .host_str()
+        .unwrap_or_default()
+        .trim_start_matches('[')
+        .trim_end_matches(']');
+    let is_loopback = host.eq_ignore_ascii_case("localhost")
+        || host
+            .parse::<std::net::IpAddr>()
+            .map(|ip| ip.is_loopback())
+            .unwrap_or(false);
+    if !is_loopback {
+        anyhow::bail!("离线模式下只允许访问本机 AI 服务，已拦截 {}", base_url);
+    }
+    Ok(())
+}
+
+/// Handles base URLs that already include /v1 (e.g., Aliyun DashScope).
+fn endpoint_url(base_url: &str, path: &str) -> String {
+    if base_url.ends_with("/v1") {
+        format!("{}/{}", base_url, path)
+    } else {
+        format!("{}/v1/{}", base_url, path)
+    }
+}
+
+pub(crate) fn resolve_model(request: &AiActionRequest) -> String {
+    request
+        .model
+        .as_deref()
+        .map(str::trim)
+        .filter(|model| !model.is_empty())
+        .unwrap_or("gpt-4o-mini")
+        .to_string()
+}
+
+pub(crate) fn resolve_temperature(request: &AiActionRequest) -> f32 {
+    request.temperature.unwrap_or(0.3_f32)
+}
+
+pub(crate) fn resolve_language(request: &AiActionRequest) -> &str {
+    request
         .language
         .as_ref()
         .map(|s| s.trim())
         .filter(|s| !s.is_empty())
-        .unwrap_or("zh-CN");
+        .unwrap_or("zh-CN")
+}
+
+pub(crate) fn build_prompts(request: &AiActionRequest) -> (String, String) {
+    if let Some(template) = &request.rendered_template {
+        return (template.system_prompt.clone(), template.user_prompt.clone());
+    }
+    let language = resolve_language(request);
 
     match request.action {
         AiActionKind::Translate => (
@@ -131,12 +464,38 @@ Keep the meaning but improve fluency in {}.",
             ),
             format!("Improve the following content:\n{}", request.input.trim()),
         ),
-        AiActionKind::Jsonify => (
-            "You are VibeClip Pro, a data formatter returning strict JSON.".to_string(),
+        AiActionKind::Jsonify => {
+            let schema_hint = request
+                .json_schema
+                .as_ref()
+                .map(|schema| format!("The JSON must conform to this JSON Schema:\n{}\n", schema))
+                .unwrap_or_default();
+            (
+                "You are VibeClip Pro, a data formatter returning strict JSON.".to_string(),
+                format!(
+                    "Convert the following content into valid JSON. Use lowercase keys.\n{}{}",
+                    schema_hint,
+                    request.input.trim()
+                ),
+            )
+        }
+        AiActionKind::Ocr => (
+            "You are VibeClip Pro, an OCR engine.
+Transcribe every piece of text visible in the image exactly as written, preserving line breaks.
+Reply with the extracted text only; reply with an empty message if there is no text."
+                .to_string(),
+            "Extract the text from this image.".to_string(),
+        ),
+        AiActionKind::Describe => (
             format!(
-                "Convert the following content into valid JSON. Use lowercase keys.\n{}",
-                request.input.trim()
+                "You are VibeClip Pro, a visual assistant.
+Describe images concisely and factually in {}.",
+                language
             ),
+            match request.input.trim() {
+                "" => "Describe this image.".to_string(),
+                hint => format!("Describe this image. Focus on: {}", hint),
+            },
         ),
         AiActionKind::Custom => (
             request
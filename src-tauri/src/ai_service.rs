@@ -0,0 +1,350 @@
+use anyhow::Context;
+
+use crate::ai_cache::{self, AiCacheStatus};
+use crate::ai_client::{self, AiActionKind, AiActionRequest, AiActionResponse, AiRoute};
+use crate::clip_events::ClipOrigin;
+use crate::clipboard::finalize_payload;
+use crate::db::{ClipKind, ClipPayload, DbState, NewProvenance};
+use crate::lang_detect;
+use crate::prompt_templates::RenderedPrompt;
+use crate::runtime_config::{RuntimePreferences, TranslationPair};
+use crate::secret_store::SecretName;
+use crate::usage::{self, UsageRecord};
+
+/// Everything an AI action needs besides the request itself. Commands build
+/// it from managed state so the same pipeline serves every entry point.
+#[derive(Debug, Clone)]
+pub struct AiContext {
+    pub db: DbState,
+    pub prefs: RuntimePreferences,
+    pub offline: bool,
+}
+
+/// Runs a request end to end: provider profiles, prompt templates, image
+/// loading, the response cache, offline routing and saving the result as a
+/// derived clip.
+pub async fn run(
+    ctx: &AiContext,
+    mut request: AiActionRequest,
+) -> anyhow::Result<AiActionResponse> {
+    if let Some(provider_id) = request.provider_id {
+        resolve_provider(&ctx.db, provider_id, &mut request).await?;
+    }
+    resolve_auto_language(&mut request, &ctx.prefs.translation_pair);
+    if let Some(template_id) = request.template_id {
+        request.rendered_template =
+            Some(render_prompt_template(&ctx.db, template_id, &request).await?);
+    }
+    if request.action.is_vision() {
+        request.image_base64 = Some(load_image_clip(&ctx.db, request.source_clip_id).await?);
+    }
+
+    // Vision results are always kept so the extracted text stays linked to its image.
+    let save_result = request.save_result || request.action.is_vision();
+    let source_clip_id = request.source_clip_id;
+    let action = request.action_label();
+    let cache_policy = &ctx.prefs.ai_cache;
+    let local_profile = &ctx.prefs.local_ai;
+
+    let remote_key = ai_cache::cache_key(&request);
+    let route = if ctx.offline {
+        if !local_profile.enabled {
+            None
+        } else {
+            let db = ctx.db.clone_for_thread();
+            request.base_url = local_profile.base_url.clone();
+            request.api_key =
+                tauri::async_runtime::spawn_blocking(move || db.secret(SecretName::LocalAiApiKey))
+                    .await??
+                    .unwrap_or_default();
+            request.model = Some(local_profile.model.clone());
+            Some(AiRoute::Local)
+        }
+    } else {
+        Some(AiRoute::Remote)
+    };
+    let cache_key = match route {
+        Some(AiRoute::Local) => ai_cache::cache_key(&request),
+        _ => remote_key.clone(),
+    };
+
+    // While offline, cached answers are served even if they are past their TTL,
+    // including answers that were produced by the remote provider earlier.
+    let mut cached = None;
+    if (cache_policy.enabled || ctx.offline) && !request.bypass_cache {
+        let max_age = if ctx.offline {
+            None
+        } else {
+            cache_policy.max_age()
+        };
+        let mut keys = vec![remote_key];
+        if cache_key != keys[0] {
+            keys.push(cache_key.clone());
+        }
+        let db = ctx.db.clone_for_thread();
+        cached = tauri::async_runtime::spawn_blocking(move || {
+            for key in keys {
+                if let Some(hit) = db.cached_ai_result(&key, max_age)? {
+                    return Ok(Some(hit));
+                }
+            }
+            Ok::<_, anyhow::Error>(None)
+        })
+        .await?
+        .unwrap_or_else(|err| {
+            tracing::warn!("ai cache lookup failed: {err:?}");
+            None
+        });
+    }
+
+    let mut response = match (cached, route) {
+        (Some(hit), _) => hit.into_response(&request),
+        (None, None) => anyhow::bail!("离线模式已开启，且未配置本地 AI 服务"),
+        (None, Some(route)) => {
+            if route == AiRoute::Remote {
+                let db = ctx.db.clone_for_thread();
+                let pricing = ctx.prefs.ai_pricing.clone();
+                let budget =
+                    tauri::async_runtime::spawn_blocking(move || db.budget_status(&pricing))
+                        .await??;
+                if budget.exceeded {
+                    anyhow::bail!(
+                        "本月 AI 预算已用完（${:.2} / ${:.2}）",
+                        budget.month_cost_usd,
+                        budget.monthly_budget_usd.unwrap_or_default()
+                    );
+                }
+            }
+            let bypass_cache = request.bypass_cache;
+            let provider = request.base_url.trim().trim_end_matches('/').to_string();
+            let mut response = ai_client::perform(request, route).await?;
+            response.cost_usd = response.usage.as_ref().and_then(|usage| {
+                usage::estimate_cost(&ctx.prefs.ai_pricing, &response.model, usage)
+            });
+            let record = UsageRecord {
+                provider,
+                model: response.model.clone(),
+                action: action.clone(),
+                route,
+                usage: response.usage.unwrap_or_default(),
+                cost_usd: response.cost_usd,
+            };
+            // Awaited so that a short-lived caller such as the CLI does not
+            // exit before the call is accounted for.
+            let db = ctx.db.clone_for_thread();
+            if let Err(err) =
+                tauri::async_runtime::spawn_blocking(move || db.record_ai_usage(&record)).await?
+            {
+                tracing::warn!("failed to record ai usage: {err:?}");
+            }
+            response.cache = if !cache_policy.enabled {
+                AiCacheStatus::Disabled
+            } else if bypass_cache {
+                AiCacheStatus::Bypassed
+            } else {
+                AiCacheStatus::Miss
+            };
+            // JSON that failed validation is not worth serving again.
+            if cache_policy.enabled && response.json_valid != Some(false) {
+                let db = ctx.db.clone_for_thread();
+                let fresh = response.clone();
+                let max_entries = cache_policy.max_entries;
+                // Awaited like the usage record above.
+                if let Err(err) = tauri::async_runtime::spawn_blocking(move || {
+                    db.store_ai_result(&cache_key, &fresh, max_entries)
+                })
+                .await?
+                {
+                    tracing::warn!("failed to store ai cache entry: {err:?}");
+                }
+            }
+            response
+        }
+    };
+
+    if save_result && !response.result.is_empty() {
+        let payload = finalize_payload(ClipPayload {
+            kind: ClipKind::Text,
+            content: response.result.clone(),
+            preview: Some(response.result.chars().take(120).collect()),
+            extra: None,
+            content_hash: None,
+            is_pinned: false,
+            is_favorite: false,
+            source_app: None,
+        });
+        let provenance = NewProvenance {
+            derived_from: source_clip_id,
+            action,
+            model: Some(response.model.clone()),
+            prompt: Some(response.used_prompt.clone()),
+        };
+        let prefs = ctx.prefs.clone();
+        let db = ctx.db.clone_for_thread();
+        let derived = tauri::async_runtime::spawn_blocking(move || {
+            let derived = db.insert_derived(payload, provenance, ClipOrigin::Ai)?;
+            db.apply_retention(&prefs)?;
+            Ok::<_, anyhow::Error>(derived)
+        })
+        .await??;
+        response.saved_clip = Some(derived);
+    }
+
+    Ok(response)
+}
+
+/// Takes the endpoint and key from a stored provider profile. Both always come
+/// from the profile together, so a request cannot send the stored key to an
+/// address of its own choosing; only the model may still be picked per request.
+async fn resolve_provider(
+    db: &DbState,
+    provider_id: i64,
+    request: &mut AiActionRequest,
+) -> anyhow::Result<()> {
+    if !request.base_url.trim().is_empty() || !request.api_key.trim().is_empty() {
+        anyhow::bail!(
+            "a request that names a provider profile must not set its own base URL or API key"
+        );
+    }
+    let db = db.clone_for_thread();
+    let credentials =
+        tauri::async_runtime::spawn_blocking(move || db.provider_credentials(provider_id))
+            .await??;
+    request.base_url = credentials.base_url;
+    request.api_key = credentials.api_key;
+    if request
+        .model
+        .as_deref()
+        .is_none_or(|model| model.trim().is_empty())
+    {
+        request.model = credentials.model;
+    }
+    Ok(())
+}
+
+/// Replaces `language: "auto"` with a concrete target. Translations go to the
+/// other side of the configured pair, every other action keeps the language
+/// the input is written in.
+fn resolve_auto_language(request: &mut AiActionRequest, pair: &TranslationPair) {
+    let is_auto = request
+        .language
+        .as_deref()
+        .is_some_and(|language| language.trim().eq_ignore_ascii_case("auto"));
+    if !is_auto {
+        return;
+    }
+    let detected = lang_detect::detect(&request.input);
+    let target = match (&request.action, detected) {
+        (AiActionKind::Translate, Some(source))
+            if lang_detect::same_language(&source, &pair.primary) =>
+        {
+            pair.secondary.clone()
+        }
+        (AiActionKind::Translate, _) => pair.primary.clone(),
+        (_, Some(source)) => source,
+        (_, None) => pair.primary.clone(),
+    };
+    request.language = Some(target);
+}
+
+async fn load_image_clip(db: &DbState, clip_id: Option<i64>) -> anyhow::Result<String> {
+    let clip_id = clip_id.context("图像操作需要指定图像剪贴板条目")?;
+    let db = db.clone_for_thread();
+    let clip = tauri::async_runtime::spawn_blocking(move || db.get(clip_id))
+        .await??
+        .with_context(|| format!("剪贴板条目 {clip_id} 不存在"))?;
+    match clip.kind {
+        ClipKind::Image => Ok(clip.content),
+        _ => anyhow::bail!("剪贴板条目 {clip_id} 不是图像"),
+    }
+}
+
+async fn render_prompt_template(
+    db: &DbState,
+    template_id: i64,
+    request: &AiActionRequest,
+) -> anyhow::Result<RenderedPrompt> {
+    let db = db.clone_for_thread();
+    let source_clip_id = request.source_clip_id;
+    let (template, clip) = tauri::async_runtime::spawn_blocking(move || {
+        let template = db
+            .get_prompt_template(template_id)?
+            .with_context(|| format!("prompt template {} does not exist", template_id))?;
+        let clip = match source_clip_id {
+            Some(id) => db.get(id)?,
+            None => None,
+        };
+        Ok::<_, anyhow::Error>((template, clip))
+    })
+    .await??;
+    Ok(template.render(
+        &request.input,
+        ai_client::resolve_language(request),
+        clip.as_ref(),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn auto_language_targets() {
+        let pair = TranslationPair::default();
+        let cases = [
+            // Translations go to the other side of the pair.
+            (
+                AiActionKind::Translate,
+                "今天天气很好，我们去公园散步吧。",
+                "en",
+            ),
+            (
+                AiActionKind::Translate,
+                "The quick brown fox jumps over the lazy dog and runs away.",
+                "zh-CN",
+            ),
+            // Undetectable or foreign input goes to the primary language.
+            (AiActionKind::Translate, "ok", "zh-CN"),
+            (
+                AiActionKind::Translate,
+                "Привет, как дела у тебя сегодня?",
+                "zh-CN",
+            ),
+            // Other actions answer in the language of the input.
+            (
+                AiActionKind::Summarize,
+                "Привет, как дела у тебя сегодня?",
+                "ru",
+            ),
+            (
+                AiActionKind::Polish,
+                "今天天气很好，我们去公园散步吧。",
+                "zh-CN",
+            ),
+            (AiActionKind::Polish, "ok", "zh-CN"),
+        ];
+        for (action, input, expected) in cases {
+            let mut request = AiActionRequest {
+                action: action.clone(),
+                input: input.to_string(),
+                language: Some("Auto".to_string()),
+                ..Default::default()
+            };
+            resolve_auto_language(&mut request, &pair);
+            assert_eq!(
+                request.language.as_deref(),
+                Some(expected),
+                "{action:?} {input:?}"
+            );
+        }
+
+        let mut request = AiActionRequest {
+            action: AiActionKind::Translate,
+            input: "今天天气很好".to_string(),
+            language: Some("ja".to_string()),
+            ..Default::default()
+        };
+        resolve_auto_language(&mut request, &pair);
+        assert_eq!(request.language.as_deref(), Some("ja"));
+    }
+}
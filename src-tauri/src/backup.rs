@@ -0,0 +1,316 @@
+//! Online backups of the history database.
+//!
+//! Backups are copied page by page with SQLite's backup API, so the app keeps
+//! running while they are taken. They live in `backups/` next to the
+//! database, named after the UTC time they were taken, and are rotated so
+//! that the newest copy of each of the last N days and N ISO weeks survives.
+
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use anyhow::Context;
+use chrono::{DateTime, Datelike, Local, NaiveDateTime, Utc};
+use rusqlite::backup::Backup;
+use rusqlite::{Connection, DatabaseName, OpenFlags};
+use serde::Serialize;
+use tauri::{AppHandle, Emitter, Manager};
+use tracing::{info, warn};
+
+use crate::db::DbState;
+use crate::runtime_config::RuntimeConfigState;
+use crate::state::AppStatus;
+use crate::undo::UndoState;
+
+const BACKUP_DIR: &str = "backups";
+const BACKUP_PREFIX: &str = "vibeclip_pro-";
+const BACKUP_SUFFIX: &str = ".db";
+const BACKUP_TIME_FORMAT: &str = "%Y%m%d-%H%M%S";
+/// How often the scheduler checks whether a backup is due.
+const CHECK_INTERVAL: Duration = Duration::from_secs(10 * 60);
+
+#[derive(Debug, Clone, Serialize)]
+pub struct BackupInfo {
+    pub file_name: String,
+    pub created_at: DateTime<Utc>,
+    pub size: u64,
+}
+
+/// Runs `PRAGMA integrity_check` and fails with its findings unless the
+/// database is intact.
+pub(crate) fn integrity_check(conn: &Connection) -> anyhow::Result<()> {
+    run_check(conn, "integrity_check")
+}
+
+/// Like [`integrity_check`], but skips the index cross-checks so that it is
+/// cheap enough for every startup.
+pub(crate) fn quick_check(conn: &Connection) -> anyhow::Result<()> {
+    run_check(conn, "quick_check")
+}
+
+/// Problems found by `PRAGMA quick_check` or `integrity_check`, as opposed
+/// to failing to run the check at all.
+#[derive(Debug, thiserror::Error)]
+#[error("{pragma} failed: {findings}")]
+pub(crate) struct CheckFailed {
+    pragma: &'static str,
+    findings: String,
+}
+
+fn run_check(conn: &Connection, pragma: &'static str) -> anyhow::Result<()> {
+    let mut stmt = conn.prepare(&format!("PRAGMA {pragma}"))?;
+    let findings = stmt
+        .query_map([], |row| row.get::<_, String>(0))?
+        .collect::<Result<Vec<_>, _>>()?;
+    if findings.len() == 1 && findings[0] == "ok" {
+        return Ok(());
+    }
+    let summary = findings.iter().take(5).cloned().collect::<Vec<_>>();
+    Err(CheckFailed {
+        pragma,
+        findings: summary.join("; "),
+    }
+    .into())
+}
+
+fn parse_backup_name(file_name: &str) -> Option<DateTime<Utc>> {
+    let stamp = file_name
+        .strip_prefix(BACKUP_PREFIX)?
+        .strip_suffix(BACKUP_SUFFIX)?;
+    NaiveDateTime::parse_from_str(stamp, BACKUP_TIME_FORMAT)
+        .ok()
+        .map(|time| time.and_utc())
+}
+
+impl DbState {
+    pub fn backup_dir(&self) -> PathBuf {
+        self.data_dir().join(BACKUP_DIR)
+    }
+
+    /// Newest first.
+    pub fn list_backups(&self) -> anyhow::Result<Vec<BackupInfo>> {
+        let dir = self.backup_dir();
+        if !dir.exists() {
+            return Ok(Vec::new());
+        }
+        let mut backups = Vec::new();
+        for entry in
+            std::fs::read_dir(&dir).with_context(|| format!("failed to read {}", dir.display()))?
+        {
+            let entry = entry?;
+            let file_name = entry.file_name().to_string_lossy().into_owned();
+            let Some(created_at) = parse_backup_name(&file_name) else {
+                continue;
+            };
+            backups.push(BackupInfo {
+                file_name,
+                created_at,
+                size: entry.metadata()?.len(),
+            });
+        }
+        backups.sort_by_key(|backup| std::cmp::Reverse(backup.created_at));
+        Ok(backups)
+    }
+
+    /// Checks the live database and copies it into the backup directory.
+    /// A database that fails the integrity check is not backed up, so a
+    /// corrupted copy never pushes good ones out of the rotation.
+    pub fn create_backup(&self) -> anyhow::Result<BackupInfo> {
+        let dir = self.backup_dir();
+        std::fs::create_dir_all(&dir)
+            .with_context(|| format!("failed to create {}", dir.display()))?;
+        let mut created_at = Utc::now();
+        let mut file_name = format!(
+            "{BACKUP_PREFIX}{}{BACKUP_SUFFIX}",
+            created_at.format(BACKUP_TIME_FORMAT)
+        );
+        // Two backups within the same second would share a name.
+        while dir.join(&file_name).exists() {
+            created_at += chrono::Duration::seconds(1);
+            file_name = format!(
+                "{BACKUP_PREFIX}{}{BACKUP_SUFFIX}",
+                created_at.format(BACKUP_TIME_FORMAT)
+            );
+        }
+        let partial = dir.join(format!("{file_name}.partial"));
+        let _ = std::fs::remove_file(&partial);
+        self.read(|source| {
+            integrity_check(source).context("refusing to back up a damaged database")?;
+            let mut target = Connection::open(&partial)
+                .with_context(|| format!("failed to create {}", partial.display()))?;
+            Backup::new(source, &mut target)?.run_to_completion(
+                256,
+                Duration::from_millis(10),
+                None,
+            )?;
+            // A self-contained file, without -wal/-shm companions.
+            target.execute_batch("PRAGMA journal_mode = DELETE;")?;
+            Ok(())
+        })?;
+        let path = dir.join(&file_name);
+        std::fs::rename(&partial, &path)
+            .with_context(|| format!("failed to move backup to {}", path.display()))?;
+        Ok(BackupInfo {
+            file_name,
+            created_at,
+            size: std::fs::metadata(&path)?.len(),
+        })
+    }
+
+    /// Keeps the newest backup of each of the last `keep_daily` days and
+    /// `keep_weekly` weeks, and always the newest one. Returns how many
+    /// backups were deleted.
+    pub fn rotate_backups(&self, keep_daily: usize, keep_weekly: usize) -> anyhow::Result<usize> {
+        let backups = self.list_backups()?;
+        let mut keep = HashSet::new();
+        let mut days = Vec::new();
+        let mut weeks = Vec::new();
+        for (index, backup) in backups.iter().enumerate() {
+            let date = backup.created_at.with_timezone(&Local).date_naive();
+            let week = (date.iso_week().year(), date.iso_week().week());
+            if index == 0 {
+                keep.insert(index);
+            }
+            if !days.contains(&date) && days.len() < keep_daily {
+                days.push(date);
+                keep.insert(index);
+            }
+            if !weeks.contains(&week) && weeks.len() < keep_weekly {
+                weeks.push(week);
+                keep.insert(index);
+            }
+        }
+        let dir = self.backup_dir();
+        let mut removed = 0;
+        for (index, backup) in backups.iter().enumerate() {
+            if keep.contains(&index) {
+                continue;
+            }
+            match std::fs::remove_file(dir.join(&backup.file_name)) {
+                Ok(()) => removed += 1,
+                Err(err) => warn!("failed to remove old backup {}: {err}", backup.file_name),
+            }
+        }
+        Ok(removed)
+    }
+
+    /// Replaces the live database with a backup, in place so that other
+    /// connections keep working. The current state is backed up first when
+    /// it is intact. Returns that safety copy.
+    pub fn restore_backup(&self, file_name: &str) -> anyhow::Result<Option<BackupInfo>> {
+        if parse_backup_name(file_name).is_none() || Path::new(file_name).components().count() != 1
+        {
+            anyhow::bail!("{file_name} is not a backup file");
+        }
+        let path = self.backup_dir().join(file_name);
+        {
+            let backup = Connection::open_with_flags(&path, OpenFlags::SQLITE_OPEN_READ_ONLY)
+                .with_context(|| format!("failed to open {}", path.display()))?;
+            integrity_check(&backup).context("the backup is damaged")?;
+        }
+        let safety = match self.create_backup() {
+            Ok(info) => Some(info),
+            Err(err) => {
+                warn!("restoring without a copy of the current database: {err:#}");
+                None
+            }
+        };
+        // On the writer, so that no write can interleave with the restore.
+        self.write(move |live| {
+            live.restore(
+                DatabaseName::Main,
+                &path,
+                None::<fn(rusqlite::backup::Progress)>,
+            )
+            .context("failed to restore backup")
+        })?;
+        // Older backups may predate newer tables and columns.
+        self.migrate()?;
+        Ok(safety)
+    }
+}
+
+/// Serializes backups and restores started from the app.
+#[derive(Default)]
+pub struct BackupState {
+    busy: std::sync::Mutex<()>,
+}
+
+impl BackupState {
+    fn run<T>(&self, job: impl FnOnce() -> anyhow::Result<T>) -> anyhow::Result<T> {
+        let _guard = self
+            .busy
+            .try_lock()
+            .map_err(|_| anyhow::anyhow!("另一个备份或恢复任务正在进行"))?;
+        job()
+    }
+}
+
+/// Takes a backup and rotates old ones according to the preferences.
+pub fn backup_now(app: &AppHandle) -> anyhow::Result<BackupInfo> {
+    let db = app.state::<DbState>().clone_for_thread();
+    let policy = app.state::<RuntimeConfigState>().get().backup;
+    let info = app.state::<BackupState>().run(|| {
+        let info = db.create_backup()?;
+        let removed = db.rotate_backups(policy.keep_daily, policy.keep_weekly)?;
+        info!(
+            "database backed up to {} ({removed} old backups removed)",
+            info.file_name
+        );
+        Ok(info)
+    })?;
+    let _ = app.emit("backup://created", &info);
+    Ok(info)
+}
+
+/// Restores a backup with clipboard capture paused, then tells every window
+/// to reload. The undo stack refers to the replaced rows and is dropped.
+pub fn restore(app: &AppHandle, file_name: &str) -> anyhow::Result<Option<BackupInfo>> {
+    let db = app.state::<DbState>().clone_for_thread();
+    let status = app.state::<AppStatus>();
+    status.set_capture_suspended(true);
+    let result = app
+        .state::<BackupState>()
+        .run(|| db.restore_backup(file_name));
+    status.set_capture_suspended(false);
+    let safety = result?;
+    let undo = app.state::<UndoState>();
+    undo.clear();
+    let _ = app.emit("undo://changed", undo.status());
+    let _ = app.emit(
+        "backup://restored",
+        serde_json::json!({ "file_name": file_name, "safety_copy": safety }),
+    );
+    Ok(safety)
+}
+
+/// Checks periodically whether a backup is due and takes it.
+pub fn spawn_scheduler(app: AppHandle) {
+    tauri::async_runtime::spawn(async move {
+        loop {
+            let policy = app.state::<RuntimeConfigState>().get().backup;
+            if policy.enabled && policy.interval_hours > 0 {
+                let check_app = app.clone();
+                let result = tauri::async_runtime::spawn_blocking(move || {
+                    let db = check_app.state::<DbState>().clone_for_thread();
+                    let latest = db.list_backups()?.first().map(|backup| backup.created_at);
+                    let due = latest.is_none_or(|latest| {
+                        Utc::now() - latest
+                            >= chrono::Duration::hours(i64::from(policy.interval_hours))
+                    });
+                    if due {
+                        backup_now(&check_app)?;
+                    }
+                    anyhow::Ok(())
+                })
+                .await;
+                match result {
+                    Ok(Err(err)) => warn!("automatic backup failed: {err:#}"),
+                    Err(err) => warn!("automatic backup task failed: {err}"),
+                    Ok(Ok(())) => {}
+                }
+            }
+            tokio::time::sleep(CHECK_INTERVAL).await;
+        }
+    });
+}
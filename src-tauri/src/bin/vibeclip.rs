@@ -0,0 +1,3 @@
+fn main() -> std::process::ExitCode {
+    vibeclip_pro_lib::cli::main()
+}
@@ -0,0 +1,708 @@
+//! `vibeclip`: headless access to the clipboard history database.
+//!
+//! The CLI opens the same SQLite file as the desktop app and never creates a
+//! window, so it also works over SSH or on machines without a display.
+
+use std::io::{Read, Write};
+use std::path::PathBuf;
+use std::process::ExitCode;
+
+use anyhow::Context;
+use base64::engine::general_purpose::STANDARD as BASE64_STANDARD;
+use base64::Engine;
+use clap::{Parser, Subcommand};
+
+use crate::ai_client::{AiActionKind, AiActionRequest};
+use crate::ai_service::{self, AiContext};
+use crate::clip_events::{ClipEventQuery, ClipOrigin};
+use crate::clipboard::finalize_payload;
+use crate::db::{ClipItem, ClipKind, ClipPayload, DbState};
+use crate::history_export::{render_export, ExportFilter, ExportFormat};
+use crate::history_import::{load_history_file, ImportOptions, ImportStrategy};
+use crate::lan_sync::LanSync;
+use crate::remote_sync::{RemoteSync, RemoteSyncSecrets};
+use crate::runtime_config::{RemoteSyncConfig, RuntimePreferences};
+
+/// Same identifier as `tauri.conf.json`, so the default directory matches the
+/// app's `app_data_dir`.
+const APP_IDENTIFIER: &str = "app.vibeclip.pro";
+const DEFAULT_LIMIT: u32 = 20;
+
+#[derive(Debug, Parser)]
+#[command(
+    name = "vibeclip",
+    version,
+    about = "Command-line access to VibeClip Pro history"
+)]
+struct Cli {
+    /// Print machine-readable JSON instead of a table.
+    #[arg(long, global = true)]
+    json: bool,
+    /// Directory that holds vibeclip_pro.db.
+    #[arg(long, global = true, env = "VIBECLIP_DATA_DIR")]
+    data_dir: Option<PathBuf>,
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Debug, Subcommand)]
+enum Command {
+    /// List the most recent clips.
+    List {
+        #[arg(short, long, default_value_t = DEFAULT_LIMIT)]
+        limit: u32,
+        #[arg(long, default_value_t = 0)]
+        offset: u32,
+    },
+    /// Search clip content and previews.
+    Search {
+        query: String,
+        #[arg(short, long, default_value_t = DEFAULT_LIMIT)]
+        limit: u32,
+    },
+    /// Print one clip.
+    Get { id: i64 },
+    /// Put a clip on the system clipboard.
+    Copy {
+        id: i64,
+        /// Keep serving the clipboard until another application takes it
+        /// over. Needed on X11/Wayland without a clipboard manager.
+        #[arg(long)]
+        wait: bool,
+    },
+    /// Add a text clip read from stdin.
+    Add {
+        #[arg(long)]
+        pin: bool,
+    },
+    /// Write the history to a file or stdout.
+    Export {
+        path: Option<PathBuf>,
+        /// json can be imported again; zip needs a path.
+        #[arg(long, value_enum, default_value_t)]
+        format: ExportFormat,
+        /// Only clips whose content or preview contains this text.
+        #[arg(long)]
+        query: Option<String>,
+        /// Only the newest clips.
+        #[arg(short, long)]
+        limit: Option<u32>,
+    },
+    /// Import clips from a VibeClip export or from CopyQ, Ditto, Maccy,
+    /// GPaste or clipman history files.
+    Import {
+        path: PathBuf,
+        /// What to do with clips whose content is already stored.
+        #[arg(long, value_enum, default_value_t)]
+        strategy: ImportStrategy,
+        /// Only report what would change.
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// Keep only the newest clips.
+    Prune {
+        #[arg(long)]
+        keep: usize,
+    },
+    /// Show the journal of history changes, newest first.
+    Events {
+        /// Only events for this clip.
+        #[arg(long)]
+        clip: Option<i64>,
+        #[arg(short, long, default_value_t = DEFAULT_LIMIT)]
+        limit: u32,
+    },
+    /// Create, list or restore database backups.
+    Backup {
+        #[command(subcommand)]
+        action: BackupAction,
+    },
+    /// Sync history with paired devices on the local network.
+    Sync {
+        #[command(subcommand)]
+        action: SyncAction,
+    },
+    /// Send control flags such as --toggle-panel or --paste-clip <id> to the
+    /// running app.
+    #[cfg(unix)]
+    Remote {
+        #[arg(trailing_var_arg = true, allow_hyphen_values = true, required = true)]
+        args: Vec<String>,
+    },
+    /// Run an AI action on stdin or on a stored clip.
+    Ai {
+        /// translate, summarize, polish, jsonify, custom, ocr or describe.
+        action: String,
+        /// Use this clip as input instead of stdin.
+        #[arg(long)]
+        id: Option<i64>,
+        #[arg(long)]
+        language: Option<String>,
+        #[arg(long)]
+        prompt: Option<String>,
+        /// Stored provider profile to use.
+        #[arg(long)]
+        provider: Option<i64>,
+        #[arg(long, env = "VIBECLIP_BASE_URL", default_value = "")]
+        base_url: String,
+        #[arg(
+            long,
+            env = "VIBECLIP_API_KEY",
+            default_value = "",
+            hide_env_values = true
+        )]
+        api_key: String,
+        #[arg(long)]
+        model: Option<String>,
+        /// Save the result as a clip derived from the input.
+        #[arg(long)]
+        save: bool,
+    },
+}
+
+#[derive(Debug, Subcommand)]
+enum SyncAction {
+    /// Accept sync connections until interrupted and print a pairing code.
+    Serve {
+        #[arg(long, default_value_t = 0)]
+        port: u16,
+        /// Don't announce this device over mDNS.
+        #[arg(long)]
+        no_mdns: bool,
+        #[arg(long)]
+        name: Option<String>,
+    },
+    /// Pair with the device at <address> (host:port) using the code it shows.
+    Pair { address: String, code: String },
+    /// Sync with one paired device, or with all of them.
+    Now {
+        /// Device id, or host:port of a paired device.
+        target: Option<String>,
+    },
+    /// List paired devices.
+    Peers,
+    /// Sync once with a WebDAV or S3 remote described by a JSON file with
+    /// the `remoteSync` preferences (backend). `passphrase`, `password` and
+    /// `secretAccessKey` may be given in the file too; otherwise the ones
+    /// stored by the app are used.
+    Remote { config: PathBuf },
+}
+
+#[derive(Debug, Subcommand)]
+enum BackupAction {
+    /// Back up the database now and rotate old backups.
+    Now {
+        #[arg(long, default_value_t = 7)]
+        keep_daily: usize,
+        #[arg(long, default_value_t = 4)]
+        keep_weekly: usize,
+    },
+    /// List backups, newest first.
+    List,
+    /// Replace the database with a backup. Quit the app first: unlike the
+    /// app's own restore, this cannot pause clipboard capture.
+    Restore { file_name: String },
+}
+
+/// Entry point of the `vibeclip` binary.
+pub fn main() -> ExitCode {
+    let cli = Cli::parse();
+    match run(cli) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) => {
+            eprintln!("vibeclip: {err:#}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn run(cli: Cli) -> anyhow::Result<()> {
+    // Talking to the running app needs no database access.
+    #[cfg(unix)]
+    if let Command::Remote { args } = &cli.command {
+        let reply = crate::ipc::send(args)?;
+        if cli.json {
+            return print_json(&serde_json::json!({ "reply": reply }));
+        }
+        println!("{reply}");
+        return Ok(());
+    }
+    let data_dir = match cli.data_dir {
+        Some(dir) => dir,
+        None => dirs::data_dir()
+            .context("cannot determine the user data directory, pass --data-dir")?
+            .join(APP_IDENTIFIER),
+    };
+    let db = DbState::open(&data_dir)?;
+    let json = cli.json;
+
+    match cli.command {
+        Command::List { limit, offset } => {
+            print_clips(&db.list(None, Some(limit), offset, false)?, json)
+        }
+        Command::Search { query, limit } => {
+            print_clips(&db.list(Some(query), Some(limit), 0, false)?, json)
+        }
+        Command::Get { id } => {
+            let clip = find_clip(&db, id)?;
+            if json {
+                print_json(&clip)
+            } else {
+                println!("{}", clip.content);
+                Ok(())
+            }
+        }
+        Command::Copy { id, wait } => copy_clip(&find_clip(&db, id)?, wait),
+        Command::Add { pin } => {
+            let mut text = read_stdin()?;
+            // `echo foo | vibeclip add` should store `foo`, not `foo\n`.
+            if text.ends_with('\n') {
+                text.pop();
+                if text.ends_with('\r') {
+                    text.pop();
+                }
+            }
+            if text.trim().is_empty() {
+                anyhow::bail!("stdin is empty");
+            }
+            let clip = db.upsert(
+                finalize_payload(ClipPayload {
+                    kind: ClipKind::Text,
+                    preview: Some(text.chars().take(120).collect()),
+                    content: text,
+                    extra: None,
+                    content_hash: None,
+                    is_pinned: pin,
+                    is_favorite: false,
+                    source_app: None,
+                }),
+                ClipOrigin::Cli,
+            )?;
+            if json {
+                print_json(&clip)
+            } else {
+                println!("{}", clip.id);
+                Ok(())
+            }
+        }
+        Command::Export {
+            path,
+            format,
+            query,
+            limit,
+        } => {
+            let items = db.export_filtered(&ExportFilter {
+                query,
+                limit,
+                ..Default::default()
+            })?;
+            let body = render_export(items, format)?;
+            match path {
+                Some(path) => std::fs::write(&path, body)
+                    .with_context(|| format!("failed to write {}", path.display())),
+                None if format == ExportFormat::Zip => {
+                    anyhow::bail!("zip exports need an output path")
+                }
+                None => {
+                    let mut stdout = std::io::stdout().lock();
+                    stdout.write_all(&body)?;
+                    stdout.flush()?;
+                    Ok(())
+                }
+            }
+        }
+        Command::Import {
+            path,
+            strategy,
+            dry_run,
+        } => {
+            let parsed = load_history_file(&path)?;
+            let result = db.import_history(parsed, &ImportOptions { strategy, dry_run })?;
+            if json {
+                return print_json(&result);
+            }
+            for (key, count) in [
+                ("inserted", result.inserted),
+                ("skipped", result.skipped),
+                ("overwritten", result.overwritten),
+                ("kept_both", result.kept_both),
+                ("merged", result.merged),
+                ("failed", result.failed),
+            ] {
+                println!("{key} {count}");
+            }
+            for error in &result.errors {
+                eprintln!("item {}: {}", error.index, error.message);
+            }
+            if dry_run {
+                println!("dry run, nothing was written");
+            }
+            Ok(())
+        }
+        Command::Prune { keep } => {
+            let removed = db.prune_older_than(keep, ClipOrigin::Cli)?;
+            report(json, "removed", removed.len())
+        }
+        Command::Events { clip, limit } => {
+            let events = db.list_clip_events(&ClipEventQuery {
+                clip_id: clip,
+                limit: Some(limit),
+                ..Default::default()
+            })?;
+            if json {
+                return print_json(&events);
+            }
+            for event in events {
+                let line = format!(
+                    "{}  {:<13}  {:>6}  {:<9}  {}",
+                    event.created_at.format("%Y-%m-%d %H:%M:%S"),
+                    event.op,
+                    event.clip_id.map(|id| id.to_string()).unwrap_or_default(),
+                    event.origin,
+                    event
+                        .detail
+                        .map(|detail| detail.to_string())
+                        .unwrap_or_default()
+                );
+                println!("{}", line.trim_end());
+            }
+            Ok(())
+        }
+        Command::Backup { action } => run_backup(db, action, json),
+        Command::Sync { action } => run_sync(db, action, json),
+        #[cfg(unix)]
+        Command::Remote { .. } => unreachable!("handled before opening the database"),
+        Command::Ai {
+            action,
+            id,
+            language,
+            prompt,
+            provider,
+            base_url,
+            api_key,
+            model,
+            save,
+        } => {
+            let action: AiActionKind = serde_json::from_value(serde_json::Value::String(action))
+                .context("unknown action")?;
+            let input = match id {
+                Some(id) => {
+                    let clip = find_clip(&db, id)?;
+                    match clip.kind {
+                        ClipKind::Image => String::new(),
+                        _ => clip.content,
+                    }
+                }
+                None if action.is_vision() => anyhow::bail!("image actions need --id"),
+                None => read_stdin()?,
+            };
+            let request = AiActionRequest {
+                action,
+                input,
+                language,
+                custom_prompt: prompt,
+                provider_id: provider,
+                api_key,
+                base_url,
+                model,
+                source_clip_id: id,
+                save_result: save,
+                ..Default::default()
+            };
+            let ctx = AiContext {
+                db,
+                prefs: RuntimePreferences::default(),
+                offline: false,
+            };
+            let response = tauri::async_runtime::block_on(ai_service::run(&ctx, request))?;
+            if json {
+                print_json(&response)
+            } else {
+                println!("{}", response.result);
+                Ok(())
+            }
+        }
+    }
+}
+
+fn run_sync(db: DbState, action: SyncAction, json: bool) -> anyhow::Result<()> {
+    if let SyncAction::Remote { config } = &action {
+        let raw = std::fs::read_to_string(config)
+            .with_context(|| format!("failed to read {}", config.display()))?;
+        let file: RemoteSyncFile =
+            serde_json::from_str(&raw).context("invalid remote sync config")?;
+        let stored = RemoteSyncSecrets::load(&db)?;
+        let secrets = RemoteSyncSecrets {
+            passphrase: or_stored(file.secrets.passphrase, stored.passphrase),
+            password: or_stored(file.secrets.password, stored.password),
+            secret_access_key: or_stored(file.secrets.secret_access_key, stored.secret_access_key),
+        };
+        let engine = RemoteSync::new(db, &file.config, &secrets)?;
+        let report = tauri::async_runtime::block_on(engine.sync())?;
+        if json {
+            return print_json(&report);
+        }
+        println!(
+            "received {} new / {} updated / {} conflicts from {} devices, sent {}",
+            report.received.inserted,
+            report.received.updated,
+            report.received.conflicts,
+            report.devices,
+            report.sent
+        );
+        return Ok(());
+    }
+    let name = match &action {
+        SyncAction::Serve { name, .. } => name.clone(),
+        _ => None,
+    };
+    let engine = LanSync::new(db.clone_for_thread(), name, |stats| {
+        eprintln!(
+            "merged: {} inserted, {} updated, {} conflicts",
+            stats.inserted, stats.updated, stats.conflicts
+        );
+    })?;
+    match action {
+        SyncAction::Serve { port, no_mdns, .. } => {
+            let port = engine.start(port, !no_mdns)?;
+            let code = engine.begin_pairing()?;
+            eprintln!(
+                "device {} listening on port {port}, pairing code {code} (valid 5 minutes)",
+                engine.device_id()
+            );
+            loop {
+                std::thread::park();
+            }
+        }
+        SyncAction::Pair { address, code } => {
+            let peer = engine.pair(&address, &code)?;
+            if json {
+                print_json(&peer)
+            } else {
+                println!("paired with {} ({})", peer.name, peer.device_id);
+                Ok(())
+            }
+        }
+        SyncAction::Now { target } => {
+            let reports = match target {
+                Some(target) if target.contains(':') => vec![engine.sync_address(&target)?],
+                Some(device_id) => vec![engine.sync_peer(&device_id)?],
+                None => engine.sync_all()?,
+            };
+            if json {
+                return print_json(&reports);
+            }
+            for report in reports {
+                println!(
+                    "{}: received {} new / {} updated / {} conflicts, sent {}",
+                    report.name,
+                    report.received.inserted,
+                    report.received.updated,
+                    report.received.conflicts,
+                    report.sent
+                );
+            }
+            Ok(())
+        }
+        SyncAction::Remote { .. } => unreachable!("handled above"),
+        SyncAction::Peers => {
+            let peers = db.list_sync_peers()?;
+            if json {
+                return print_json(&peers);
+            }
+            for peer in peers {
+                println!(
+                    "{}  {}  {}",
+                    peer.device_id,
+                    peer.last_address.as_deref().unwrap_or("-"),
+                    peer.name
+                );
+            }
+            Ok(())
+        }
+    }
+}
+
+fn run_backup(db: DbState, action: BackupAction, json: bool) -> anyhow::Result<()> {
+    match action {
+        BackupAction::Now {
+            keep_daily,
+            keep_weekly,
+        } => {
+            let info = db.create_backup()?;
+            let removed = db.rotate_backups(keep_daily, keep_weekly)?;
+            if json {
+                return print_json(&serde_json::json!({ "backup": info, "removed": removed }));
+            }
+            println!("{}  {} bytes", info.file_name, info.size);
+            if removed > 0 {
+                println!("removed {removed} old backups");
+            }
+            Ok(())
+        }
+        BackupAction::List => {
+            let backups = db.list_backups()?;
+            if json {
+                return print_json(&backups);
+            }
+            for backup in backups {
+                println!("{}  {:>10} bytes", backup.file_name, backup.size);
+            }
+            Ok(())
+        }
+        BackupAction::Restore { file_name } => {
+            let safety = db.restore_backup(&file_name)?;
+            if json {
+                return print_json(
+                    &serde_json::json!({ "restored": file_name, "safety_copy": safety }),
+                );
+            }
+            println!("restored {file_name}");
+            if let Some(safety) = safety {
+                println!("previous database saved as {}", safety.file_name);
+            }
+            Ok(())
+        }
+    }
+}
+
+/// `vibeclip sync remote` config file.
+#[derive(serde::Deserialize)]
+struct RemoteSyncFile {
+    #[serde(flatten)]
+    config: RemoteSyncConfig,
+    #[serde(flatten)]
+    secrets: RemoteSyncSecrets,
+}
+
+fn or_stored(given: String, stored: String) -> String {
+    if given.is_empty() {
+        stored
+    } else {
+        given
+    }
+}
+
+fn find_clip(db: &DbState, id: i64) -> anyhow::Result<ClipItem> {
+    db.get(id)?
+        .with_context(|| format!("clip {id} does not exist"))
+}
+
+fn read_stdin() -> anyhow::Result<String> {
+    let mut text = String::new();
+    std::io::stdin()
+        .read_to_string(&mut text)
+        .context("failed to read stdin")?;
+    Ok(text)
+}
+
+fn print_json<T: serde::Serialize>(value: &T) -> anyhow::Result<()> {
+    let mut stdout = std::io::stdout().lock();
+    serde_json::to_writer_pretty(&mut stdout, value)?;
+    writeln!(stdout)?;
+    Ok(())
+}
+
+fn print_clips(clips: &[ClipItem], json: bool) -> anyhow::Result<()> {
+    if json {
+        return print_json(&clips);
+    }
+    let mut stdout = std::io::stdout().lock();
+    for clip in clips {
+        let kind = match clip.kind {
+            ClipKind::Text => "text",
+            ClipKind::Image => "image",
+            ClipKind::File => "file",
+        };
+        let flags = format!(
+            "{}{}",
+            if clip.is_pinned { "P" } else { "-" },
+            if clip.is_favorite { "F" } else { "-" }
+        );
+        let summary = match clip.kind {
+            ClipKind::Image => clip.preview.clone().unwrap_or_default(),
+            _ => clip.content.clone(),
+        };
+        let summary: String = summary
+            .split_whitespace()
+            .collect::<Vec<_>>()
+            .join(" ")
+            .chars()
+            .take(80)
+            .collect();
+        writeln!(
+            stdout,
+            "{:>6}  {:<5}  {}  {}  {}",
+            clip.id,
+            kind,
+            flags,
+            clip.updated_at.format("%Y-%m-%d %H:%M"),
+            summary
+        )?;
+    }
+    Ok(())
+}
+
+fn report(json: bool, key: &str, count: usize) -> anyhow::Result<()> {
+    if json {
+        print_json(&serde_json::json!({ key: count }))
+    } else {
+        println!("{key} {count}");
+        Ok(())
+    }
+}
+
+/// Copies through the system clipboard, or through an OSC 52 escape sequence
+/// when there is no display (SSH sessions, headless servers).
+fn copy_clip(clip: &ClipItem, wait: bool) -> anyhow::Result<()> {
+    let mut clipboard = match arboard::Clipboard::new() {
+        Ok(clipboard) => clipboard,
+        Err(err) => {
+            if matches!(clip.kind, ClipKind::Image) {
+                return Err(err).context("no clipboard available for image clips");
+            }
+            return copy_osc52(&clip.content);
+        }
+    };
+
+    #[cfg(target_os = "linux")]
+    let set = {
+        use arboard::SetExtLinux;
+        let set = clipboard.set();
+        if wait {
+            set.wait()
+        } else {
+            set
+        }
+    };
+    #[cfg(not(target_os = "linux"))]
+    let set = {
+        let _ = wait;
+        clipboard.set()
+    };
+
+    match clip.kind {
+        ClipKind::Image => {
+            let bytes = BASE64_STANDARD
+                .decode(clip.content.trim())
+                .context("image clip is not valid base64")?;
+            let image = image::load_from_memory(&bytes)
+                .context("failed to decode image clip")?
+                .to_rgba8();
+            set.image(arboard::ImageData {
+                width: image.width() as usize,
+                height: image.height() as usize,
+                bytes: image.into_raw().into(),
+            })?;
+        }
+        _ => set.text(clip.content.clone())?,
+    }
+    Ok(())
+}
+
+fn copy_osc52(text: &str) -> anyhow::Result<()> {
+    let mut stdout = std::io::stdout().lock();
+    write!(stdout, "\x1b]52;c;{}\x07", BASE64_STANDARD.encode(text))?;
+    stdout.flush()?;
+    Ok(())
+}
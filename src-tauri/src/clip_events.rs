@@ -0,0 +1,386 @@
+//! Append-only journal of history mutations.
+//!
+//! Every write to `clips` records what happened, to which clip and who asked
+//! for it, inside the same transaction as the change itself. Rows are only
+//! appended, except that repeated recaptures of a clip count up on one
+//! event; old events go when the journal is pruned along with the history
+//! it describes.
+
+use chrono::{DateTime, Utc};
+use rusqlite::{params, Connection, OptionalExtension, Row};
+use serde::{Deserialize, Serialize};
+
+use crate::db::{datetime_to_timestamp, timestamp_to_datetime, DbState};
+use crate::runtime_config::RetentionPolicy;
+
+/// Events kept per clip the history may hold when it is capped by count.
+const EVENTS_PER_CLIP: usize = 20;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum ClipOp {
+    Insert,
+    /// Content that was already stored was captured again.
+    Recapture,
+    UpdateFlags,
+    UpdateContent,
+    Delete,
+    Import,
+}
+
+impl ClipOp {
+    fn as_str(self) -> &'static str {
+        match self {
+            ClipOp::Insert => "insert",
+            ClipOp::Recapture => "recapture",
+            ClipOp::UpdateFlags => "updateFlags",
+            ClipOp::UpdateContent => "updateContent",
+            ClipOp::Delete => "delete",
+            ClipOp::Import => "import",
+        }
+    }
+}
+
+/// Who caused a mutation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum ClipOrigin {
+    Watcher,
+    Ui,
+    Tray,
+    Import,
+    Retention,
+    Ai,
+    Sync,
+    Cli,
+    Api,
+    Ipc,
+    /// Undo or redo of an earlier operation.
+    Undo,
+}
+
+impl ClipOrigin {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ClipOrigin::Watcher => "watcher",
+            ClipOrigin::Ui => "ui",
+            ClipOrigin::Tray => "tray",
+            ClipOrigin::Import => "import",
+            ClipOrigin::Retention => "retention",
+            ClipOrigin::Ai => "ai",
+            ClipOrigin::Sync => "sync",
+            ClipOrigin::Cli => "cli",
+            ClipOrigin::Api => "api",
+            ClipOrigin::Ipc => "ipc",
+            ClipOrigin::Undo => "undo",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ClipEvent {
+    pub id: i64,
+    pub op: String,
+    pub clip_id: Option<i64>,
+    pub content_hash: Option<String>,
+    pub origin: String,
+    pub detail: Option<serde_json::Value>,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ClipEventQuery {
+    pub clip_id: Option<i64>,
+    pub origin: Option<ClipOrigin>,
+    pub op: Option<ClipOp>,
+    /// Only events after this unix timestamp (seconds).
+    pub since: Option<i64>,
+    pub limit: Option<u32>,
+    pub offset: Option<u32>,
+}
+
+pub(crate) fn migrate(conn: &Connection) -> anyhow::Result<()> {
+    conn.execute_batch(
+        r#"
+        CREATE TABLE IF NOT EXISTS clip_events (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            op TEXT NOT NULL,
+            clip_id INTEGER,
+            content_hash TEXT,
+            origin TEXT NOT NULL,
+            detail TEXT,
+            created_at INTEGER NOT NULL
+        );
+        CREATE INDEX IF NOT EXISTS idx_clip_events_clip_id ON clip_events(clip_id);
+        CREATE INDEX IF NOT EXISTS idx_clip_events_created_at ON clip_events(created_at DESC);
+        "#,
+    )?;
+    Ok(())
+}
+
+/// Appends one event. Pass the transaction that performs the change.
+///
+/// Recapturing the same clip again and again counts up on the latest
+/// `recapture` event instead of appending another one; the row keeps its id
+/// and time, and `lastAt` in its detail tells when the run ended.
+pub(crate) fn record(
+    conn: &Connection,
+    op: ClipOp,
+    clip_id: Option<i64>,
+    content_hash: Option<&str>,
+    origin: ClipOrigin,
+    detail: Option<serde_json::Value>,
+) -> anyhow::Result<()> {
+    let now = datetime_to_timestamp(Utc::now());
+    if op == ClipOp::Recapture && detail.is_none() {
+        let previous: Option<(i64, Option<String>)> = conn
+            .prepare_cached(
+                "SELECT id, detail FROM clip_events \
+                 WHERE id = (SELECT MAX(id) FROM clip_events WHERE clip_id = ?1) \
+                 AND op = ?2 AND origin = ?3",
+            )?
+            .query_row(params![clip_id, op.as_str(), origin.as_str()], |row| {
+                Ok((row.get(0)?, row.get(1)?))
+            })
+            .optional()?;
+        if let Some((id, previous_detail)) = previous {
+            let count = previous_detail
+                .and_then(|detail| serde_json::from_str::<serde_json::Value>(&detail).ok())
+                .and_then(|detail| detail.get("count").and_then(serde_json::Value::as_u64))
+                .unwrap_or(1);
+            let detail = serde_json::json!({ "count": count + 1, "lastAt": now });
+            conn.prepare_cached("UPDATE clip_events SET detail = ?1 WHERE id = ?2")?
+                .execute(params![detail.to_string(), id])?;
+            return Ok(());
+        }
+    }
+    // Prepared once per connection; every capture goes through here.
+    let mut stmt = conn.prepare_cached(
+        "INSERT INTO clip_events (op, clip_id, content_hash, origin, detail, created_at) VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+    )?;
+    stmt.execute(params![
+        op.as_str(),
+        clip_id,
+        content_hash,
+        origin.as_str(),
+        detail.map(|detail| detail.to_string()),
+        now
+    ])?;
+    Ok(())
+}
+
+/// Records a `delete` event for every clip matching `condition` (a SQL
+/// expression over `clips`). Call it right before deleting those rows.
+pub(crate) fn record_deletes(
+    conn: &Connection,
+    condition: &str,
+    params: &[&dyn rusqlite::ToSql],
+    origin: ClipOrigin,
+    detail: Option<serde_json::Value>,
+) -> anyhow::Result<()> {
+    let mut values: Vec<&dyn rusqlite::ToSql> = params.to_vec();
+    let origin = origin.as_str();
+    let detail = detail.map(|detail| detail.to_string());
+    let now = datetime_to_timestamp(Utc::now());
+    values.extend([
+        &origin as &dyn rusqlite::ToSql,
+        &detail as &dyn rusqlite::ToSql,
+        &now as &dyn rusqlite::ToSql,
+    ]);
+    let base = params.len();
+    conn.execute(
+        &format!(
+            "INSERT INTO clip_events (op, clip_id, content_hash, origin, detail, created_at) \
+             SELECT 'delete', id, content_hash, ?{}, ?{}, ?{} FROM clips WHERE {condition}",
+            base + 1,
+            base + 2,
+            base + 3
+        ),
+        values.as_slice(),
+    )?;
+    Ok(())
+}
+
+fn map_event_row(row: &Row<'_>) -> rusqlite::Result<ClipEvent> {
+    let detail: Option<String> = row.get(5)?;
+    Ok(ClipEvent {
+        id: row.get(0)?,
+        op: row.get(1)?,
+        clip_id: row.get(2)?,
+        content_hash: row.get(3)?,
+        origin: row.get(4)?,
+        detail: detail.and_then(|detail| serde_json::from_str(&detail).ok()),
+        created_at: timestamp_to_datetime(row.get(6)?),
+    })
+}
+
+impl DbState {
+    /// Drops events older than the history's age limit and, when the history
+    /// is capped by count, keeps `EVENTS_PER_CLIP` events for every clip it
+    /// may hold.
+    pub fn prune_clip_events(&self, policy: &RetentionPolicy) -> anyhow::Result<()> {
+        let cutoff = policy
+            .max_age_days
+            .filter(|days| *days > 0)
+            .map(|days| datetime_to_timestamp(Utc::now() - chrono::Duration::days(days.into())));
+        let keep = policy
+            .max_entries
+            .filter(|entries| *entries > 0)
+            .map(|entries| entries.saturating_mul(EVENTS_PER_CLIP) as i64);
+        if cutoff.is_none() && keep.is_none() {
+            return Ok(());
+        }
+        self.write(move |conn| {
+            if let Some(cutoff) = cutoff {
+                conn.execute(
+                    "DELETE FROM clip_events WHERE created_at < ?1",
+                    params![cutoff],
+                )?;
+            }
+            if let Some(keep) = keep {
+                conn.execute(
+                    "DELETE FROM clip_events WHERE id <= \
+                     (SELECT id FROM clip_events ORDER BY id DESC LIMIT 1 OFFSET ?1)",
+                    params![keep],
+                )?;
+            }
+            Ok(())
+        })
+    }
+
+    /// Newest events first.
+    pub fn list_clip_events(&self, query: &ClipEventQuery) -> anyhow::Result<Vec<ClipEvent>> {
+        self.read(|conn| {
+            let mut stmt = conn.prepare(
+                "SELECT id, op, clip_id, content_hash, origin, detail, created_at FROM clip_events \
+                 WHERE (?1 IS NULL OR clip_id = ?1) AND (?2 IS NULL OR origin = ?2) \
+                 AND (?3 IS NULL OR op = ?3) AND (?4 IS NULL OR created_at > ?4) \
+                 ORDER BY id DESC LIMIT ?5 OFFSET ?6",
+            )?;
+            let rows = stmt.query_map(
+                params![
+                    query.clip_id,
+                    query.origin.map(ClipOrigin::as_str),
+                    query.op.map(ClipOp::as_str),
+                    query.since,
+                    query.limit.unwrap_or(200),
+                    query.offset.unwrap_or(0)
+                ],
+                map_event_row,
+            )?;
+            Ok(rows.collect::<Result<Vec<_>, _>>()?)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::TestDb;
+
+    fn record_op(db: &DbState, op: ClipOp, clip_id: i64, origin: ClipOrigin) {
+        db.write(move |conn| record(conn, op, Some(clip_id), Some("hash"), origin, None))
+            .unwrap();
+    }
+
+    fn events(db: &DbState) -> Vec<ClipEvent> {
+        let mut events = db.list_clip_events(&ClipEventQuery::default()).unwrap();
+        events.reverse();
+        events
+    }
+
+    fn count(event: &ClipEvent) -> Option<u64> {
+        event.detail.as_ref()?.get("count")?.as_u64()
+    }
+
+    #[test]
+    fn record_counts_repeated_recaptures() {
+        let db = TestDb::new();
+        record_op(&db, ClipOp::Insert, 1, ClipOrigin::Watcher);
+        for _ in 0..3 {
+            record_op(&db, ClipOp::Recapture, 1, ClipOrigin::Watcher);
+        }
+        // Another event, or another origin, starts a new run.
+        record_op(&db, ClipOp::UpdateFlags, 1, ClipOrigin::Ui);
+        record_op(&db, ClipOp::Recapture, 1, ClipOrigin::Watcher);
+        record_op(&db, ClipOp::Recapture, 1, ClipOrigin::Cli);
+        // Events of other clips do not interrupt a run.
+        record_op(&db, ClipOp::Insert, 2, ClipOrigin::Watcher);
+        record_op(&db, ClipOp::Recapture, 1, ClipOrigin::Cli);
+
+        let events = events(&db);
+        let summary: Vec<(&str, Option<i64>, Option<u64>)> = events
+            .iter()
+            .map(|event| (event.op.as_str(), event.clip_id, count(event)))
+            .collect();
+        assert_eq!(
+            summary,
+            [
+                ("insert", Some(1), None),
+                ("recapture", Some(1), Some(3)),
+                ("updateFlags", Some(1), None),
+                ("recapture", Some(1), None),
+                ("recapture", Some(1), Some(2)),
+                ("insert", Some(2), None),
+            ]
+        );
+        // Counting up never reorders or renumbers the journal.
+        assert!(events.windows(2).all(|pair| pair[0].id < pair[1].id));
+    }
+
+    #[test]
+    fn prune_keeps_the_newest_events() {
+        let db = TestDb::new();
+        for clip_id in 0..(EVENTS_PER_CLIP as i64 + 5) {
+            record_op(&db, ClipOp::Insert, clip_id, ClipOrigin::Cli);
+        }
+        db.prune_clip_events(&RetentionPolicy {
+            max_entries: Some(1),
+            max_age_days: None,
+            vacuum_on_start: false,
+        })
+        .unwrap();
+        let kept: Vec<i64> = events(&db)
+            .iter()
+            .filter_map(|event| event.clip_id)
+            .collect();
+        assert_eq!(kept, (5..EVENTS_PER_CLIP as i64 + 5).collect::<Vec<_>>());
+
+        // Without limits nothing is removed.
+        db.prune_clip_events(&RetentionPolicy {
+            max_entries: None,
+            max_age_days: None,
+            vacuum_on_start: false,
+        })
+        .unwrap();
+        assert_eq!(events(&db).len(), EVENTS_PER_CLIP);
+    }
+
+    #[test]
+    fn prune_drops_events_past_the_age_limit() {
+        let db = TestDb::new();
+        record_op(&db, ClipOp::Insert, 1, ClipOrigin::Cli);
+        record_op(&db, ClipOp::Insert, 2, ClipOrigin::Cli);
+        let old = datetime_to_timestamp(Utc::now() - chrono::Duration::days(10));
+        db.write(move |conn| {
+            conn.execute(
+                "UPDATE clip_events SET created_at = ?1 WHERE clip_id = 1",
+                params![old],
+            )?;
+            Ok(())
+        })
+        .unwrap();
+        db.prune_clip_events(&RetentionPolicy {
+            max_entries: None,
+            max_age_days: Some(7),
+            vacuum_on_start: false,
+        })
+        .unwrap();
+        let kept: Vec<i64> = events(&db)
+            .iter()
+            .filter_map(|event| event.clip_id)
+            .collect();
+        assert_eq!(kept, [2]);
+    }
+}
@@ -46,6 +46,7 @@ impl ClipboardDraft {
                     content_hash: None,
                     is_pinned: self.is_pinned,
                     is_favorite: self.is_favorite,
+                    source_app: None,
                 }))
             }
             ClipKind::Image => {
@@ -62,6 +63,7 @@ impl ClipboardDraft {
                     content_hash: None,
                     is_pinned: self.is_pinned,
                     is_favorite: self.is_favorite,
+                    source_app: None,
                 }))
             }
             ClipKind::File => {
@@ -77,6 +79,7 @@ impl ClipboardDraft {
                     content_hash: None,
                     is_pinned: self.is_pinned,
                     is_favorite: self.is_favorite,
+                    source_app: None,
                 }))
             }
         }
@@ -10,6 +10,7 @@ use tauri_plugin_clipboard_manager::ClipboardExt;
 use tokio::time::sleep;
 use tracing::error;
 
+use crate::clip_events::ClipOrigin;
 use crate::clipboard::ClipboardDraft;
 use crate::db::{ClipItem, ClipKind, DbState};
 use crate::runtime_config::{RuntimeConfigState, RuntimePreferences};
@@ -24,7 +25,7 @@ pub fn spawn_clipboard_watcher<R: Runtime + 'static>(app_handle: AppHandle<R>) {
 
         loop {
             let status = app_handle.state::<AppStatus>();
-            let listening = status.listening();
+            let listening = status.listening() && !status.capture_suspended();
             drop(status);
 
             if !listening {
@@ -97,7 +98,9 @@ pub fn spawn_clipboard_watcher<R: Runtime + 'static>(app_handle: AppHandle<R>) {
             let db_clone = db_state.clone_for_thread();
             let prefs_snapshot = prefs.clone();
             let result = tauri::async_runtime::spawn_blocking(move || -> Result<ClipItem> {
-                let clip = db_clone.upsert(payload)?;
+                let mut payload = payload;
+                payload.source_app = crate::foreground_app::current();
+                let clip = db_clone.upsert(payload, ClipOrigin::Watcher)?;
                 db_clone.apply_retention(&prefs_snapshot)?;
                 Ok(clip)
             })
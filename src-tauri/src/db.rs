@@ -1,4 +1,5 @@
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
 
 use anyhow::Context;
 use chrono::{DateTime, Duration, Utc};
@@ -8,38 +9,88 @@ use serde::{Deserialize, Serialize};
 use serde_repr::{Deserialize_repr, Serialize_repr};
 use tauri::{AppHandle, Manager};
 
+use crate::clip_events::{self, ClipOp, ClipOrigin};
+use crate::db_pool::{open_connection, DbPool};
 use crate::hash::compute_content_hash;
+use crate::recovery::RecoveryReport;
 use crate::runtime_config::RuntimePreferences;
+use crate::undo::{self, ClipSnapshot};
 
 #[derive(Debug, Clone)]
 pub struct DbState {
     path: PathBuf,
+    pool: Arc<DbPool>,
 }
 
 impl DbState {
-    pub fn initialize(app: &AppHandle) -> anyhow::Result<Self> {
-        let mut path = app
+    /// Opens the app's database, recovering it when it is damaged. The
+    /// report says what recovery did, if anything.
+    pub fn initialize(app: &AppHandle) -> anyhow::Result<(Self, Option<RecoveryReport>)> {
+        let dir = app
             .path()
             .app_data_dir()
             .context("failed to resolve application data directory")?;
-        std::fs::create_dir_all(&path).context("failed to create application data directory")?;
-        path.push("vibeclip_pro.db");
-        let state = Self { path };
+        Self::open_or_recover(&dir)
+    }
+
+    /// Opens and migrates the database inside `dir` without a running app,
+    /// which is how the `vibeclip` CLI reaches the same history.
+    pub fn open(dir: &Path) -> anyhow::Result<Self> {
+        let state = Self::locate(dir)?;
         state.migrate()?;
         Ok(state)
     }
 
+    /// The database inside `dir`, neither opened nor migrated.
+    pub(crate) fn locate(dir: &Path) -> anyhow::Result<Self> {
+        std::fs::create_dir_all(dir).context("failed to create application data directory")?;
+        let path = dir.join("vibeclip_pro.db");
+        Ok(Self {
+            pool: Arc::new(DbPool::new(path.clone())),
+            path,
+        })
+    }
+
+    /// Clones share the writer thread and the read pool.
     pub fn clone_for_thread(&self) -> Self {
-        Self {
-            path: self.path.clone(),
-        }
+        self.clone()
+    }
+
+    pub(crate) fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Directory holding the database and its companion files.
+    pub fn data_dir(&self) -> &Path {
+        self.path.parent().unwrap_or_else(|| Path::new("."))
+    }
+
+    /// A connection of its own, outside the pool. Only for startup and
+    /// recovery, before the database is known to be usable.
+    pub(crate) fn connect(&self) -> anyhow::Result<Connection> {
+        open_connection(&self.path)
     }
 
-    fn connect(&self) -> anyhow::Result<Connection> {
-        Connection::open(&self.path).context("failed to open sqlite connection")
+    /// Runs `read` on a pooled read-only connection.
+    pub(crate) fn read<T>(
+        &self,
+        read: impl FnOnce(&Connection) -> anyhow::Result<T>,
+    ) -> anyhow::Result<T> {
+        self.pool.read(read)
     }
 
-    fn migrate(&self) -> anyhow::Result<()> {
+    /// Runs `job` on the writer connection. See [`DbPool::write`].
+    pub(crate) fn write<T, F>(&self, job: F) -> anyhow::Result<T>
+    where
+        T: Send + 'static,
+        F: FnOnce(&mut Connection) -> anyhow::Result<T> + Send + 'static,
+    {
+        self.pool.write(job)
+    }
+
+    /// Runs on a connection of its own: at startup nothing else is using the
+    /// database yet, and after a restore clipboard capture is paused.
+    pub(crate) fn migrate(&self) -> anyhow::Result<()> {
         let conn = self.connect()?;
         conn.execute_batch(
             r#"
@@ -64,10 +115,30 @@ impl DbState {
             CREATE INDEX IF NOT EXISTS idx_clips_kind ON clips(kind, updated_at DESC);
             CREATE INDEX IF NOT EXISTS idx_clips_pinned ON clips(is_pinned DESC, updated_at DESC);
             CREATE INDEX IF NOT EXISTS idx_clips_composite ON clips(is_favorite DESC, is_pinned DESC, kind, updated_at DESC);
+            CREATE TABLE IF NOT EXISTS clip_provenance (
+                clip_id INTEGER PRIMARY KEY REFERENCES clips(id) ON DELETE CASCADE,
+                derived_from INTEGER REFERENCES clips(id) ON DELETE SET NULL,
+                action TEXT NOT NULL,
+                model TEXT,
+                prompt TEXT,
+                created_at INTEGER NOT NULL
+            );
+            CREATE INDEX IF NOT EXISTS idx_provenance_source ON clip_provenance(derived_from, created_at DESC);
             "#,
         )
         .context("failed to run migrations")?;
         self.ensure_content_hash_column(&conn)?;
+        self.ensure_language_column(&conn)?;
+        self.ensure_source_app_column(&conn)?;
+        crate::ai_cache::migrate(&conn)?;
+        crate::prompt_templates::migrate(&conn)?;
+        crate::provider_profiles::migrate(&conn)?;
+        crate::secret_store::migrate(&conn)?;
+        crate::usage::migrate(&conn)?;
+        crate::workflows::migrate(&conn)?;
+        crate::clip_events::migrate(&conn)?;
+        crate::sync_store::migrate(&conn)?;
+        crate::remote_sync::migrate(&conn)?;
         Ok(())
     }
 
@@ -84,6 +155,45 @@ impl DbState {
         Ok(())
     }
 
+    fn ensure_language_column(&self, conn: &Connection) -> anyhow::Result<()> {
+        let mut stmt = conn.prepare("PRAGMA table_info(clips)")?;
+        let has_column = stmt
+            .query_map([], |row| row.get::<_, String>(1))?
+            .filter_map(|res| res.ok())
+            .any(|name| name == "language");
+        if has_column {
+            return Ok(());
+        }
+        conn.execute("ALTER TABLE clips ADD COLUMN language TEXT", [])?;
+        let mut stmt = conn.prepare("SELECT id, content FROM clips WHERE kind = ?1")?;
+        let rows = stmt
+            .query_map(params![i64::from(ClipKind::Text)], |row| {
+                Ok((row.get::<_, i64>(0)?, row.get::<_, String>(1)?))
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+        for (id, content) in rows {
+            if let Some(language) = detect_language(ClipKind::Text, &content) {
+                conn.execute(
+                    "UPDATE clips SET language = ?1 WHERE id = ?2",
+                    params![language, id],
+                )?;
+            }
+        }
+        Ok(())
+    }
+
+    fn ensure_source_app_column(&self, conn: &Connection) -> anyhow::Result<()> {
+        let mut stmt = conn.prepare("PRAGMA table_info(clips)")?;
+        let has_column = stmt
+            .query_map([], |row| row.get::<_, String>(1))?
+            .filter_map(|res| res.ok())
+            .any(|name| name == "source_app");
+        if !has_column {
+            conn.execute("ALTER TABLE clips ADD COLUMN source_app TEXT", [])?;
+        }
+        Ok(())
+    }
+
     fn populate_missing_hashes(&self, conn: &Connection) -> anyhow::Result<()> {
         let mut stmt = conn.prepare(
             "SELECT id, kind, content FROM clips WHERE content_hash IS NULL OR content_hash = ''",
@@ -113,9 +223,8 @@ impl DbState {
         offset: u32,
         include_favorites_first: bool,
     ) -> anyhow::Result<Vec<ClipItem>> {
-        let conn = self.connect()?;
         let mut sql = String::from(
-            "SELECT id, kind, content, content_hash, preview, extra, is_pinned, is_favorite, created_at, updated_at FROM clips",
+            "SELECT id, kind, content, content_hash, preview, extra, is_pinned, is_favorite, created_at, updated_at, language, source_app FROM clips",
         );
         let search_term = query
             .as_ref()
@@ -130,85 +239,108 @@ impl DbState {
         } else {
             sql.push_str(" ORDER BY is_pinned DESC, updated_at DESC");
         }
-        if let Some(l) = limit {
-            sql.push_str(&format!(" LIMIT {}", l));
-        }
-        if offset > 0 {
-            sql.push_str(&format!(" OFFSET {}", offset));
-        }
-        let mut statement = conn.prepare(&sql)?;
-        let rows = if let Some(ref value) = search_term {
-            let pattern = format!("%{}%", value);
-            statement.query_map(named_params! {":pattern": pattern.as_str()}, map_clip_row)?
-        } else {
-            statement.query_map([], map_clip_row)?
-        };
-        let items = rows.collect::<Result<Vec<_>, _>>()?;
-        Ok(items)
+        // Bound rather than formatted in, so that the statement cache sees
+        // only a handful of distinct queries. A negative limit means none.
+        sql.push_str(" LIMIT :limit OFFSET :offset");
+        let limit = limit.map_or(-1, i64::from);
+        self.read(|conn| {
+            let mut statement = conn.prepare_cached(&sql)?;
+            let rows = if let Some(ref value) = search_term {
+                let pattern = format!("%{}%", value);
+                statement.query_map(
+                    named_params! {":pattern": pattern.as_str(), ":limit": limit, ":offset": offset},
+                    map_clip_row,
+                )?
+            } else {
+                statement.query_map(
+                    named_params! {":limit": limit, ":offset": offset},
+                    map_clip_row,
+                )?
+            };
+            let items = rows.collect::<Result<Vec<_>, _>>()?;
+            Ok(items)
+        })
     }
 
     pub fn get(&self, id: i64) -> anyhow::Result<Option<ClipItem>> {
-        let conn = self.connect()?;
-        Ok(conn
-            .query_row(
-                "SELECT id, kind, content, content_hash, preview, extra, is_pinned, is_favorite, created_at, updated_at FROM clips WHERE id = ?1",
-                params![id],
-                map_clip_row,
-            )
-            .optional()?)
-    }
-
-    pub fn upsert(&self, payload: ClipPayload) -> anyhow::Result<ClipItem> {
-        let ClipPayload {
-            kind,
-            content,
-            preview,
-            extra,
-            is_pinned,
-            is_favorite,
-            content_hash,
-        } = payload;
-
-        let hash = content_hash.unwrap_or_else(|| compute_content_hash(kind, &content));
-        let now = Utc::now();
-        let preview_ref = preview.as_deref();
-        let extra_ref = extra.as_deref();
-        let mut conn = self.connect()?;
-        let tx = conn.transaction()?;
-        let existing: Option<i64> = tx
-            .query_row(
-                "SELECT id FROM clips WHERE content_hash = ?1 LIMIT 1",
-                params![&hash],
-                |row| row.get(0),
-            )
-            .optional()?;
-
-        if let Some(id) = existing {
-            tx.execute(
-                "UPDATE clips SET content = ?1, content_hash = ?2, preview = COALESCE(?3, preview), extra = COALESCE(?4, extra), updated_at = ?5 WHERE id = ?6",
-                params![&content, &hash, preview_ref, extra_ref, datetime_to_timestamp(now), id],
-            )?;
-            tx.commit()?;
-            return self.get(id)?.context("failed to load inserted clip");
-        }
+        self.read(|conn| load_clip(conn, id))
+    }
 
-        tx.execute(
-            "INSERT INTO clips (kind, content, content_hash, preview, extra, is_pinned, is_favorite, created_at, updated_at) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
-            params![
-                i64::from(kind),
-                &content,
-                &hash,
-                preview_ref,
-                extra_ref,
-                is_pinned as i64,
-                is_favorite as i64,
-                datetime_to_timestamp(now),
-                datetime_to_timestamp(now)
-            ],
-        )?;
-        let id = tx.last_insert_rowid();
-        tx.commit()?;
-        self.get(id)?.context("failed to load inserted clip")
+    /// Most recently captured or touched clip, ignoring pins.
+    pub fn latest(&self) -> anyhow::Result<Option<ClipItem>> {
+        self.read(|conn| {
+            Ok(conn
+                .prepare_cached(
+                    "SELECT id, kind, content, content_hash, preview, extra, is_pinned, is_favorite, created_at, updated_at, language, source_app FROM clips ORDER BY updated_at DESC, id DESC LIMIT 1",
+                )?
+                .query_row([], map_clip_row)
+                .optional()?)
+        })
+    }
+
+    pub fn upsert(&self, payload: ClipPayload, origin: ClipOrigin) -> anyhow::Result<ClipItem> {
+        self.write(move |conn| upsert_clip(conn, payload, origin))
+    }
+
+    /// Stores `payload` as a clip and records where it came from. When the
+    /// content already exists the existing clip is reused and its provenance
+    /// is replaced with the latest derivation.
+    pub fn insert_derived(
+        &self,
+        payload: ClipPayload,
+        provenance: NewProvenance,
+        origin: ClipOrigin,
+    ) -> anyhow::Result<DerivedClip> {
+        self.write(move |conn| {
+            let clip = upsert_clip(conn, payload, origin)?;
+            let now = datetime_to_timestamp(Utc::now());
+            conn.prepare_cached(
+                "INSERT OR REPLACE INTO clip_provenance (clip_id, derived_from, action, model, prompt, created_at) VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+            )?
+            .execute(params![
+                clip.id,
+                provenance.derived_from.filter(|source| *source != clip.id),
+                &provenance.action,
+                &provenance.model,
+                &provenance.prompt,
+                now
+            ])?;
+            let provenance =
+                load_provenance(conn, clip.id)?.context("failed to load clip provenance")?;
+            Ok(DerivedClip { clip, provenance })
+        })
+    }
+
+    pub fn provenance(&self, clip_id: i64) -> anyhow::Result<Option<ClipProvenance>> {
+        self.read(|conn| load_provenance(conn, clip_id))
+    }
+
+    /// Lists every clip derived from `source_id`, following derivation chains
+    /// (a translation of a summary of the source is included as well).
+    pub fn list_derivations(&self, source_id: i64) -> anyhow::Result<Vec<DerivedClip>> {
+        self.read(|conn| {
+            let mut stmt = conn.prepare_cached(
+                "WITH RECURSIVE lineage(clip_id) AS ( \
+                    SELECT clip_id FROM clip_provenance WHERE derived_from = ?1 \
+                    UNION \
+                    SELECT p.clip_id FROM clip_provenance p JOIN lineage l ON p.derived_from = l.clip_id \
+                ) \
+                SELECT c.id, c.kind, c.content, c.content_hash, c.preview, c.extra, c.is_pinned, c.is_favorite, c.created_at, c.updated_at, c.language, c.source_app, \
+                    p.clip_id, p.derived_from, p.action, p.model, p.prompt, p.created_at \
+                FROM lineage l \
+                JOIN clips c ON c.id = l.clip_id \
+                JOIN clip_provenance p ON p.clip_id = l.clip_id \
+                ORDER BY p.created_at DESC",
+            )?;
+            let rows = stmt.query_map(params![source_id], |row| {
+                Ok(DerivedClip {
+                    clip: map_clip_row(row)?,
+                    provenance: map_provenance_columns(row, 12)?,
+                })
+            })?;
+            let items = rows.collect::<Result<Vec<_>, _>>()?;
+            Ok(items)
+        })
     }
 
     pub fn update_flags(
@@ -216,6 +348,7 @@ impl DbState {
         id: i64,
         pinned: Option<bool>,
         favorite: Option<bool>,
+        origin: ClipOrigin,
     ) -> anyhow::Result<()> {
         log::info!(
             "update_flags called: id={}, pinned={:?}, favorite={:?}",
@@ -229,37 +362,54 @@ impl DbState {
             return Ok(());
         }
 
-        let conn = self.connect()?;
-        let pinned_value = pinned.map(|value| if value { 1 } else { 0 });
-        let favorite_value = favorite.map(|value| if value { 1 } else { 0 });
-
-        log::info!(
-            "update_flags: executing SQL with pinned_value={:?}, favorite_value={:?}",
-            pinned_value,
-            favorite_value
-        );
-
-        let rows_affected = conn.execute(
-            "UPDATE clips SET \
-                is_pinned = COALESCE(:pinned, is_pinned), \
-                is_favorite = COALESCE(:favorite, is_favorite), \
-                updated_at = :updated_at \
-            WHERE id = :id",
-            named_params! {
-                ":pinned": pinned_value,
-                ":favorite": favorite_value,
-                ":updated_at": datetime_to_timestamp(Utc::now()),
-                ":id": id,
-            },
-        )?;
+        self.write(move |conn| {
+            let tx = conn.transaction()?;
+            let pinned_value = pinned.map(|value| if value { 1 } else { 0 });
+            let favorite_value = favorite.map(|value| if value { 1 } else { 0 });
 
-        log::info!("update_flags: {} rows affected", rows_affected);
+            log::info!(
+                "update_flags: executing SQL with pinned_value={:?}, favorite_value={:?}",
+                pinned_value,
+                favorite_value
+            );
 
-        if rows_affected == 0 {
-            log::warn!("update_flags: no rows were updated for id={}", id);
-        }
+            let rows_affected = tx
+                .prepare_cached(
+                    "UPDATE clips SET \
+                        is_pinned = COALESCE(:pinned, is_pinned), \
+                        is_favorite = COALESCE(:favorite, is_favorite), \
+                        updated_at = :updated_at, \
+                        flags_modified_at = :updated_at \
+                    WHERE id = :id",
+                )?
+                .execute(named_params! {
+                    ":pinned": pinned_value,
+                    ":favorite": favorite_value,
+                    ":updated_at": datetime_to_timestamp(Utc::now()),
+                    ":id": id,
+                })?;
+
+            log::info!("update_flags: {} rows affected", rows_affected);
+
+            if rows_affected == 0 {
+                log::warn!("update_flags: no rows were updated for id={}", id);
+                return Ok(());
+            }
 
-        Ok(())
+            let hash: String = tx
+                .prepare_cached("SELECT content_hash FROM clips WHERE id = ?1")?
+                .query_row(params![id], |row| row.get(0))?;
+            clip_events::record(
+                &tx,
+                ClipOp::UpdateFlags,
+                Some(id),
+                Some(&hash),
+                origin,
+                Some(serde_json::json!({ "pinned": pinned, "favorite": favorite })),
+            )?;
+            tx.commit()?;
+            Ok(())
+        })
     }
 
     pub fn update_content(
@@ -267,129 +417,248 @@ impl DbState {
         id: i64,
         content: String,
         preview: Option<String>,
+        origin: ClipOrigin,
     ) -> anyhow::Result<()> {
-        let conn = self.connect()?;
-        let kind: ClipKind =
-            conn.query_row("SELECT kind FROM clips WHERE id = ?1", params![id], |row| {
-                row.get(0)
-            })?;
-        let hash = compute_content_hash(kind, &content);
-        conn.execute(
-            "UPDATE clips SET content = ?1, content_hash = ?2, preview = ?3, updated_at = ?4 WHERE id = ?5",
-            params![content, hash, preview, datetime_to_timestamp(Utc::now()), id],
-        )?;
-        Ok(())
+        self.write(move |conn| {
+            let tx = conn.transaction()?;
+            let (kind, previous_hash): (ClipKind, String) = tx.query_row(
+                "SELECT kind, content_hash FROM clips WHERE id = ?1",
+                params![id],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )?;
+            let hash = compute_content_hash(kind, &content);
+            let language = detect_language(kind, &content);
+            tx.execute(
+                "UPDATE clips SET sync_id = COALESCE(sync_id, content_hash), content = ?1, content_hash = ?2, preview = ?3, updated_at = ?4, content_modified_at = ?4, language = ?5 WHERE id = ?6",
+                params![content, hash, preview, datetime_to_timestamp(Utc::now()), language, id],
+            )?;
+            clip_events::record(
+                &tx,
+                ClipOp::UpdateContent,
+                Some(id),
+                Some(&hash),
+                origin,
+                Some(serde_json::json!({ "previousHash": previous_hash })),
+            )?;
+            tx.commit()?;
+            Ok(())
+        })
     }
 
-    pub fn delete(&self, id: i64) -> anyhow::Result<()> {
-        let conn = self.connect()?;
-        conn.execute("DELETE FROM clips WHERE id = ?1", params![id])?;
-        Ok(())
+    /// Returns snapshots of the deleted clip so the deletion can be undone.
+    pub fn delete(&self, id: i64, origin: ClipOrigin) -> anyhow::Result<Vec<ClipSnapshot>> {
+        self.write(move |conn| {
+            let tx = conn.transaction()?;
+            let removed = undo::snapshot_where(&tx, "id = ?1", &[&id])?;
+            clip_events::record_deletes(&tx, "id = ?1", &[&id], origin, None)?;
+            tx.execute("DELETE FROM clips WHERE id = ?1", params![id])?;
+            tx.commit()?;
+            Ok(removed)
+        })
     }
 
-    pub fn clear(&self) -> anyhow::Result<()> {
-        let conn = self.connect()?;
-        conn.execute("DELETE FROM clips", [])?;
-        Ok(())
+    pub fn clear(&self, origin: ClipOrigin) -> anyhow::Result<Vec<ClipSnapshot>> {
+        self.write(move |conn| {
+            let tx = conn.transaction()?;
+            let removed = undo::snapshot_where(&tx, "1 = 1", &[])?;
+            clip_events::record_deletes(
+                &tx,
+                "1 = 1",
+                &[],
+                origin,
+                Some(serde_json::json!({ "clear": true })),
+            )?;
+            tx.execute("DELETE FROM clips", [])?;
+            tx.commit()?;
+            Ok(removed)
+        })
     }
 
     pub fn vacuum(&self) -> anyhow::Result<()> {
-        let conn = self.connect()?;
-        conn.execute_batch("VACUUM")?;
-        Ok(())
+        self.write(|conn| {
+            conn.execute_batch("VACUUM")?;
+            Ok(())
+        })
     }
 
     pub fn export_all(&self) -> anyhow::Result<Vec<ClipItem>> {
         self.list(None, None, 0, true)
     }
 
-    pub fn import_many(&self, items: Vec<ClipItem>) -> anyhow::Result<usize> {
-        let mut conn = self.connect()?;
-        let tx = conn.transaction()?;
-        let mut changes = 0usize;
-        for item in items {
-            let content_hash = if item.content_hash.is_empty() {
-                compute_content_hash(item.kind, &item.content)
-            } else {
-                item.content_hash.clone()
-            };
-            tx.execute(
-                "INSERT INTO clips (id, kind, content, content_hash, preview, extra, is_pinned, is_favorite, created_at, updated_at) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)",
-                params![
-                    item.id,
-                    i64::from(item.kind),
-                    item.content,
-                    content_hash,
-                    item.preview,
-                    item.extra,
-                    item.is_pinned as i64,
-                    item.is_favorite as i64,
-                    datetime_to_timestamp(item.created_at),
-                    datetime_to_timestamp(item.updated_at)
-                ],
-            )?;
-            changes += 1;
-        }
-        tx.commit()?;
-        Ok(changes)
-    }
-
-    pub fn prune_older_than(&self, keep_latest: usize) -> anyhow::Result<usize> {
+    pub fn prune_older_than(
+        &self,
+        keep_latest: usize,
+        origin: ClipOrigin,
+    ) -> anyhow::Result<Vec<ClipSnapshot>> {
         if keep_latest == 0 {
-            return Ok(0);
+            return Ok(Vec::new());
         }
-        let conn = self.connect()?;
-        let mut stmt = conn
-            .prepare("SELECT updated_at FROM clips ORDER BY updated_at DESC LIMIT 1 OFFSET ?1")?;
-        let threshold: Option<i64> = stmt
-            .query_row(params![(keep_latest as i64).saturating_sub(1)], |row| {
-                row.get(0)
-            })
-            .optional()?;
-        if let Some(threshold_ts) = threshold {
-            let deleted = conn.execute(
+        self.write(move |conn| {
+            let tx = conn.transaction()?;
+            let threshold: Option<i64> = tx
+                .query_row(
+                    "SELECT updated_at FROM clips ORDER BY updated_at DESC LIMIT 1 OFFSET ?1",
+                    params![(keep_latest as i64).saturating_sub(1)],
+                    |row| row.get(0),
+                )
+                .optional()?;
+            let Some(threshold_ts) = threshold else {
+                return Ok(Vec::new());
+            };
+            let removed = undo::snapshot_where(&tx, "updated_at < ?1", &[&threshold_ts])?;
+            clip_events::record_deletes(
+                &tx,
+                "updated_at < ?1",
+                &[&threshold_ts],
+                origin,
+                Some(serde_json::json!({ "keepLatest": keep_latest })),
+            )?;
+            tx.execute(
                 "DELETE FROM clips WHERE updated_at < ?1",
                 params![threshold_ts],
             )?;
-            Ok(deleted as usize)
-        } else {
-            Ok(0)
-        }
+            tx.commit()?;
+            Ok(removed)
+        })
     }
 
-    pub fn prune_older_than_days(&self, max_age_days: u32) -> anyhow::Result<usize> {
+    pub fn prune_older_than_days(
+        &self,
+        max_age_days: u32,
+        origin: ClipOrigin,
+    ) -> anyhow::Result<Vec<ClipSnapshot>> {
         if max_age_days == 0 {
-            return Ok(0);
+            return Ok(Vec::new());
         }
-        let conn = self.connect()?;
-        let threshold = Utc::now() - Duration::days(max_age_days as i64);
-        let deleted = conn.execute(
-            "DELETE FROM clips WHERE created_at < ?1",
-            params![datetime_to_timestamp(threshold)],
-        )?;
-        Ok(deleted as usize)
+        self.write(move |conn| {
+            let tx = conn.transaction()?;
+            let threshold = datetime_to_timestamp(Utc::now() - Duration::days(max_age_days as i64));
+            let removed = undo::snapshot_where(&tx, "created_at < ?1", &[&threshold])?;
+            clip_events::record_deletes(
+                &tx,
+                "created_at < ?1",
+                &[&threshold],
+                origin,
+                Some(serde_json::json!({ "maxAgeDays": max_age_days })),
+            )?;
+            tx.execute(
+                "DELETE FROM clips WHERE created_at < ?1",
+                params![threshold],
+            )?;
+            tx.commit()?;
+            Ok(removed)
+        })
     }
 
     pub fn apply_retention(&self, prefs: &RuntimePreferences) -> anyhow::Result<()> {
         if let Some(max_entries) = prefs.retention.max_entries {
             if max_entries > 0 {
-                let _ = self.prune_older_than(max_entries);
+                let _ = self.prune_older_than(max_entries, ClipOrigin::Retention);
             }
         }
         if let Some(days) = prefs.retention.max_age_days {
             if days > 0 {
-                let _ = self.prune_older_than_days(days);
+                let _ = self.prune_older_than_days(days, ClipOrigin::Retention);
             }
         }
-        Ok(())
+        self.prune_clip_events(&prefs.retention)
     }
 }
 
-fn datetime_to_timestamp(dt: DateTime<Utc>) -> i64 {
+fn load_clip(conn: &Connection, id: i64) -> anyhow::Result<Option<ClipItem>> {
+    Ok(conn
+        .prepare_cached(
+            "SELECT id, kind, content, content_hash, preview, extra, is_pinned, is_favorite, created_at, updated_at, language, source_app FROM clips WHERE id = ?1",
+        )?
+        .query_row(params![id], map_clip_row)
+        .optional()?)
+}
+
+fn load_provenance(conn: &Connection, clip_id: i64) -> anyhow::Result<Option<ClipProvenance>> {
+    Ok(conn
+        .prepare_cached(
+            "SELECT clip_id, derived_from, action, model, prompt, created_at FROM clip_provenance WHERE clip_id = ?1",
+        )?
+        .query_row(params![clip_id], map_provenance_row)
+        .optional()?)
+}
+
+/// Inserts a clip, or touches the stored one with the same content. Returns
+/// the clip as stored, read back inside the same transaction.
+fn upsert_clip(
+    conn: &mut Connection,
+    payload: ClipPayload,
+    origin: ClipOrigin,
+) -> anyhow::Result<ClipItem> {
+    let ClipPayload {
+        kind,
+        content,
+        preview,
+        extra,
+        is_pinned,
+        is_favorite,
+        content_hash,
+        source_app,
+    } = payload;
+
+    let hash = content_hash.unwrap_or_else(|| compute_content_hash(kind, &content));
+    let language = detect_language(kind, &content);
+    let now = Utc::now();
+    let preview_ref = preview.as_deref();
+    let extra_ref = extra.as_deref();
+    let tx = conn.transaction()?;
+    let existing: Option<i64> = tx
+        .prepare_cached("SELECT id FROM clips WHERE content_hash = ?1 LIMIT 1")?
+        .query_row(params![&hash], |row| row.get(0))
+        .optional()?;
+
+    let id = if let Some(id) = existing {
+        tx.prepare_cached(
+            "UPDATE clips SET content = ?1, content_hash = ?2, preview = COALESCE(?3, preview), extra = COALESCE(?4, extra), updated_at = ?5, language = ?6, source_app = COALESCE(?7, source_app) WHERE id = ?8",
+        )?
+        .execute(params![
+            &content,
+            &hash,
+            preview_ref,
+            extra_ref,
+            datetime_to_timestamp(now),
+            &language,
+            &source_app,
+            id
+        ])?;
+        clip_events::record(&tx, ClipOp::Recapture, Some(id), Some(&hash), origin, None)?;
+        id
+    } else {
+        tx.prepare_cached(
+            "INSERT INTO clips (kind, content, content_hash, preview, extra, is_pinned, is_favorite, created_at, updated_at, language, source_app) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11)",
+        )?
+        .execute(params![
+            i64::from(kind),
+            &content,
+            &hash,
+            preview_ref,
+            extra_ref,
+            is_pinned as i64,
+            is_favorite as i64,
+            datetime_to_timestamp(now),
+            datetime_to_timestamp(now),
+            &language,
+            &source_app
+        ])?;
+        let id = tx.last_insert_rowid();
+        clip_events::record(&tx, ClipOp::Insert, Some(id), Some(&hash), origin, None)?;
+        id
+    };
+    let clip = load_clip(&tx, id)?.context("failed to load inserted clip")?;
+    tx.commit()?;
+    Ok(clip)
+}
+
+pub(crate) fn datetime_to_timestamp(dt: DateTime<Utc>) -> i64 {
     dt.timestamp()
 }
 
-fn timestamp_to_datetime(ts: i64) -> DateTime<Utc> {
+pub(crate) fn timestamp_to_datetime(ts: i64) -> DateTime<Utc> {
     DateTime::from_timestamp(ts, 0).unwrap_or_else(|| {
         log::warn!(
             "invalid timestamp {} in database; falling back to current time",
@@ -412,6 +681,12 @@ pub struct ClipItem {
     pub is_favorite: bool,
     pub created_at: DateTime<Utc>,
     pub updated_at: DateTime<Utc>,
+    /// Detected language of text clips, e.g. `zh-CN` or `en`.
+    #[serde(default)]
+    pub language: Option<String>,
+    /// Application that was focused when the watcher captured the clip.
+    #[serde(default)]
+    pub source_app: Option<String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -426,6 +701,32 @@ pub struct ClipPayload {
     pub is_pinned: bool,
     #[serde(default)]
     pub is_favorite: bool,
+    #[serde(default)]
+    pub source_app: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ClipProvenance {
+    pub clip_id: i64,
+    pub derived_from: Option<i64>,
+    pub action: String,
+    pub model: Option<String>,
+    pub prompt: Option<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone)]
+pub struct NewProvenance {
+    pub derived_from: Option<i64>,
+    pub action: String,
+    pub model: Option<String>,
+    pub prompt: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DerivedClip {
+    pub clip: ClipItem,
+    pub provenance: ClipProvenance,
 }
 
 #[derive(Debug, Clone, Copy, Serialize_repr, Deserialize_repr)]
@@ -464,7 +765,14 @@ impl ToSql for ClipKind {
     }
 }
 
-fn map_clip_row(row: &Row<'_>) -> rusqlite::Result<ClipItem> {
+pub(crate) fn detect_language(kind: ClipKind, content: &str) -> Option<String> {
+    match kind {
+        ClipKind::Text => crate::lang_detect::detect(content),
+        _ => None,
+    }
+}
+
+pub(crate) fn map_clip_row(row: &Row<'_>) -> rusqlite::Result<ClipItem> {
     let created_at_ts: i64 = row.get(8)?;
     let updated_at_ts: i64 = row.get(9)?;
     Ok(ClipItem {
@@ -478,5 +786,65 @@ fn map_clip_row(row: &Row<'_>) -> rusqlite::Result<ClipItem> {
         is_favorite: row.get::<_, i64>(7)? == 1,
         created_at: timestamp_to_datetime(created_at_ts),
         updated_at: timestamp_to_datetime(updated_at_ts),
+        language: row.get(10)?,
+        source_app: row.get(11)?,
+    })
+}
+
+fn map_provenance_row(row: &Row<'_>) -> rusqlite::Result<ClipProvenance> {
+    map_provenance_columns(row, 0)
+}
+
+pub(crate) fn map_provenance_columns(
+    row: &Row<'_>,
+    offset: usize,
+) -> rusqlite::Result<ClipProvenance> {
+    let created_at_ts: i64 = row.get(offset + 5)?;
+    Ok(ClipProvenance {
+        clip_id: row.get(offset)?,
+        derived_from: row.get(offset + 1)?,
+        action: row.get(offset + 2)?,
+        model: row.get(offset + 3)?,
+        prompt: row.get(offset + 4)?,
+        created_at: timestamp_to_datetime(created_at_ts),
     })
 }
+
+/// A migrated database in a fresh temporary directory, removed on drop.
+#[cfg(test)]
+pub(crate) struct TestDb {
+    db: DbState,
+    dir: PathBuf,
+}
+
+#[cfg(test)]
+impl TestDb {
+    pub(crate) fn new() -> Self {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+        static NEXT: AtomicUsize = AtomicUsize::new(0);
+        let dir = std::env::temp_dir().join(format!(
+            "vibeclip-test-{}-{}",
+            std::process::id(),
+            NEXT.fetch_add(1, Ordering::Relaxed)
+        ));
+        let _ = std::fs::remove_dir_all(&dir);
+        let db = DbState::open(&dir).expect("open test database");
+        Self { db, dir }
+    }
+}
+
+#[cfg(test)]
+impl std::ops::Deref for TestDb {
+    type Target = DbState;
+
+    fn deref(&self) -> &DbState {
+        &self.db
+    }
+}
+
+#[cfg(test)]
+impl Drop for TestDb {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.dir);
+    }
+}
@@ -0,0 +1,126 @@
+//! Connections to the history database.
+//!
+//! All writes go through one connection owned by a dedicated thread, which
+//! runs them one at a time in the order they were sent, so the watcher and
+//! UI commands never race each other for the write lock. Reads borrow a
+//! connection from a small pool and run on the calling thread; in WAL mode
+//! they never wait for the writer. Both kinds of connection are kept open
+//! and cache their prepared statements.
+
+use std::panic::AssertUnwindSafe;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Sender};
+use std::sync::{Mutex, OnceLock};
+
+use anyhow::Context;
+use rusqlite::Connection;
+use tracing::error;
+
+/// Idle read connections kept open; more are opened under load and closed
+/// when they are returned to a full pool.
+const READ_POOL_SIZE: usize = 4;
+const STATEMENT_CACHE_CAPACITY: usize = 64;
+
+type Job = Box<dyn FnOnce(anyhow::Result<&mut Connection>) + Send>;
+
+#[derive(Debug)]
+pub(crate) struct DbPool {
+    path: PathBuf,
+    writer: OnceLock<Sender<Job>>,
+    readers: Mutex<Vec<Connection>>,
+}
+
+pub(crate) fn open_connection(path: &Path) -> anyhow::Result<Connection> {
+    let conn = Connection::open(path).context("failed to open sqlite connection")?;
+    conn.execute_batch("PRAGMA foreign_keys = ON;")?;
+    conn.set_prepared_statement_cache_capacity(STATEMENT_CACHE_CAPACITY);
+    Ok(conn)
+}
+
+impl DbPool {
+    pub(crate) fn new(path: PathBuf) -> Self {
+        Self {
+            path,
+            writer: OnceLock::new(),
+            readers: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// Runs `job` on the writer thread and waits for its result. Jobs must
+    /// not call back into [`DbPool::write`], which would wait forever.
+    pub(crate) fn write<T, F>(&self, job: F) -> anyhow::Result<T>
+    where
+        T: Send + 'static,
+        F: FnOnce(&mut Connection) -> anyhow::Result<T> + Send + 'static,
+    {
+        let (reply, result) = mpsc::sync_channel(1);
+        let job: Job = Box::new(move |conn| {
+            let _ = reply.send(conn.and_then(job));
+        });
+        self.writer()
+            .send(job)
+            .map_err(|_| anyhow::anyhow!("database writer has stopped"))?;
+        result
+            .recv()
+            .map_err(|_| anyhow::anyhow!("database write was aborted"))?
+    }
+
+    /// Runs `read` on a pooled read-only connection.
+    pub(crate) fn read<T>(
+        &self,
+        read: impl FnOnce(&Connection) -> anyhow::Result<T>,
+    ) -> anyhow::Result<T> {
+        let pooled = self.readers.lock().unwrap().pop();
+        let conn = match pooled {
+            Some(conn) => conn,
+            None => {
+                let conn = open_connection(&self.path)?;
+                conn.execute_batch("PRAGMA query_only = ON;")?;
+                conn
+            }
+        };
+        let result = read(&conn);
+        let mut readers = self.readers.lock().unwrap();
+        if readers.len() < READ_POOL_SIZE {
+            readers.push(conn);
+        }
+        result
+    }
+
+    fn writer(&self) -> &Sender<Job> {
+        self.writer.get_or_init(|| {
+            let (sender, jobs) = mpsc::channel::<Job>();
+            let path = self.path.clone();
+            std::thread::Builder::new()
+                .name("vibeclip-db-writer".into())
+                .spawn(move || run_writer(path, jobs))
+                .expect("failed to spawn database writer thread");
+            sender
+        })
+    }
+}
+
+/// Runs jobs until every sender is gone. The connection is opened on the
+/// first job and retried on the next one if that fails.
+fn run_writer(path: PathBuf, jobs: mpsc::Receiver<Job>) {
+    let mut conn: Option<Connection> = None;
+    for job in jobs {
+        if conn.is_none() {
+            match open_connection(&path) {
+                Ok(opened) => conn = Some(opened),
+                Err(err) => {
+                    job(Err(err));
+                    continue;
+                }
+            }
+        }
+        let Some(conn) = conn.as_mut() else {
+            continue;
+        };
+        // A panicking job only loses its own result; the sender sees the
+        // reply channel close.
+        if std::panic::catch_unwind(AssertUnwindSafe(|| job(Ok(conn)))).is_err() {
+            error!("database write panicked");
+        }
+    }
+}
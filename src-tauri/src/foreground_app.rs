@@ -0,0 +1,115 @@
+//! Best-effort lookup of the application that owns the focused window.
+//!
+//! The watcher asks right after it sees a new clipboard value, so the answer
+//! is normally the app the user copied from. Any failure (no display server,
+//! Wayland, missing helper tools) just yields `None`.
+
+/// Returns a short, human-readable name such as `firefox` or `Safari`.
+pub fn current() -> Option<String> {
+    let name = platform::current()?;
+    let name = name.trim();
+    (!name.is_empty()).then(|| name.to_string())
+}
+
+#[cfg(target_os = "windows")]
+mod platform {
+    use std::path::Path;
+
+    use windows_sys::Win32::Foundation::CloseHandle;
+    use windows_sys::Win32::System::Threading::{
+        OpenProcess, QueryFullProcessImageNameW, PROCESS_NAME_WIN32,
+        PROCESS_QUERY_LIMITED_INFORMATION,
+    };
+    use windows_sys::Win32::UI::WindowsAndMessaging::{
+        GetForegroundWindow, GetWindowThreadProcessId,
+    };
+
+    pub fn current() -> Option<String> {
+        // SAFETY: plain Win32 calls; the process handle is closed before
+        // returning and the buffer outlives the call that fills it.
+        unsafe {
+            let window = GetForegroundWindow();
+            if window.is_null() {
+                return None;
+            }
+            let mut pid = 0u32;
+            GetWindowThreadProcessId(window, &mut pid);
+            if pid == 0 {
+                return None;
+            }
+            let process = OpenProcess(PROCESS_QUERY_LIMITED_INFORMATION, 0, pid);
+            if process.is_null() {
+                return None;
+            }
+            let mut buffer = [0u16; 1024];
+            let mut len = buffer.len() as u32;
+            let ok = QueryFullProcessImageNameW(
+                process,
+                PROCESS_NAME_WIN32,
+                buffer.as_mut_ptr(),
+                &mut len,
+            );
+            CloseHandle(process);
+            if ok == 0 {
+                return None;
+            }
+            let path = String::from_utf16_lossy(&buffer[..len as usize]);
+            Path::new(&path)
+                .file_stem()
+                .map(|stem| stem.to_string_lossy().into_owned())
+        }
+    }
+}
+
+#[cfg(target_os = "macos")]
+mod platform {
+    use std::process::Command;
+
+    /// `lsappinfo` ships with macOS and, unlike System Events, needs no
+    /// automation permission.
+    pub fn current() -> Option<String> {
+        let front = Command::new("lsappinfo").arg("front").output().ok()?;
+        if !front.status.success() {
+            return None;
+        }
+        let asn = String::from_utf8_lossy(&front.stdout).trim().to_string();
+        let info = Command::new("lsappinfo")
+            .args(["info", "-only", "name", &asn])
+            .output()
+            .ok()?;
+        // Prints `"LSDisplayName"="Safari"`.
+        let text = String::from_utf8_lossy(&info.stdout);
+        let (_, value) = text.trim().split_once('=')?;
+        Some(value.trim_matches('"').to_string())
+    }
+}
+
+#[cfg(all(unix, not(target_os = "macos")))]
+mod platform {
+    use std::process::Command;
+
+    /// Asks the X server through `xdotool`; Wayland compositors do not expose
+    /// the focused window, so this returns `None` there.
+    pub fn current() -> Option<String> {
+        std::env::var_os("DISPLAY")?;
+        let output = Command::new("xdotool")
+            .args(["getactivewindow", "getwindowpid"])
+            .output()
+            .ok()?;
+        if !output.status.success() {
+            return None;
+        }
+        let pid: u32 = String::from_utf8_lossy(&output.stdout)
+            .trim()
+            .parse()
+            .ok()?;
+        std::fs::read_to_string(format!("/proc/{pid}/comm")).ok()
+    }
+}
+
+#[cfg(not(any(unix, target_os = "windows")))]
+mod platform {
+    pub fn current() -> Option<String> {
+        None
+    }
+}
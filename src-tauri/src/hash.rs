@@ -8,3 +8,7 @@ pub fn compute_content_hash(kind: ClipKind, content: &str) -> String {
     hasher.update(content.as_bytes());
     hasher.finalize().to_hex().to_string()
 }
+
+pub fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{byte:02x}")).collect()
+}
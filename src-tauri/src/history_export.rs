@@ -0,0 +1,405 @@
+//! Writing history in formats meant for reading and sharing.
+//!
+//! JSON is the re-importable export. Markdown, CSV and HTML are for people;
+//! images are embedded in HTML and only described elsewhere. The zip archive
+//! holds `clips.json` with each image clip pointing at a real PNG file under
+//! `images/`, and imports back like a JSON export.
+
+use std::io::{Cursor, Read, Write};
+
+use anyhow::Context;
+use base64::engine::general_purpose::STANDARD as BASE64_STANDARD;
+use base64::Engine;
+use chrono::{DateTime, Local, Utc};
+use serde::{Deserialize, Serialize};
+use zip::write::SimpleFileOptions;
+use zip::{CompressionMethod, ZipArchive, ZipWriter};
+
+use crate::db::{ClipItem, ClipKind, DbState};
+
+const ARCHIVE_MANIFEST: &str = "clips.json";
+const ARCHIVE_IMAGE_DIR: &str = "images/";
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct HistoryExportPayload {
+    pub exported_at: i64,
+    pub items: Vec<ClipItem>,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "camelCase")]
+pub enum ExportFormat {
+    #[default]
+    Json,
+    Markdown,
+    Csv,
+    Html,
+    Zip,
+}
+
+/// Which clips to export; the same criteria as `fetch_clips`, except that
+/// no limit means the whole history.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ExportFilter {
+    pub query: Option<String>,
+    pub favorites_first: Option<bool>,
+    pub limit: Option<u32>,
+    pub offset: Option<u32>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ExportSummary {
+    pub format: ExportFormat,
+    pub path: String,
+    pub clips: usize,
+    pub bytes: usize,
+}
+
+impl DbState {
+    pub fn export_filtered(&self, filter: &ExportFilter) -> anyhow::Result<Vec<ClipItem>> {
+        self.list(
+            filter.query.clone(),
+            filter.limit,
+            filter.offset.unwrap_or_default(),
+            filter.favorites_first.unwrap_or(true),
+        )
+    }
+}
+
+/// Renders `items` in `format`.
+pub fn render_export(items: Vec<ClipItem>, format: ExportFormat) -> anyhow::Result<Vec<u8>> {
+    let exported_at = Utc::now();
+    match format {
+        ExportFormat::Json => Ok(serde_json::to_vec_pretty(&HistoryExportPayload {
+            exported_at: exported_at.timestamp(),
+            items,
+        })?),
+        ExportFormat::Markdown => Ok(render_markdown(&items, exported_at).into_bytes()),
+        ExportFormat::Csv => Ok(render_csv(&items).into_bytes()),
+        ExportFormat::Html => Ok(render_html(&items, exported_at).into_bytes()),
+        ExportFormat::Zip => render_zip(items, exported_at),
+    }
+}
+
+fn local_time(time: DateTime<Utc>) -> String {
+    time.with_timezone(&Local)
+        .format("%Y-%m-%d %H:%M:%S")
+        .to_string()
+}
+
+fn kind_label(kind: ClipKind) -> &'static str {
+    match kind {
+        ClipKind::Text => "文本",
+        ClipKind::Image => "图像",
+        ClipKind::File => "文件",
+    }
+}
+
+fn flag_labels(clip: &ClipItem) -> String {
+    let mut labels = Vec::new();
+    if clip.is_pinned {
+        labels.push("置顶");
+    }
+    if clip.is_favorite {
+        labels.push("收藏");
+    }
+    labels.join(" · ")
+}
+
+fn image_caption(clip: &ClipItem) -> String {
+    clip.preview
+        .clone()
+        .unwrap_or_else(|| kind_label(clip.kind).to_string())
+}
+
+fn render_markdown(items: &[ClipItem], exported_at: DateTime<Utc>) -> String {
+    let mut out = format!(
+        "# VibeClip 剪贴板历史\n\n导出于 {}，共 {} 条。\n",
+        local_time(exported_at),
+        items.len()
+    );
+    for clip in items {
+        let flags = flag_labels(clip);
+        out.push_str(&format!(
+            "\n## {} · {}{}\n\n",
+            local_time(clip.created_at),
+            kind_label(clip.kind),
+            if flags.is_empty() {
+                String::new()
+            } else {
+                format!(" · {flags}")
+            }
+        ));
+        match clip.kind {
+            ClipKind::Text => {
+                // The fence must be longer than any backtick run in the text.
+                let longest = clip
+                    .content
+                    .split(|c| c != '`')
+                    .map(str::len)
+                    .max()
+                    .unwrap_or(0);
+                let fence = "`".repeat(longest.max(2) + 1);
+                out.push_str(&format!("{fence}\n{}\n{fence}\n", clip.content));
+            }
+            ClipKind::File => {
+                for path in clip.content.lines() {
+                    out.push_str(&format!("- `{path}`\n"));
+                }
+            }
+            ClipKind::Image => out.push_str(&format!("*{}*\n", image_caption(clip))),
+        }
+    }
+    out
+}
+
+fn csv_field(value: &str) -> String {
+    // Spreadsheets evaluate cells that look like formulas; a leading quote
+    // keeps clipboard content as plain text.
+    let value = if value.starts_with(['=', '+', '-', '@', '\t', '\r']) {
+        format!("'{value}")
+    } else {
+        value.to_string()
+    };
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value
+    }
+}
+
+fn render_csv(items: &[ClipItem]) -> String {
+    let mut out =
+        String::from("id,kind,content,preview,pinned,favorite,created_at,updated_at,language\r\n");
+    for clip in items {
+        // Base64 image data is useless in a spreadsheet.
+        let content = match clip.kind {
+            ClipKind::Image => "",
+            _ => clip.content.as_str(),
+        };
+        let row = [
+            clip.id.to_string(),
+            kind_label(clip.kind).to_string(),
+            content.to_string(),
+            clip.preview.clone().unwrap_or_default(),
+            clip.is_pinned.to_string(),
+            clip.is_favorite.to_string(),
+            clip.created_at.to_rfc3339(),
+            clip.updated_at.to_rfc3339(),
+            clip.language.clone().unwrap_or_default(),
+        ];
+        let row = row.iter().map(|field| csv_field(field)).collect::<Vec<_>>();
+        out.push_str(&row.join(","));
+        out.push_str("\r\n");
+    }
+    out
+}
+
+fn escape_html(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+fn render_html(items: &[ClipItem], exported_at: DateTime<Utc>) -> String {
+    let mut out = format!(
+        r#"<!DOCTYPE html>
+<html lang="zh-CN">
+<head>
+<meta charset="utf-8">
+<title>VibeClip 剪贴板历史</title>
+<style>
+body {{ font-family: system-ui, sans-serif; max-width: 860px; margin: 2rem auto; padding: 0 1rem; color: #1f2328; }}
+article {{ border: 1px solid #d0d7de; border-radius: 8px; padding: 0.75rem 1rem; margin: 1rem 0; }}
+header {{ color: #656d76; font-size: 0.85rem; margin-bottom: 0.5rem; }}
+pre {{ white-space: pre-wrap; word-break: break-word; margin: 0; }}
+img {{ max-width: 100%; }}
+</style>
+</head>
+<body>
+<h1>VibeClip 剪贴板历史</h1>
+<p>导出于 {}，共 {} 条。</p>
+"#,
+        local_time(exported_at),
+        items.len()
+    );
+    for clip in items {
+        let flags = flag_labels(clip);
+        out.push_str(&format!(
+            "<article>\n<header>{} · {}{}</header>\n",
+            local_time(clip.created_at),
+            kind_label(clip.kind),
+            if flags.is_empty() {
+                String::new()
+            } else {
+                format!(" · {flags}")
+            }
+        ));
+        match clip.kind {
+            ClipKind::Text => out.push_str(&format!("<pre>{}</pre>\n", escape_html(&clip.content))),
+            ClipKind::File => {
+                out.push_str("<ul>\n");
+                for path in clip.content.lines() {
+                    out.push_str(&format!("<li><code>{}</code></li>\n", escape_html(path)));
+                }
+                out.push_str("</ul>\n");
+            }
+            // Only valid base64 goes into the attribute; anything else could
+            // close it and inject markup, so it is described instead.
+            ClipKind::Image if BASE64_STANDARD.decode(clip.content.trim()).is_ok() => {
+                out.push_str(&format!(
+                    "<img src=\"data:image/png;base64,{}\" alt=\"{}\">\n",
+                    clip.content.trim(),
+                    escape_html(&image_caption(clip))
+                ))
+            }
+            ClipKind::Image => out.push_str(&format!(
+                "<p><em>{}</em></p>\n",
+                escape_html(&image_caption(clip))
+            )),
+        }
+        out.push_str("</article>\n");
+    }
+    out.push_str("</body>\n</html>\n");
+    out
+}
+
+fn render_zip(mut items: Vec<ClipItem>, exported_at: DateTime<Utc>) -> anyhow::Result<Vec<u8>> {
+    let options = SimpleFileOptions::default().compression_method(CompressionMethod::Deflated);
+    // PNG data is already compressed.
+    let stored = SimpleFileOptions::default().compression_method(CompressionMethod::Stored);
+    let mut zip = ZipWriter::new(Cursor::new(Vec::new()));
+    for clip in items
+        .iter_mut()
+        .filter(|clip| matches!(clip.kind, ClipKind::Image))
+    {
+        let png = BASE64_STANDARD
+            .decode(clip.content.trim())
+            .with_context(|| format!("clip {} has invalid image data", clip.id))?;
+        let name = format!("{ARCHIVE_IMAGE_DIR}{}.png", clip.id);
+        zip.start_file(name.as_str(), stored)?;
+        zip.write_all(&png)?;
+        clip.content = name;
+    }
+    zip.start_file(ARCHIVE_MANIFEST, options)?;
+    serde_json::to_writer_pretty(
+        &mut zip,
+        &HistoryExportPayload {
+            exported_at: exported_at.timestamp(),
+            items,
+        },
+    )?;
+    Ok(zip.finish()?.into_inner())
+}
+
+/// Reads an archive written by [`render_zip`], putting the image data back
+/// into the clips. Returns the raw clip objects of `clips.json`.
+pub(crate) fn read_archive(bytes: &[u8]) -> anyhow::Result<Vec<serde_json::Value>> {
+    let mut archive = ZipArchive::new(Cursor::new(bytes)).context("invalid zip archive")?;
+    let mut manifest = String::new();
+    archive
+        .by_name(ARCHIVE_MANIFEST)
+        .with_context(|| format!("archive has no {ARCHIVE_MANIFEST}"))?
+        .read_to_string(&mut manifest)?;
+    let mut payload: serde_json::Value =
+        serde_json::from_str(&manifest).with_context(|| format!("invalid {ARCHIVE_MANIFEST}"))?;
+    let Some(serde_json::Value::Array(mut items)) = payload.get_mut("items").map(std::mem::take)
+    else {
+        anyhow::bail!("{ARCHIVE_MANIFEST} has no items");
+    };
+    for item in &mut items {
+        let is_image =
+            item.get("kind").and_then(|kind| kind.as_u64()) == Some(ClipKind::Image as u64);
+        let Some(name) = item
+            .get("content")
+            .and_then(|content| content.as_str())
+            .filter(|content| is_image && content.starts_with(ARCHIVE_IMAGE_DIR))
+            .map(str::to_string)
+        else {
+            continue;
+        };
+        // Missing files are left as is and fail as invalid image data later.
+        if let Ok(mut file) = archive.by_name(&name) {
+            let mut png = Vec::new();
+            file.read_to_end(&mut png)?;
+            item["content"] = serde_json::Value::String(BASE64_STANDARD.encode(png));
+        }
+    }
+    Ok(items)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn text_clip(content: &str) -> ClipItem {
+        ClipItem {
+            id: 1,
+            kind: ClipKind::Text,
+            content: content.to_string(),
+            content_hash: String::new(),
+            preview: None,
+            extra: None,
+            is_pinned: false,
+            is_favorite: false,
+            created_at: Utc::now(),
+            updated_at: Utc::now(),
+            language: None,
+            source_app: None,
+        }
+    }
+
+    #[test]
+    fn csv_field_quotes_and_defuses_formulas() {
+        let cases = [
+            ("plain", "plain"),
+            ("", ""),
+            ("a,b", "\"a,b\""),
+            ("say \"hi\"", "\"say \"\"hi\"\"\""),
+            ("two\nlines", "\"two\nlines\""),
+            ("=SUM(A1:A2)", "'=SUM(A1:A2)"),
+            ("+1", "'+1"),
+            ("-1", "'-1"),
+            ("@cmd", "'@cmd"),
+            ("\tindented", "'\tindented"),
+            (
+                "=HYPERLINK(\"x\",\"y\")",
+                "\"'=HYPERLINK(\"\"x\"\",\"\"y\"\")\"",
+            ),
+            ("a=b", "a=b"),
+        ];
+        for (value, expected) in cases {
+            assert_eq!(csv_field(value), expected, "csv_field({value:?})");
+        }
+    }
+
+    #[test]
+    fn markdown_fence_outgrows_backtick_runs() {
+        let cases = [
+            ("no backticks", "```"),
+            ("inline `code`", "```"),
+            ("```rust\nfn main() {}\n```", "````"),
+            ("a ````` b", "``````"),
+        ];
+        for (content, fence) in cases {
+            let out = render_markdown(&[text_clip(content)], Utc::now());
+            let block = format!("{fence}\n{content}\n{fence}\n");
+            assert!(out.contains(&block), "{content:?} rendered as {out}");
+        }
+    }
+
+    #[test]
+    fn html_only_embeds_valid_base64_images() {
+        let mut good = text_clip("iVBORw0KGgo=");
+        good.kind = ClipKind::Image;
+        let mut bad = text_clip("\"><script>alert(1)</script>");
+        bad.kind = ClipKind::Image;
+        let out = render_html(&[good, bad], Utc::now());
+        assert!(out.contains("src=\"data:image/png;base64,iVBORw0KGgo=\""));
+        assert!(!out.contains("<script>"));
+        assert_eq!(out.matches("<img").count(), 1);
+    }
+}
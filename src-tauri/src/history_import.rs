@@ -0,0 +1,589 @@
+//! Importing history into a database that may already hold clips.
+//!
+//! Imported clips are matched against stored ones by `content_hash` and the
+//! chosen [`ImportStrategy`] decides what happens to duplicates. Every item
+//! runs in its own savepoint, so a broken item is reported and skipped while
+//! the rest of the file still goes in. A dry run performs the same work and
+//! rolls the transaction back, which keeps its counts exact.
+
+use std::path::Path;
+
+use anyhow::Context;
+use base64::engine::general_purpose::STANDARD as BASE64_STANDARD;
+use base64::Engine;
+use chrono::{DateTime, Utc};
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::{Deserialize, Serialize};
+
+use crate::clip_events::{self, ClipOp, ClipOrigin};
+use crate::db::{datetime_to_timestamp, detect_language, ClipItem, ClipKind, DbState};
+use crate::hash::compute_content_hash;
+use crate::history_export;
+use crate::import_sources;
+
+/// What to do with an imported clip whose content is already stored.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "camelCase")]
+pub enum ImportStrategy {
+    /// Leave the stored clip alone.
+    #[default]
+    Skip,
+    /// Replace the stored clip's flags, timestamps and metadata.
+    Overwrite,
+    /// Store the imported clip next to the existing one.
+    KeepBoth,
+    /// Take pinned/favorite from whichever copy was updated last.
+    MergeFlags,
+}
+
+impl ImportStrategy {
+    fn as_str(self) -> &'static str {
+        match self {
+            ImportStrategy::Skip => "skip",
+            ImportStrategy::Overwrite => "overwrite",
+            ImportStrategy::KeepBoth => "keepBoth",
+            ImportStrategy::MergeFlags => "mergeFlags",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ImportOptions {
+    #[serde(default)]
+    pub strategy: ImportStrategy,
+    /// Report what would happen without changing the database.
+    #[serde(default)]
+    pub dry_run: bool,
+}
+
+/// Layout of an import file, detected from its content.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum ImportFormat {
+    /// `{ "exported_at": ..., "items": [...] }` as written by export.
+    VibeClip,
+    /// A bare JSON array of clips.
+    ClipArray,
+    /// One clip object per line.
+    JsonLines,
+    /// A zip archive with `clips.json` and PNG files, as written by export.
+    Archive,
+    #[serde(rename = "copyq")]
+    CopyQ,
+    Ditto,
+    Maccy,
+    #[serde(rename = "gpaste")]
+    GPaste,
+    Clipman,
+}
+
+/// Clips read from a source, each either usable or the reason it is not.
+pub struct ParsedImport {
+    pub format: ImportFormat,
+    pub entries: Vec<Result<ClipItem, String>>,
+}
+
+impl ParsedImport {
+    /// Wraps raw clip objects, e.g. as sent by the frontend.
+    pub fn from_values(format: ImportFormat, values: Vec<serde_json::Value>) -> Self {
+        Self {
+            format,
+            entries: values
+                .into_iter()
+                .map(|value| serde_json::from_value(value).map_err(|err| err.to_string()))
+                .collect(),
+        }
+    }
+}
+
+/// Detects the format of a text file and parses it. Only a file that matches
+/// no format at all is an error; malformed items end up in `entries`.
+/// `modified` dates items from sources that keep no timestamps.
+pub fn parse_history(raw: &str, modified: DateTime<Utc>) -> anyhow::Result<ParsedImport> {
+    let trimmed = raw.trim_start_matches('\u{feff}').trim();
+    if trimmed.starts_with('<') {
+        return import_sources::parse_gpaste(trimmed, modified);
+    }
+    if let Ok(value) = serde_json::from_str::<serde_json::Value>(trimmed) {
+        match value {
+            serde_json::Value::Object(mut object) => {
+                if let Some(serde_json::Value::Array(items)) = object.remove("items") {
+                    return Ok(ParsedImport::from_values(ImportFormat::VibeClip, items));
+                }
+            }
+            serde_json::Value::Array(items) => {
+                if !items.is_empty() && items.iter().all(serde_json::Value::is_string) {
+                    let texts = items
+                        .into_iter()
+                        .filter_map(|item| item.as_str().map(str::to_string))
+                        .collect();
+                    return Ok(import_sources::parse_clipman(texts, modified));
+                }
+                return Ok(ParsedImport::from_values(ImportFormat::ClipArray, items));
+            }
+            _ => {}
+        }
+    }
+    if trimmed.starts_with('{') {
+        let entries = trimmed
+            .lines()
+            .filter(|line| !line.trim().is_empty())
+            .map(|line| serde_json::from_str(line).map_err(|err| err.to_string()))
+            .collect::<Vec<_>>();
+        if entries.iter().any(Result::is_ok) {
+            return Ok(ParsedImport {
+                format: ImportFormat::JsonLines,
+                entries,
+            });
+        }
+    }
+    anyhow::bail!("unrecognized history file format")
+}
+
+/// Reads an import file: a VibeClip export or another clipboard manager's
+/// history (CopyQ, Ditto, Maccy, GPaste or clipman).
+pub fn load_history_file(path: &Path) -> anyhow::Result<ParsedImport> {
+    let bytes =
+        std::fs::read(path).with_context(|| format!("failed to read {}", path.display()))?;
+    if bytes.starts_with(b"SQLite format 3\0") {
+        return import_sources::read_sqlite(path);
+    }
+    if bytes.starts_with(b"PK\x03\x04") {
+        let items = history_export::read_archive(&bytes)?;
+        return Ok(ParsedImport::from_values(ImportFormat::Archive, items));
+    }
+    let modified = std::fs::metadata(path)
+        .and_then(|metadata| metadata.modified())
+        .map(DateTime::<Utc>::from)
+        .unwrap_or_else(|_| Utc::now());
+    let text_error = match std::str::from_utf8(&bytes) {
+        Ok(raw) => match parse_history(raw, modified) {
+            Ok(parsed) => return Ok(parsed),
+            Err(err) => err,
+        },
+        Err(_) => anyhow::anyhow!("unrecognized history file format"),
+    };
+    import_sources::parse_copyq(&bytes, modified).map_err(|_| text_error)
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ImportItemError {
+    /// Position of the item in the source.
+    pub index: usize,
+    pub message: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ImportReport {
+    pub format: ImportFormat,
+    pub strategy: ImportStrategy,
+    pub dry_run: bool,
+    pub total: usize,
+    pub inserted: usize,
+    pub skipped: usize,
+    pub overwritten: usize,
+    pub kept_both: usize,
+    pub merged: usize,
+    pub failed: usize,
+    pub errors: Vec<ImportItemError>,
+}
+
+enum ImportOutcome {
+    Inserted,
+    Skipped,
+    Overwritten,
+    KeptBoth,
+    Merged,
+}
+
+impl DbState {
+    pub fn import_history(
+        &self,
+        parsed: ParsedImport,
+        options: &ImportOptions,
+    ) -> anyhow::Result<ImportReport> {
+        let mut report = ImportReport {
+            format: parsed.format,
+            strategy: options.strategy,
+            dry_run: options.dry_run,
+            total: parsed.entries.len(),
+            inserted: 0,
+            skipped: 0,
+            overwritten: 0,
+            kept_both: 0,
+            merged: 0,
+            failed: 0,
+            errors: Vec::new(),
+        };
+        let options = options.clone();
+        self.write(move |conn| {
+            let mut tx = conn.transaction()?;
+            for (index, entry) in parsed.entries.into_iter().enumerate() {
+                let outcome = entry.map_err(anyhow::Error::msg).and_then(|item| {
+                    let savepoint = tx.savepoint()?;
+                    let outcome = import_one(&savepoint, item, options.strategy)?;
+                    savepoint.commit()?;
+                    Ok(outcome)
+                });
+                match outcome {
+                    Ok(ImportOutcome::Inserted) => report.inserted += 1,
+                    Ok(ImportOutcome::Skipped) => report.skipped += 1,
+                    Ok(ImportOutcome::Overwritten) => report.overwritten += 1,
+                    Ok(ImportOutcome::KeptBoth) => report.kept_both += 1,
+                    Ok(ImportOutcome::Merged) => report.merged += 1,
+                    Err(err) => {
+                        report.failed += 1;
+                        report.errors.push(ImportItemError {
+                            index,
+                            message: format!("{err:#}"),
+                        });
+                    }
+                }
+            }
+            if options.dry_run {
+                tx.rollback()?;
+            } else {
+                tx.commit()?;
+            }
+            Ok(report)
+        })
+    }
+}
+
+fn import_one(
+    conn: &Connection,
+    item: ClipItem,
+    strategy: ImportStrategy,
+) -> anyhow::Result<ImportOutcome> {
+    if item.content.is_empty() {
+        anyhow::bail!("clip has no content");
+    }
+    if matches!(item.kind, ClipKind::Image) {
+        BASE64_STANDARD
+            .decode(item.content.trim())
+            .context("invalid image data")?;
+    }
+    // The file's own hash is never trusted: a wrong one would attach the
+    // clip to unrelated stored content.
+    let content_hash = compute_content_hash(item.kind, &item.content);
+    let existing: Option<(i64, bool, bool, i64)> = conn
+        .query_row(
+            "SELECT id, is_pinned, is_favorite, updated_at FROM clips WHERE content_hash = ?1 ORDER BY updated_at DESC LIMIT 1",
+            params![content_hash],
+            |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?)),
+        )
+        .optional()?;
+    let detail = Some(serde_json::json!({
+        "sourceId": item.id,
+        "strategy": strategy.as_str(),
+    }));
+    let updated_at = datetime_to_timestamp(item.updated_at);
+    let now = datetime_to_timestamp(Utc::now());
+
+    let Some((existing_id, pinned, favorite, existing_updated_at)) = existing else {
+        insert(conn, &item, &content_hash, detail)?;
+        return Ok(ImportOutcome::Inserted);
+    };
+    match strategy {
+        ImportStrategy::Skip => Ok(ImportOutcome::Skipped),
+        ImportStrategy::KeepBoth => {
+            insert(conn, &item, &content_hash, detail)?;
+            Ok(ImportOutcome::KeptBoth)
+        }
+        ImportStrategy::Overwrite => {
+            let language = item
+                .language
+                .clone()
+                .or_else(|| detect_language(item.kind, &item.content));
+            conn.execute(
+                "UPDATE clips SET preview = ?1, extra = ?2, is_pinned = ?3, is_favorite = ?4, created_at = ?5, \
+                 updated_at = ?6, language = ?7, flags_modified_at = ?8, content_modified_at = ?8 WHERE id = ?9",
+                params![
+                    item.preview,
+                    item.extra,
+                    item.is_pinned as i64,
+                    item.is_favorite as i64,
+                    datetime_to_timestamp(item.created_at),
+                    updated_at,
+                    language,
+                    now,
+                    existing_id
+                ],
+            )?;
+            clip_events::record(
+                conn,
+                ClipOp::Import,
+                Some(existing_id),
+                Some(&content_hash),
+                ClipOrigin::Import,
+                detail,
+            )?;
+            Ok(ImportOutcome::Overwritten)
+        }
+        ImportStrategy::MergeFlags => {
+            let unchanged = (item.is_pinned, item.is_favorite) == (pinned, favorite);
+            if updated_at <= existing_updated_at || unchanged {
+                return Ok(ImportOutcome::Skipped);
+            }
+            conn.execute(
+                "UPDATE clips SET is_pinned = ?1, is_favorite = ?2, updated_at = ?3, flags_modified_at = ?4 WHERE id = ?5",
+                params![
+                    item.is_pinned as i64,
+                    item.is_favorite as i64,
+                    updated_at,
+                    now,
+                    existing_id
+                ],
+            )?;
+            clip_events::record(
+                conn,
+                ClipOp::Import,
+                Some(existing_id),
+                Some(&content_hash),
+                ClipOrigin::Import,
+                detail,
+            )?;
+            Ok(ImportOutcome::Merged)
+        }
+    }
+}
+
+/// Inserts under a fresh id; the source id is only kept in the event detail.
+fn insert(
+    conn: &Connection,
+    item: &ClipItem,
+    content_hash: &str,
+    detail: Option<serde_json::Value>,
+) -> anyhow::Result<()> {
+    let language = item
+        .language
+        .clone()
+        .or_else(|| detect_language(item.kind, &item.content));
+    conn.execute(
+        "INSERT INTO clips (kind, content, content_hash, preview, extra, is_pinned, is_favorite, created_at, updated_at, language, source_app) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11)",
+        params![
+            i64::from(item.kind),
+            item.content,
+            content_hash,
+            item.preview,
+            item.extra,
+            item.is_pinned as i64,
+            item.is_favorite as i64,
+            datetime_to_timestamp(item.created_at),
+            datetime_to_timestamp(item.updated_at),
+            language,
+            item.source_app
+        ],
+    )?;
+    clip_events::record(
+        conn,
+        ClipOp::Import,
+        Some(conn.last_insert_rowid()),
+        Some(content_hash),
+        ClipOrigin::Import,
+        detail,
+    )?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    use crate::db::TestDb;
+
+    fn at(seconds: i64) -> DateTime<Utc> {
+        Utc.timestamp_opt(1_700_000_000 + seconds, 0).unwrap()
+    }
+
+    fn clip(content: &str, pinned: bool, updated: i64) -> ClipItem {
+        ClipItem {
+            id: 99,
+            kind: ClipKind::Text,
+            content: content.to_string(),
+            content_hash: String::new(),
+            preview: Some(format!("imported {content}")),
+            extra: None,
+            is_pinned: pinned,
+            is_favorite: false,
+            created_at: at(0),
+            updated_at: at(updated),
+            language: None,
+            source_app: None,
+        }
+    }
+
+    fn import(
+        db: &DbState,
+        items: Vec<ClipItem>,
+        strategy: ImportStrategy,
+        dry_run: bool,
+    ) -> ImportReport {
+        let parsed = ParsedImport {
+            format: ImportFormat::ClipArray,
+            entries: items.into_iter().map(Ok).collect(),
+        };
+        db.import_history(parsed, &ImportOptions { strategy, dry_run })
+            .unwrap()
+    }
+
+    /// A database holding "a" (unpinned, updated at 100) and "b".
+    fn seeded() -> TestDb {
+        let db = TestDb::new();
+        import(
+            &db,
+            vec![clip("a", false, 100), clip("b", false, 100)],
+            ImportStrategy::Skip,
+            false,
+        );
+        db
+    }
+
+    fn stored(db: &DbState) -> Vec<(String, bool, Option<String>)> {
+        let mut clips: Vec<_> = db
+            .list(None, None, 0, false)
+            .unwrap()
+            .into_iter()
+            .map(|clip| (clip.content, clip.is_pinned, clip.preview))
+            .collect();
+        clips.sort();
+        clips
+    }
+
+    fn counts(report: &ImportReport) -> [usize; 6] {
+        [
+            report.inserted,
+            report.skipped,
+            report.overwritten,
+            report.kept_both,
+            report.merged,
+            report.failed,
+        ]
+    }
+
+    #[test]
+    fn skip_leaves_stored_clips_alone() {
+        let db = seeded();
+        let report = import(
+            &db,
+            vec![clip("a", true, 200), clip("c", false, 0)],
+            ImportStrategy::Skip,
+            false,
+        );
+        assert_eq!(counts(&report), [1, 1, 0, 0, 0, 0]);
+        assert!(!stored(&db)[0].1);
+        assert_eq!(stored(&db).len(), 3);
+    }
+
+    #[test]
+    fn overwrite_replaces_metadata() {
+        let db = seeded();
+        let mut item = clip("a", true, 50);
+        item.preview = Some("new preview".to_string());
+        let report = import(&db, vec![item], ImportStrategy::Overwrite, false);
+        assert_eq!(counts(&report), [0, 0, 1, 0, 0, 0]);
+        assert_eq!(
+            stored(&db)[0],
+            ("a".to_string(), true, Some("new preview".to_string()))
+        );
+    }
+
+    #[test]
+    fn keep_both_adds_a_second_copy() {
+        let db = seeded();
+        let report = import(
+            &db,
+            vec![clip("a", true, 200)],
+            ImportStrategy::KeepBoth,
+            false,
+        );
+        assert_eq!(counts(&report), [0, 0, 0, 1, 0, 0]);
+        let contents: Vec<String> = stored(&db).into_iter().map(|clip| clip.0).collect();
+        assert_eq!(contents, ["a", "a", "b"]);
+    }
+
+    #[test]
+    fn merge_flags_takes_the_newer_copy() {
+        let db = seeded();
+        // Older than the stored copy: ignored.
+        let report = import(
+            &db,
+            vec![clip("a", true, 50)],
+            ImportStrategy::MergeFlags,
+            false,
+        );
+        assert_eq!(counts(&report), [0, 1, 0, 0, 0, 0]);
+        assert!(!stored(&db)[0].1);
+
+        let report = import(
+            &db,
+            vec![clip("a", true, 200)],
+            ImportStrategy::MergeFlags,
+            false,
+        );
+        assert_eq!(counts(&report), [0, 0, 0, 0, 1, 0]);
+        assert_eq!(
+            stored(&db)[0],
+            ("a".to_string(), true, Some("imported a".to_string()))
+        );
+    }
+
+    #[test]
+    fn dry_run_reports_the_same_counts_without_writing() {
+        let items = || {
+            let mut broken = clip("image", false, 0);
+            broken.kind = ClipKind::Image;
+            broken.content = "not base64!".to_string();
+            vec![
+                clip("a", true, 200),
+                clip("c", false, 0),
+                // Duplicate inside the file: inserted once, then seen as stored.
+                clip("c", true, 10),
+                clip("", false, 0),
+                broken,
+            ]
+        };
+        for strategy in [
+            ImportStrategy::Skip,
+            ImportStrategy::Overwrite,
+            ImportStrategy::KeepBoth,
+            ImportStrategy::MergeFlags,
+        ] {
+            let db = seeded();
+            let before = stored(&db);
+            let dry = import(&db, items(), strategy, true);
+            assert!(dry.dry_run);
+            assert_eq!(stored(&db), before, "{strategy:?} dry run wrote");
+            let real = import(&db, items(), strategy, false);
+            assert_eq!(counts(&dry), counts(&real), "{strategy:?}");
+            assert_eq!(real.failed, 2);
+            assert_eq!(
+                real.errors.iter().map(|err| err.index).collect::<Vec<_>>(),
+                [3, 4]
+            );
+        }
+    }
+
+    #[test]
+    fn file_hashes_are_recomputed() {
+        let db = seeded();
+        // Claims to be "b" but carries different content.
+        let mut item = clip("not b", true, 200);
+        item.content_hash = compute_content_hash(ClipKind::Text, "b");
+        let report = import(&db, vec![item], ImportStrategy::Overwrite, false);
+        assert_eq!(counts(&report), [1, 0, 0, 0, 0, 0]);
+        let stored = stored(&db);
+        assert_eq!(stored.len(), 3);
+        assert!(stored.iter().any(|clip| clip.0 == "b" && !clip.1));
+        let hashes: Vec<String> = db
+            .list(None, None, 0, false)
+            .unwrap()
+            .into_iter()
+            .filter(|clip| clip.content == "not b")
+            .map(|clip| clip.content_hash)
+            .collect();
+        assert_eq!(hashes, [compute_content_hash(ClipKind::Text, "not b")]);
+    }
+}
@@ -0,0 +1,794 @@
+//! Readers for other clipboard managers' history files.
+//!
+//! Each reader turns the foreign data into [`ClipItem`]s that
+//! [`DbState::import_history`](crate::db::DbState) can merge like a VibeClip
+//! export. Timestamps and pinned state are kept where the source has them;
+//! sources without timestamps get ones spaced a second apart, ending at the
+//! file's modification time, so their order survives.
+
+use std::io::{Cursor, Read};
+use std::path::Path;
+
+use anyhow::Context;
+use base64::engine::general_purpose::STANDARD as BASE64_STANDARD;
+use base64::Engine;
+use chrono::{DateTime, Duration, Utc};
+use flate2::read::ZlibDecoder;
+use quick_xml::events::Event;
+use quick_xml::Reader;
+use rusqlite::{params, Connection, OpenFlags, Row};
+
+use crate::db::{timestamp_to_datetime, ClipItem, ClipKind};
+use crate::history_import::{ImportFormat, ParsedImport};
+
+/// Seconds between the Unix epoch and Core Data's reference date (2001-01-01).
+const CORE_DATA_EPOCH: f64 = 978_307_200.0;
+/// Ditto's `stickyClipOrder` for clips that are not sticky.
+const DITTO_NOT_STICKY: i64 = -2_147_483_647;
+
+fn new_clip(
+    source_id: i64,
+    kind: ClipKind,
+    content: String,
+    preview: String,
+    extra: Option<String>,
+    created_at: DateTime<Utc>,
+) -> ClipItem {
+    ClipItem {
+        id: source_id,
+        kind,
+        content,
+        content_hash: String::new(),
+        preview: Some(preview),
+        extra,
+        is_pinned: false,
+        is_favorite: false,
+        created_at,
+        updated_at: created_at,
+        language: None,
+        source_app: None,
+    }
+}
+
+fn text_clip(source_id: i64, text: String, created_at: DateTime<Utc>) -> Result<ClipItem, String> {
+    let text = text.replace('\0', "");
+    if text.trim().is_empty() {
+        return Err("empty text".to_string());
+    }
+    let preview = text.chars().take(120).collect();
+    Ok(new_clip(
+        source_id,
+        ClipKind::Text,
+        text,
+        preview,
+        None,
+        created_at,
+    ))
+}
+
+fn file_clip(
+    source_id: i64,
+    paths: Vec<String>,
+    created_at: DateTime<Utc>,
+) -> Result<ClipItem, String> {
+    if paths.is_empty() {
+        return Err("no file paths".to_string());
+    }
+    let preview = if paths.len() == 1 {
+        Path::new(&paths[0])
+            .file_name()
+            .and_then(|name| name.to_str())
+            .map(|name| name.to_string())
+            .unwrap_or_else(|| paths[0].clone())
+    } else {
+        format!("{} 个项目", paths.len())
+    };
+    let extra = paths.first().cloned();
+    Ok(new_clip(
+        source_id,
+        ClipKind::File,
+        paths.join("\n"),
+        preview,
+        extra,
+        created_at,
+    ))
+}
+
+/// Stores PNG bytes as an image clip.
+fn image_clip(source_id: i64, png: &[u8], created_at: DateTime<Utc>) -> Result<ClipItem, String> {
+    let (width, height) =
+        image::ImageReader::with_format(Cursor::new(png), image::ImageFormat::Png)
+            .into_dimensions()
+            .map_err(|err| format!("invalid PNG: {err}"))?;
+    Ok(new_clip(
+        source_id,
+        ClipKind::Image,
+        BASE64_STANDARD.encode(png),
+        format!("{width} × {height} 图像"),
+        None,
+        created_at,
+    ))
+}
+
+/// Timestamps for sources that only keep order: the last of `count` items
+/// gets `newest`, each earlier one a second less.
+fn spaced_timestamp(newest: DateTime<Utc>, count: usize, position: usize) -> DateTime<Utc> {
+    newest - Duration::seconds(count.saturating_sub(position + 1) as i64)
+}
+
+fn file_url_to_path(url: &str) -> Option<String> {
+    let rest = url.trim().strip_prefix("file://")?;
+    let rest = rest.strip_prefix("localhost").unwrap_or(rest);
+    let mut bytes = Vec::with_capacity(rest.len());
+    let mut input = rest.bytes();
+    while let Some(byte) = input.next() {
+        if byte == b'%' {
+            let hex = [input.next()?, input.next()?];
+            let hex = std::str::from_utf8(&hex).ok()?;
+            bytes.push(u8::from_str_radix(hex, 16).ok()?);
+        } else {
+            bytes.push(byte);
+        }
+    }
+    String::from_utf8(bytes).ok()
+}
+
+fn uri_list_paths(list: &str) -> Vec<String> {
+    list.lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .filter_map(file_url_to_path)
+        .collect()
+}
+
+/// Opens a foreign SQLite store read-only and picks the reader by its tables.
+pub(crate) fn read_sqlite(path: &Path) -> anyhow::Result<ParsedImport> {
+    let conn = Connection::open_with_flags(path, OpenFlags::SQLITE_OPEN_READ_ONLY)
+        .with_context(|| format!("failed to open {}", path.display()))?;
+    let has_table = |name: &str| -> rusqlite::Result<bool> {
+        conn.query_row(
+            "SELECT COUNT(*) FROM sqlite_master WHERE type = 'table' AND name = ?1",
+            params![name],
+            |row| row.get::<_, i64>(0),
+        )
+        .map(|count| count > 0)
+    };
+    if has_table("Main")? && has_table("Data")? {
+        return read_ditto(&conn);
+    }
+    if has_table("ZHISTORYITEM")? && has_table("ZHISTORYITEMCONTENT")? {
+        return read_maccy(&conn);
+    }
+    if has_table("clips")? {
+        anyhow::bail!("this is a VibeClip database; restore it as a backup instead");
+    }
+    anyhow::bail!("unrecognized SQLite database")
+}
+
+/// Ditto keeps one row per clip in `Main` and every clipboard format of it
+/// in `Data`. Sticky clips become pinned, "never auto delete" ones favorites.
+fn read_ditto(conn: &Connection) -> anyhow::Result<ParsedImport> {
+    let mut stmt = conn.prepare("SELECT * FROM Main ORDER BY lID")?;
+    let mut formats =
+        conn.prepare("SELECT strClipBoardFormat, ooData FROM Data WHERE lParentID = ?1")?;
+    let rows = stmt
+        .query_map([], |row| {
+            Ok((
+                row.get::<_, i64>("lID")?,
+                optional_column::<i64>(row, "lDate"),
+                optional_column::<String>(row, "mText"),
+                optional_column::<i64>(row, "bIsGroup"),
+                optional_column::<i64>(row, "stickyClipOrder"),
+                optional_column::<i64>(row, "lDontAutoDelete"),
+                optional_column::<i64>(row, "lastPasteDate"),
+            ))
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+    let mut entries = Vec::with_capacity(rows.len());
+    for (id, date, text, is_group, sticky, keep, last_paste) in rows {
+        if is_group.unwrap_or(0) != 0 {
+            continue;
+        }
+        let data = formats
+            .query_map(params![id], |row| {
+                Ok((row.get::<_, String>(0)?, row.get::<_, Vec<u8>>(1)?))
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+        let created_at = date.map(timestamp_to_datetime).unwrap_or_else(Utc::now);
+        entries.push(ditto_clip(id, &data, text, created_at).map(|mut clip| {
+            clip.is_pinned = sticky.is_some_and(|order| order > DITTO_NOT_STICKY);
+            clip.is_favorite = keep.unwrap_or(0) > 0;
+            if let Some(pasted) = last_paste.filter(|pasted| *pasted > 0) {
+                clip.updated_at = clip.updated_at.max(timestamp_to_datetime(pasted));
+            }
+            clip
+        }));
+    }
+    Ok(ParsedImport {
+        format: ImportFormat::Ditto,
+        entries,
+    })
+}
+
+fn optional_column<T: rusqlite::types::FromSql>(row: &Row<'_>, name: &str) -> Option<T> {
+    row.get::<_, Option<T>>(name).ok().flatten()
+}
+
+fn ditto_clip(
+    id: i64,
+    data: &[(String, Vec<u8>)],
+    description: Option<String>,
+    created_at: DateTime<Utc>,
+) -> Result<ClipItem, String> {
+    let format = |name: &str| {
+        data.iter()
+            .find(|(format, _)| format.eq_ignore_ascii_case(name))
+            .map(|(_, bytes)| bytes.as_slice())
+    };
+    if let Some(drop) = format("CF_HDROP") {
+        return file_clip(id, parse_hdrop(drop)?, created_at);
+    }
+    if let Some(png) = format("PNG") {
+        return image_clip(id, png, created_at);
+    }
+    if let Some(dib) = format("CF_DIB") {
+        return image_clip(id, &dib_to_png(dib)?, created_at);
+    }
+    if let Some(text) = format("CF_UNICODETEXT") {
+        let units = text
+            .chunks_exact(2)
+            .map(|pair| u16::from_le_bytes([pair[0], pair[1]]))
+            .take_while(|unit| *unit != 0)
+            .collect::<Vec<_>>();
+        return text_clip(id, String::from_utf16_lossy(&units), created_at);
+    }
+    if let Some(text) = format("CF_TEXT") {
+        let end = text
+            .iter()
+            .position(|byte| *byte == 0)
+            .unwrap_or(text.len());
+        return text_clip(
+            id,
+            String::from_utf8_lossy(&text[..end]).into_owned(),
+            created_at,
+        );
+    }
+    match description {
+        Some(text) => text_clip(id, text, created_at),
+        None => Err("clip has no supported clipboard format".to_string()),
+    }
+}
+
+/// Parses a Windows `DROPFILES` structure into paths.
+fn parse_hdrop(data: &[u8]) -> Result<Vec<String>, String> {
+    let field = |offset: usize| {
+        data.get(offset..offset + 4)
+            .map(|bytes| u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) as usize)
+    };
+    let (Some(start), Some(wide)) = (field(0), field(16)) else {
+        return Err("truncated CF_HDROP data".to_string());
+    };
+    let list = data.get(start..).ok_or("invalid CF_HDROP offset")?;
+    let joined = if wide != 0 {
+        let units = list
+            .chunks_exact(2)
+            .map(|pair| u16::from_le_bytes([pair[0], pair[1]]))
+            .collect::<Vec<_>>();
+        String::from_utf16_lossy(&units)
+    } else {
+        String::from_utf8_lossy(list).into_owned()
+    };
+    Ok(joined
+        .split('\0')
+        .take_while(|path| !path.is_empty())
+        .map(str::to_string)
+        .collect())
+}
+
+/// `CF_DIB` is a BMP file without its 14-byte file header.
+fn dib_to_png(dib: &[u8]) -> Result<Vec<u8>, String> {
+    let read_u32 = |offset: usize| {
+        dib.get(offset..offset + 4)
+            .map(|bytes| u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    };
+    let read_u16 = |offset: usize| {
+        dib.get(offset..offset + 2)
+            .map(|bytes| u16::from_le_bytes([bytes[0], bytes[1]]))
+    };
+    let (Some(header_size), Some(bit_count), Some(compression), Some(colors_used)) =
+        (read_u32(0), read_u16(14), read_u32(16), read_u32(32))
+    else {
+        return Err("truncated CF_DIB data".to_string());
+    };
+    let colors = if bit_count <= 8 && colors_used == 0 {
+        1u32 << bit_count
+    } else {
+        colors_used
+    };
+    // BI_BITFIELDS with a plain BITMAPINFOHEADER stores three masks after it.
+    let masks = if compression == 3 && header_size == 40 {
+        12
+    } else {
+        0
+    };
+    let offset = colors
+        .checked_mul(4)
+        .and_then(|palette| 14u32.checked_add(header_size)?.checked_add(palette))
+        .and_then(|offset| offset.checked_add(masks))
+        .ok_or("invalid CF_DIB header")?;
+    let file_size = u32::try_from(dib.len())
+        .ok()
+        .and_then(|len| len.checked_add(14))
+        .ok_or("CF_DIB data is too large")?;
+    let mut bmp = Vec::with_capacity(dib.len() + 14);
+    bmp.extend_from_slice(b"BM");
+    bmp.extend_from_slice(&file_size.to_le_bytes());
+    bmp.extend_from_slice(&[0; 4]);
+    bmp.extend_from_slice(&offset.to_le_bytes());
+    bmp.extend_from_slice(dib);
+    let image = image::load_from_memory_with_format(&bmp, image::ImageFormat::Bmp)
+        .map_err(|err| format!("invalid CF_DIB image: {err}"))?;
+    let mut png = Vec::new();
+    image
+        .write_to(&mut Cursor::new(&mut png), image::ImageFormat::Png)
+        .map_err(|err| format!("failed to convert CF_DIB image: {err}"))?;
+    Ok(png)
+}
+
+/// Maccy (Core Data) stores items in `ZHISTORYITEM` and one row per
+/// pasteboard type in `ZHISTORYITEMCONTENT`. Pinned items have a pin key.
+fn read_maccy(conn: &Connection) -> anyhow::Result<ParsedImport> {
+    let mut stmt = conn.prepare("SELECT * FROM ZHISTORYITEM ORDER BY Z_PK")?;
+    let mut contents =
+        conn.prepare("SELECT ZTYPE, ZVALUE FROM ZHISTORYITEMCONTENT WHERE ZITEM = ?1")?;
+    let rows = stmt
+        .query_map([], |row| {
+            Ok((
+                row.get::<_, i64>("Z_PK")?,
+                optional_column::<f64>(row, "ZFIRSTCOPIEDAT"),
+                optional_column::<f64>(row, "ZLASTCOPIEDAT"),
+                optional_column::<String>(row, "ZPIN"),
+                optional_column::<String>(row, "ZTITLE"),
+            ))
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+    let core_data_time = |seconds: f64| timestamp_to_datetime((seconds + CORE_DATA_EPOCH) as i64);
+    let mut entries = Vec::with_capacity(rows.len());
+    for (id, first, last, pin, title) in rows {
+        let data = contents
+            .query_map(params![id], |row| {
+                Ok((
+                    row.get::<_, Option<String>>(0)?.unwrap_or_default(),
+                    row.get::<_, Option<Vec<u8>>>(1)?.unwrap_or_default(),
+                ))
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+        let created_at = first.map(core_data_time).unwrap_or_else(Utc::now);
+        entries.push(maccy_clip(id, &data, title, created_at).map(|mut clip| {
+            clip.is_pinned = pin.is_some_and(|pin| !pin.is_empty());
+            if let Some(last) = last {
+                clip.updated_at = clip.updated_at.max(core_data_time(last));
+            }
+            clip
+        }));
+    }
+    Ok(ParsedImport {
+        format: ImportFormat::Maccy,
+        entries,
+    })
+}
+
+fn maccy_clip(
+    id: i64,
+    data: &[(String, Vec<u8>)],
+    title: Option<String>,
+    created_at: DateTime<Utc>,
+) -> Result<ClipItem, String> {
+    let of_type = |name: &'static str| {
+        data.iter()
+            .filter(move |(kind, _)| kind == name)
+            .map(|(_, value)| value.as_slice())
+    };
+    let paths = of_type("public.file-url")
+        .filter_map(|value| file_url_to_path(&String::from_utf8_lossy(value)))
+        .collect::<Vec<_>>();
+    if !paths.is_empty() {
+        return file_clip(id, paths, created_at);
+    }
+    if let Some(png) = of_type("public.png").next() {
+        return image_clip(id, png, created_at);
+    }
+    if let Some(text) = of_type("public.utf8-plain-text").next() {
+        return text_clip(id, String::from_utf8_lossy(text).into_owned(), created_at);
+    }
+    match title {
+        Some(title) => text_clip(id, title, created_at),
+        None => Err("item has no supported pasteboard type".to_string()),
+    }
+}
+
+/// GPaste's `history.xml`, newest item first. Image items point to PNG files
+/// next to the history; password items are left out on purpose.
+pub(crate) fn parse_gpaste(xml: &str, modified: DateTime<Utc>) -> anyhow::Result<ParsedImport> {
+    struct GPasteItem {
+        kind: String,
+        date: Option<i64>,
+        value: String,
+    }
+    let mut reader = Reader::from_str(xml);
+    let mut items = Vec::new();
+    let mut seen_history = false;
+    let mut current: Option<GPasteItem> = None;
+    let mut in_value = false;
+    loop {
+        match reader.read_event().context("invalid GPaste history XML")? {
+            Event::Start(element) => match element.local_name().as_ref() {
+                b"history" => seen_history = true,
+                b"item" => {
+                    let mut item = GPasteItem {
+                        kind: String::new(),
+                        date: None,
+                        value: String::new(),
+                    };
+                    for attribute in element.attributes().flatten() {
+                        let value = attribute.unescape_value()?;
+                        match attribute.key.local_name().as_ref() {
+                            b"kind" => item.kind = value.into_owned(),
+                            b"date" => item.date = value.trim().parse().ok(),
+                            _ => {}
+                        }
+                    }
+                    current = Some(item);
+                }
+                b"value" => in_value = true,
+                _ => {}
+            },
+            Event::End(element) => match element.local_name().as_ref() {
+                b"item" => items.extend(current.take()),
+                b"value" => in_value = false,
+                _ => {}
+            },
+            Event::CData(data) if in_value => {
+                if let Some(item) = current.as_mut() {
+                    item.value
+                        .push_str(&String::from_utf8_lossy(&data.into_inner()));
+                }
+            }
+            Event::Text(text) if in_value => {
+                if let Some(item) = current.as_mut() {
+                    item.value.push_str(&text.unescape()?);
+                }
+            }
+            Event::Eof => break,
+            _ => {}
+        }
+    }
+    if !seen_history {
+        anyhow::bail!("not a GPaste history file");
+    }
+    let count = items.len();
+    let entries = items
+        .into_iter()
+        .enumerate()
+        .map(|(index, item)| {
+            let created_at = item
+                .date
+                .map(timestamp_to_datetime)
+                .unwrap_or_else(|| spaced_timestamp(modified, count, count - 1 - index));
+            let id = index as i64;
+            match item.kind.as_str() {
+                "Text" => text_clip(id, item.value, created_at),
+                "Uris" => file_clip(id, uri_list_paths(&item.value), created_at),
+                "Image" => {
+                    let png = std::fs::read(item.value.trim())
+                        .map_err(|err| format!("failed to read {}: {err}", item.value.trim()))?;
+                    image_clip(id, &png, created_at)
+                }
+                "Password" => Err("password items are not imported".to_string()),
+                other => Err(format!("unsupported GPaste item kind {other:?}")),
+            }
+        })
+        .collect();
+    Ok(ParsedImport {
+        format: ImportFormat::GPaste,
+        entries,
+    })
+}
+
+/// clipman's history is a JSON array of strings, oldest first.
+pub(crate) fn parse_clipman(texts: Vec<String>, modified: DateTime<Utc>) -> ParsedImport {
+    let count = texts.len();
+    ParsedImport {
+        format: ImportFormat::Clipman,
+        entries: texts
+            .into_iter()
+            .enumerate()
+            .map(|(index, text)| {
+                text_clip(index as i64, text, spaced_timestamp(modified, count, index))
+            })
+            .collect(),
+    }
+}
+
+/// Reads a CopyQ tab file (`copyq_tab_*.dat`): a Qt `QDataStream` holding an
+/// item count followed by one MIME-type → data map per item, newest first.
+pub(crate) fn parse_copyq(bytes: &[u8], modified: DateTime<Utc>) -> anyhow::Result<ParsedImport> {
+    let mut stream = QDataStream { bytes, position: 0 };
+    // Some versions start the file with a "CopyQ ..." header string.
+    let start = stream.position;
+    if !matches!(stream.read_string(), Ok(Some(header)) if header.starts_with("CopyQ")) {
+        stream.position = start;
+    }
+    let count = stream.read_i32()?;
+    if !(0..=1_000_000).contains(&count) {
+        anyhow::bail!("not a CopyQ tab file");
+    }
+    let count = count as usize;
+    let mut entries = Vec::with_capacity(count);
+    for index in 0..count {
+        let data = stream
+            .read_item()
+            .with_context(|| format!("CopyQ item {index} is corrupted"))?;
+        let created_at = spaced_timestamp(modified, count, count - 1 - index);
+        entries.push(copyq_clip(index as i64, &data, created_at));
+    }
+    Ok(ParsedImport {
+        format: ImportFormat::CopyQ,
+        entries,
+    })
+}
+
+fn copyq_clip(
+    id: i64,
+    data: &[(String, Vec<u8>)],
+    created_at: DateTime<Utc>,
+) -> Result<ClipItem, String> {
+    let mime = |name: &str| {
+        data.iter()
+            .find(|(mime, _)| mime == name)
+            .map(|(_, bytes)| bytes.as_slice())
+    };
+    let clip = if let Some(list) = mime("text/uri-list") {
+        file_clip(
+            id,
+            uri_list_paths(&String::from_utf8_lossy(list)),
+            created_at,
+        )
+    } else if let Some(png) = mime("image/png") {
+        image_clip(id, png, created_at)
+    } else if let Some(text) = mime("text/plain").or_else(|| mime("text/plain;charset=utf-8")) {
+        text_clip(id, String::from_utf8_lossy(text).into_owned(), created_at)
+    } else {
+        Err("item has no supported MIME type".to_string())
+    };
+    clip.map(|mut clip| {
+        clip.is_pinned = mime("application/x-copyq-item-pinned").is_some();
+        clip
+    })
+}
+
+/// Minimal reader for the big-endian `QDataStream` encoding CopyQ uses.
+struct QDataStream<'a> {
+    bytes: &'a [u8],
+    position: usize,
+}
+
+impl QDataStream<'_> {
+    fn take(&mut self, len: usize) -> anyhow::Result<&[u8]> {
+        let end = self
+            .position
+            .checked_add(len)
+            .filter(|end| *end <= self.bytes.len())
+            .context("unexpected end of data")?;
+        let slice = &self.bytes[self.position..end];
+        self.position = end;
+        Ok(slice)
+    }
+
+    fn read_u32(&mut self) -> anyhow::Result<u32> {
+        let bytes = self.take(4)?;
+        Ok(u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+
+    fn read_i32(&mut self) -> anyhow::Result<i32> {
+        Ok(self.read_u32()? as i32)
+    }
+
+    fn read_bool(&mut self) -> anyhow::Result<bool> {
+        Ok(self.take(1)?[0] != 0)
+    }
+
+    /// `QByteArray`; a length of `0xFFFFFFFF` is a null array.
+    fn read_bytes(&mut self) -> anyhow::Result<Vec<u8>> {
+        match self.read_u32()? {
+            u32::MAX => Ok(Vec::new()),
+            len => Ok(self.take(len as usize)?.to_vec()),
+        }
+    }
+
+    /// `QString`: a byte length followed by UTF-16BE.
+    fn read_string(&mut self) -> anyhow::Result<Option<String>> {
+        let len = self.read_u32()?;
+        if len == u32::MAX {
+            return Ok(None);
+        }
+        if len % 2 != 0 {
+            anyhow::bail!("invalid string length");
+        }
+        let units = self
+            .take(len as usize)?
+            .chunks_exact(2)
+            .map(|pair| u16::from_be_bytes([pair[0], pair[1]]))
+            .collect::<Vec<_>>();
+        Ok(Some(String::from_utf16(&units)?))
+    }
+
+    /// One item's MIME map. Version 2 (marked by -2) stores MIME types as
+    /// UTF-8 with a one-digit prefix code; version 1 stores them as strings.
+    fn read_item(&mut self) -> anyhow::Result<Vec<(String, Vec<u8>)>> {
+        let marker = self.read_i32()?;
+        let (size, compact_mime) = match marker {
+            -2 => (self.read_i32()?, true),
+            size if size >= 0 => (size, false),
+            _ => anyhow::bail!("unsupported item version {marker}"),
+        };
+        let mut data = Vec::new();
+        for _ in 0..size {
+            let mime = if compact_mime {
+                expand_copyq_mime(&self.read_bytes()?)
+            } else {
+                self.read_string()?.unwrap_or_default()
+            };
+            let compressed = self.read_bool()?;
+            let mut bytes = self.read_bytes()?;
+            if compressed {
+                bytes = q_uncompress(&bytes)?;
+            }
+            data.push((mime, bytes));
+        }
+        Ok(data)
+    }
+}
+
+fn expand_copyq_mime(encoded: &[u8]) -> String {
+    let text = String::from_utf8_lossy(encoded);
+    let prefix = match text.chars().next() {
+        Some('0') => "",
+        Some('1') => "application/x-copyq-",
+        Some('2') => "text/",
+        Some('3') => "application/",
+        Some('4') => "image/",
+        _ => return text.into_owned(),
+    };
+    format!("{prefix}{}", &text[1..])
+}
+
+/// `qUncompress`: a big-endian expected length, then a zlib stream.
+fn q_uncompress(bytes: &[u8]) -> anyhow::Result<Vec<u8>> {
+    let body = bytes.get(4..).context("truncated compressed data")?;
+    let mut out = Vec::new();
+    ZlibDecoder::new(body)
+        .read_to_end(&mut out)
+        .context("invalid compressed data")?;
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use flate2::write::ZlibEncoder;
+    use flate2::Compression;
+
+    use super::*;
+
+    fn q_string(out: &mut Vec<u8>, text: &str) {
+        let units: Vec<u8> = text.encode_utf16().flat_map(u16::to_be_bytes).collect();
+        out.extend_from_slice(&(units.len() as u32).to_be_bytes());
+        out.extend_from_slice(&units);
+    }
+
+    fn q_bytes(out: &mut Vec<u8>, bytes: &[u8]) {
+        out.extend_from_slice(&(bytes.len() as u32).to_be_bytes());
+        out.extend_from_slice(bytes);
+    }
+
+    #[test]
+    fn parse_copyq_items() {
+        let mut file = Vec::new();
+        q_string(&mut file, "CopyQ v3");
+        file.extend_from_slice(&2i32.to_be_bytes());
+        // Version 1 item: MIME types as QString, uncompressed data.
+        file.extend_from_slice(&2i32.to_be_bytes());
+        q_string(&mut file, "text/plain");
+        file.push(0);
+        q_bytes(&mut file, b"newest");
+        q_string(&mut file, "application/x-copyq-item-pinned");
+        file.push(0);
+        q_bytes(&mut file, b"");
+        // Version 2 item: compact MIME type, qCompress'ed data.
+        file.extend_from_slice(&(-2i32).to_be_bytes());
+        file.extend_from_slice(&1i32.to_be_bytes());
+        q_bytes(&mut file, b"2plain");
+        file.push(1);
+        let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(b"older").unwrap();
+        let mut compressed = 5u32.to_be_bytes().to_vec();
+        compressed.extend(encoder.finish().unwrap());
+        q_bytes(&mut file, &compressed);
+
+        let modified = timestamp_to_datetime(1_700_000_000);
+        let parsed = parse_copyq(&file, modified).unwrap();
+        let clips: Vec<ClipItem> = parsed.entries.into_iter().map(Result::unwrap).collect();
+        assert_eq!(clips.len(), 2);
+        assert_eq!(clips[0].content, "newest");
+        assert!(clips[0].is_pinned);
+        assert_eq!(clips[0].created_at, modified);
+        assert_eq!(clips[1].content, "older");
+        assert!(!clips[1].is_pinned);
+        assert!(clips[1].created_at < modified);
+
+        assert!(parse_copyq(&file[..file.len() - 3], modified).is_err());
+        assert!(parse_copyq(&(-5i32).to_be_bytes(), modified).is_err());
+    }
+
+    #[test]
+    fn parse_hdrop_paths() {
+        let mut wide = vec![0u8; 20];
+        wide[0] = 20;
+        wide[16] = 1;
+        for unit in "C:\\a.txt\0D:\\b\0\0".encode_utf16() {
+            wide.extend_from_slice(&unit.to_le_bytes());
+        }
+        assert_eq!(parse_hdrop(&wide).unwrap(), ["C:\\a.txt", "D:\\b"]);
+
+        let mut narrow = vec![0u8; 20];
+        narrow[0] = 20;
+        narrow.extend_from_slice(b"C:\\only.txt\0\0");
+        assert_eq!(parse_hdrop(&narrow).unwrap(), ["C:\\only.txt"]);
+
+        assert!(parse_hdrop(&[20, 0, 0]).is_err());
+        let mut bad_offset = vec![0u8; 20];
+        bad_offset[0] = 200;
+        assert!(parse_hdrop(&bad_offset).is_err());
+    }
+
+    /// A 2×1 24-bit `BITMAPINFOHEADER` image: one red and one green pixel.
+    fn dib_fixture() -> Vec<u8> {
+        let mut dib = Vec::new();
+        for value in [40u32, 2, 1] {
+            dib.extend_from_slice(&value.to_le_bytes());
+        }
+        dib.extend_from_slice(&1u16.to_le_bytes());
+        dib.extend_from_slice(&24u16.to_le_bytes());
+        for value in [0u32, 8, 0, 0, 0, 0] {
+            dib.extend_from_slice(&value.to_le_bytes());
+        }
+        dib.extend_from_slice(&[0, 0, 255, 0, 255, 0, 0, 0]);
+        dib
+    }
+
+    #[test]
+    fn convert_dib_to_png() {
+        let png = dib_to_png(&dib_fixture()).unwrap();
+        let image = image::load_from_memory_with_format(&png, image::ImageFormat::Png)
+            .unwrap()
+            .to_rgb8();
+        assert_eq!(image.dimensions(), (2, 1));
+        assert_eq!(image.get_pixel(0, 0).0, [255, 0, 0]);
+        assert_eq!(image.get_pixel(1, 0).0, [0, 255, 0]);
+    }
+
+    #[test]
+    fn reject_malformed_dib() {
+        assert!(dib_to_png(&dib_fixture()[..20]).is_err());
+
+        let mut huge_header = dib_fixture();
+        huge_header[..4].copy_from_slice(&u32::MAX.to_le_bytes());
+        assert!(dib_to_png(&huge_header).is_err());
+
+        let mut huge_palette = dib_fixture();
+        huge_palette[32..36].copy_from_slice(&0x4000_0000u32.to_le_bytes());
+        assert!(dib_to_png(&huge_palette).is_err());
+    }
+}
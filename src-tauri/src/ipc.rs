@@ -0,0 +1,299 @@
+//! Remote control of the running instance.
+//!
+//! Commands arrive either as arguments of a second launch (forwarded by the
+//! single-instance plugin) or, on unix, as lines on a local socket, so
+//! window-manager keybindings can drive VibeClip without global shortcuts:
+//!
+//! ```text
+//! vibeclip_pro --toggle-panel
+//! vibeclip remote --paste-clip 42
+//! echo '["--add-text", "hello world"]' | socat - UNIX-CONNECT:$XDG_RUNTIME_DIR/vibeclip.sock
+//! ```
+
+use anyhow::Context;
+use base64::engine::general_purpose::STANDARD as BASE64_STANDARD;
+use base64::Engine;
+use tauri::{AppHandle, Emitter, Manager};
+use tauri_plugin_clipboard_manager::ClipboardExt;
+use tracing::{error, info};
+
+use crate::clip_events::ClipOrigin;
+use crate::clipboard::finalize_payload;
+use crate::db::{ClipKind, ClipPayload, DbState};
+use crate::hash::compute_content_hash;
+use crate::runtime_config::RuntimeConfigState;
+use crate::state::AppStatus;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum IpcCommand {
+    ShowMain,
+    TogglePanel,
+    Pause,
+    Resume,
+    AddText(String),
+    PasteClip(i64),
+}
+
+/// Extracts control commands from a command line. Flags that are not control
+/// commands (`--autostart`, the program name, ...) are skipped.
+pub fn parse_args(args: &[String]) -> anyhow::Result<Vec<IpcCommand>> {
+    let mut commands = Vec::new();
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        let command = match arg.as_str() {
+            "--show" => IpcCommand::ShowMain,
+            "--toggle-panel" => IpcCommand::TogglePanel,
+            "--pause" => IpcCommand::Pause,
+            "--resume" => IpcCommand::Resume,
+            "--add-text" => {
+                let text = iter.next().context("--add-text needs a value")?;
+                IpcCommand::AddText(text.clone())
+            }
+            "--paste-clip" => {
+                let id = iter.next().context("--paste-clip needs a clip id")?;
+                IpcCommand::PasteClip(
+                    id.parse()
+                        .with_context(|| format!("invalid clip id {id}"))?,
+                )
+            }
+            _ => continue,
+        };
+        commands.push(command);
+    }
+    Ok(commands)
+}
+
+/// Handles the arguments of a second launch. Without control commands the
+/// main window is brought to front, as before.
+pub fn handle_second_instance(app: &AppHandle, args: Vec<String>) {
+    let commands = match parse_args(&args) {
+        Ok(commands) => commands,
+        Err(err) => {
+            error!("ignoring second instance arguments: {err:#}");
+            return;
+        }
+    };
+    let commands = if commands.is_empty() {
+        vec![IpcCommand::ShowMain]
+    } else {
+        commands
+    };
+    let app = app.clone();
+    tauri::async_runtime::spawn(async move {
+        for command in commands {
+            if let Err(err) = dispatch(&app, command.clone()).await {
+                error!("ipc command {command:?} failed: {err:#}");
+            }
+        }
+    });
+}
+
+pub async fn dispatch(app: &AppHandle, command: IpcCommand) -> anyhow::Result<()> {
+    info!("ipc command: {command:?}");
+    match command {
+        IpcCommand::ShowMain => crate::show_main_window(app.clone())
+            .await
+            .map_err(anyhow::Error::msg),
+        IpcCommand::TogglePanel => crate::toggle_quick_panel(app.clone())
+            .await
+            .map_err(anyhow::Error::msg),
+        IpcCommand::Pause | IpcCommand::Resume => {
+            crate::tray::set_listening(app, command == IpcCommand::Resume);
+            Ok(())
+        }
+        IpcCommand::AddText(text) => {
+            if text.trim().is_empty() {
+                anyhow::bail!("text must not be empty");
+            }
+            let payload = finalize_payload(ClipPayload {
+                kind: ClipKind::Text,
+                preview: Some(text.chars().take(120).collect()),
+                content: text,
+                extra: None,
+                content_hash: None,
+                is_pinned: false,
+                is_favorite: false,
+                source_app: None,
+            });
+            let db = app.state::<DbState>().clone_for_thread();
+            let prefs = app.state::<RuntimeConfigState>().get();
+            let clip = tauri::async_runtime::spawn_blocking(move || {
+                let clip = db.upsert(payload, ClipOrigin::Ipc)?;
+                db.apply_retention(&prefs)?;
+                Ok::<_, anyhow::Error>(clip)
+            })
+            .await??;
+            let _ = app.emit("clip-inserted", &clip);
+            Ok(())
+        }
+        IpcCommand::PasteClip(id) => {
+            let db = app.state::<DbState>().clone_for_thread();
+            let clip = tauri::async_runtime::spawn_blocking(move || db.get(id))
+                .await??
+                .with_context(|| format!("clip {id} does not exist"))?;
+            app.state::<AppStatus>()
+                .mark_self_copy(compute_content_hash(clip.kind, &clip.content));
+            match clip.kind {
+                ClipKind::Image => {
+                    let bytes = BASE64_STANDARD
+                        .decode(clip.content.trim())
+                        .context("image clip is not valid base64")?;
+                    let image = image::load_from_memory(&bytes)?.to_rgba8();
+                    let (width, height) = image.dimensions();
+                    let image = tauri::image::Image::new_owned(image.into_raw(), width, height);
+                    app.clipboard().write_image(&image)?;
+                }
+                _ => app.clipboard().write_text(clip.content)?,
+            }
+            crate::simulate_paste().await.map_err(anyhow::Error::msg)
+        }
+    }
+}
+
+#[cfg(unix)]
+pub use socket::{send, spawn_listener};
+
+#[cfg(unix)]
+mod socket {
+    use std::io::{BufRead, BufReader, Write};
+    use std::os::unix::fs::{DirBuilderExt, MetadataExt, PermissionsExt};
+    use std::os::unix::net::{UnixListener, UnixStream};
+    use std::path::{Path, PathBuf};
+
+    use anyhow::Context;
+    use tauri::AppHandle;
+    use tracing::{error, info, warn};
+
+    use super::{dispatch, parse_args};
+
+    const SOCKET_NAME: &str = "vibeclip.sock";
+
+    /// `$XDG_RUNTIME_DIR/vibeclip.sock`, falling back to a private
+    /// `vibeclip-<user>` directory in the temp directory.
+    pub fn socket_path() -> anyhow::Result<PathBuf> {
+        if let Some(dir) = std::env::var_os("XDG_RUNTIME_DIR")
+            .map(PathBuf::from)
+            .filter(|dir| dir.is_dir())
+        {
+            return Ok(dir.join(SOCKET_NAME));
+        }
+        let user = std::env::var("USER")
+            .or_else(|_| std::env::var("LOGNAME"))
+            .unwrap_or_else(|_| "default".to_string());
+        let dir = std::env::temp_dir().join(format!("vibeclip-{user}"));
+        private_dir(&dir)?;
+        Ok(dir.join(SOCKET_NAME))
+    }
+
+    /// Creates `dir` as 0700, or checks that an existing one is a real
+    /// directory of the current user that nobody else can enter, so another
+    /// user cannot swap the socket in a shared temp directory.
+    fn private_dir(dir: &Path) -> anyhow::Result<()> {
+        match std::fs::DirBuilder::new().mode(0o700).create(dir) {
+            Ok(()) => return Ok(()),
+            Err(err) if err.kind() == std::io::ErrorKind::AlreadyExists => {}
+            Err(err) => {
+                return Err(err).with_context(|| format!("failed to create {}", dir.display()))
+            }
+        }
+        let meta = std::fs::symlink_metadata(dir)?;
+        let owner = std::env::var_os("HOME")
+            .and_then(|home| std::fs::metadata(home).ok())
+            .map(|home| home.uid());
+        if !meta.is_dir() || meta.mode() & 0o077 != 0 || owner.is_some_and(|uid| uid != meta.uid())
+        {
+            anyhow::bail!(
+                "{} is not a private directory of the current user",
+                dir.display()
+            );
+        }
+        Ok(())
+    }
+
+    /// Listens for control commands. Each line is either a JSON array of
+    /// arguments or whitespace-separated flags; the reply is `ok` or
+    /// `error: <message>`.
+    pub fn spawn_listener(app: &AppHandle) -> anyhow::Result<()> {
+        let path = socket_path()?;
+        if UnixStream::connect(&path).is_ok() {
+            anyhow::bail!("another instance already listens on {}", path.display());
+        }
+        let _ = std::fs::remove_file(&path);
+        let listener = UnixListener::bind(&path)
+            .with_context(|| format!("failed to bind {}", path.display()))?;
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o600))?;
+        info!("ipc socket listening on {}", path.display());
+
+        let app = app.clone();
+        std::thread::Builder::new()
+            .name("ipc-socket".into())
+            .spawn(move || {
+                for stream in listener.incoming() {
+                    match stream {
+                        Ok(stream) => {
+                            let app = app.clone();
+                            std::thread::spawn(move || serve(&app, stream));
+                        }
+                        Err(err) => warn!("ipc socket accept failed: {err}"),
+                    }
+                }
+            })?;
+        Ok(())
+    }
+
+    fn serve(app: &AppHandle, stream: UnixStream) {
+        let mut writer = match stream.try_clone() {
+            Ok(writer) => writer,
+            Err(err) => {
+                error!("ipc socket clone failed: {err}");
+                return;
+            }
+        };
+        for line in BufReader::new(stream).lines() {
+            let Ok(line) = line else {
+                break;
+            };
+            if line.trim().is_empty() {
+                continue;
+            }
+            let reply = match run_line(app, &line) {
+                Ok(()) => "ok".to_string(),
+                Err(err) => format!("error: {err:#}"),
+            };
+            if writeln!(writer, "{reply}").is_err() {
+                break;
+            }
+        }
+    }
+
+    fn run_line(app: &AppHandle, line: &str) -> anyhow::Result<()> {
+        let args: Vec<String> = if line.trim_start().starts_with('[') {
+            serde_json::from_str(line).context("invalid JSON argument list")?
+        } else {
+            line.split_whitespace().map(str::to_string).collect()
+        };
+        let commands = parse_args(&args)?;
+        if commands.is_empty() {
+            anyhow::bail!("no command given");
+        }
+        for command in commands {
+            tauri::async_runtime::block_on(dispatch(app, command))?;
+        }
+        Ok(())
+    }
+
+    /// Sends one argument list to the running instance and returns its reply.
+    pub fn send(args: &[String]) -> anyhow::Result<String> {
+        let path = socket_path()?;
+        let mut stream = UnixStream::connect(&path)
+            .with_context(|| format!("VibeClip is not running ({})", path.display()))?;
+        writeln!(stream, "{}", serde_json::to_string(args)?)?;
+        let mut reply = String::new();
+        BufReader::new(stream).read_line(&mut reply)?;
+        let reply = reply.trim().to_string();
+        match reply.strip_prefix("error: ") {
+            Some(message) => anyhow::bail!("{message}"),
+            None => Ok(reply),
+        }
+    }
+}
@@ -0,0 +1,101 @@
+use serde_json::Value;
+
+/// Outcome of parsing a model reply for the Jsonify action.
+#[derive(Debug, Clone)]
+pub struct JsonCheck {
+    pub value: Option<Value>,
+    pub errors: Vec<String>,
+}
+
+impl JsonCheck {
+    pub fn is_valid(&self) -> bool {
+        self.value.is_some() && self.errors.is_empty()
+    }
+
+    /// Pretty-printed JSON when the reply parsed, regardless of schema errors.
+    pub fn normalized(&self) -> Option<String> {
+        self.value
+            .as_ref()
+            .and_then(|value| serde_json::to_string_pretty(value).ok())
+    }
+
+    /// Follow-up message asking the model to correct its previous reply.
+    pub fn retry_prompt(&self) -> String {
+        format!(
+            "Your previous reply was not acceptable:\n- {}\nReturn only the corrected JSON, without markdown fences or commentary.",
+            self.errors.join("\n- ")
+        )
+    }
+}
+
+/// Compiles `schema` up front so an invalid schema fails before any API call.
+pub fn ensure_valid_schema(schema: &Value) -> anyhow::Result<()> {
+    jsonschema::validator_for(schema)
+        .map(|_| ())
+        .map_err(|err| anyhow::anyhow!("invalid JSON schema: {err}"))
+}
+
+pub fn check(text: &str, schema: Option<&Value>) -> JsonCheck {
+    let value = match parse_lenient(text) {
+        Ok(value) => value,
+        Err(err) => {
+            return JsonCheck {
+                value: None,
+                errors: vec![format!("invalid JSON: {err}")],
+            }
+        }
+    };
+    let mut errors = Vec::new();
+    if let Some(schema) = schema {
+        match jsonschema::validator_for(schema) {
+            Ok(validator) => errors.extend(validator.iter_errors(&value).map(|err| {
+                let path = err.instance_path.to_string();
+                if path.is_empty() {
+                    err.to_string()
+                } else {
+                    format!("{path}: {err}")
+                }
+            })),
+            Err(err) => errors.push(format!("invalid JSON schema: {err}")),
+        }
+    }
+    JsonCheck {
+        value: Some(value),
+        errors,
+    }
+}
+
+/// Parses the reply after removing markdown fences. Falls back to the
+/// outermost object or array when the model wrapped JSON in prose.
+fn parse_lenient(text: &str) -> serde_json::Result<Value> {
+    let stripped = strip_code_fences(text);
+    let first_error = match serde_json::from_str(stripped) {
+        Ok(value) => return Ok(value),
+        Err(err) => err,
+    };
+    let start = stripped.find(['{', '[']);
+    let end = stripped.rfind(['}', ']']);
+    if let (Some(start), Some(end)) = (start, end) {
+        if start < end {
+            if let Ok(value) = serde_json::from_str(&stripped[start..=end]) {
+                return Ok(value);
+            }
+        }
+    }
+    Err(first_error)
+}
+
+pub fn strip_code_fences(text: &str) -> &str {
+    let trimmed = text.trim();
+    let Some(open) = trimmed.find("```") else {
+        return trimmed;
+    };
+    let after_open = &trimmed[open + 3..];
+    // Skip the info string (```json) up to the end of the fence line.
+    let body_start = after_open.find('\n').map(|idx| idx + 1).unwrap_or(0);
+    let body = &after_open[body_start..];
+    match body.find("```") {
+        Some(close) => body[..close].trim(),
+        None => body.trim(),
+    }
+}
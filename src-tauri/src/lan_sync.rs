@@ -0,0 +1,1231 @@
+//! Peer-to-peer sync of clip history between paired devices on the LAN.
+//!
+//! Devices find each other over mDNS (`_vibeclip-sync._tcp`). Each device has
+//! a long-term X25519 key in `sync_identity.key`. Pairing runs a CPace-style
+//! exchange over a short code shown on the other device, so a recorded or
+//! faked pairing attempt allows one guess at the code rather than an offline
+//! search; afterwards both sides remember each other's public key. Every
+//! session derives fresh ChaCha20-Poly1305 keys from the static
+//! Diffie-Hellman secret and per-session nonces.
+//!
+//! A session is driven by the initiator: it pulls the peer's changes since
+//! the last cursor in bounded batches, merges them, then pushes its own.
+
+use std::collections::{HashMap, HashSet};
+use std::io::{Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use anyhow::Context;
+use chacha20poly1305::aead::rand_core::RngCore;
+use chacha20poly1305::aead::{Aead, KeyInit, OsRng};
+use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce};
+use chrono::{DateTime, Utc};
+use curve25519_dalek::ristretto::{CompressedRistretto, RistrettoPoint};
+use curve25519_dalek::scalar::Scalar;
+use curve25519_dalek::traits::IsIdentity;
+use mdns_sd::{ServiceDaemon, ServiceEvent, ServiceInfo};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Emitter, Manager};
+use tracing::{info, warn};
+use x25519_dalek::{PublicKey, StaticSecret};
+
+use crate::db::DbState;
+use crate::hash::to_hex;
+use crate::runtime_config::LanSyncConfig;
+use crate::sync_store::{ClipVersion, MergeStats, SyncClip, SyncPeer};
+
+const SERVICE_TYPE: &str = "_vibeclip-sync._tcp.local.";
+const IDENTITY_FILE_NAME: &str = "sync_identity.key";
+const PROTOCOL_LABEL: &[u8] = b"vibeclip-sync-v1";
+const PAIRING_TTL: Duration = Duration::from_secs(5 * 60);
+const MAX_PAIRING_FAILURES: u32 = 5;
+/// Frames before the peer has authenticated.
+const MAX_HANDSHAKE_BYTES: usize = 64 * 1024;
+/// Clips per sync frame; a single larger clip still travels on its own.
+const SYNC_BATCH_BYTES: usize = 16 * 1024 * 1024;
+const MAX_FRAME_BYTES: usize = 64 * 1024 * 1024;
+const MAX_CONNECTIONS: usize = 8;
+const IO_TIMEOUT: Duration = Duration::from_secs(60);
+
+#[derive(Debug, Clone, Serialize)]
+pub struct DiscoveredPeer {
+    pub device_id: String,
+    pub name: String,
+    pub addresses: Vec<String>,
+    pub paired: bool,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct LanSyncStatus {
+    pub device_id: String,
+    pub device_name: String,
+    pub port: Option<u16>,
+    pub pairing_code: Option<String>,
+    pub pairing_expires_at: Option<DateTime<Utc>>,
+    pub peers: Vec<SyncPeer>,
+    pub discovered: Vec<DiscoveredPeer>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SyncReport {
+    pub device_id: String,
+    pub name: String,
+    /// What merging the peer's changes did locally.
+    pub received: MergeStats,
+    /// Number of local clips sent to the peer.
+    pub sent: usize,
+    /// What merging our changes did on the peer.
+    pub remote: MergeStats,
+}
+
+struct SyncIdentity {
+    device_id: String,
+    secret: StaticSecret,
+    public: PublicKey,
+}
+
+impl SyncIdentity {
+    fn load_or_create(data_dir: &Path) -> anyhow::Result<Self> {
+        let path = data_dir.join(IDENTITY_FILE_NAME);
+        let bytes = if path.exists() {
+            let bytes = std::fs::read(&path).context("failed to read sync identity")?;
+            <[u8; 32]>::try_from(bytes.as_slice())
+                .map_err(|_| anyhow::anyhow!("sync identity {} is corrupted", path.display()))?
+        } else {
+            let mut bytes = [0u8; 32];
+            OsRng.fill_bytes(&mut bytes);
+            let mut options = std::fs::OpenOptions::new();
+            options.write(true).create_new(true);
+            #[cfg(unix)]
+            {
+                use std::os::unix::fs::OpenOptionsExt;
+                options.mode(0o600);
+            }
+            let mut file = options
+                .open(&path)
+                .context("failed to create sync identity")?;
+            file.write_all(&bytes)?;
+            file.sync_all()?;
+            bytes
+        };
+        let secret = StaticSecret::from(bytes);
+        let public = PublicKey::from(&secret);
+        Ok(Self {
+            device_id: device_id_for(public.as_bytes()),
+            secret,
+            public,
+        })
+    }
+}
+
+/// This device's sync id, creating the identity on first use.
+pub(crate) fn local_device_id(data_dir: &Path) -> anyhow::Result<String> {
+    Ok(SyncIdentity::load_or_create(data_dir)?.device_id)
+}
+
+fn device_id_for(public_key: &[u8; 32]) -> String {
+    blake3::hash(public_key).to_hex()[..16].to_string()
+}
+
+struct PairingWindow {
+    code: String,
+    expires_at: Instant,
+    expires_at_utc: DateTime<Utc>,
+    failures: u32,
+}
+
+struct ServerHandle {
+    port: u16,
+    stop: Arc<AtomicBool>,
+    mdns: Option<ServiceDaemon>,
+}
+
+pub struct LanSync {
+    db: DbState,
+    identity: SyncIdentity,
+    device_name: String,
+    pairing: Mutex<Option<PairingWindow>>,
+    discovered: Arc<Mutex<HashMap<String, DiscoveredPeer>>>,
+    server: Mutex<Option<ServerHandle>>,
+    on_change: Box<dyn Fn(&MergeStats) + Send + Sync>,
+}
+
+impl LanSync {
+    /// `on_change` runs after peers' clips were merged into the local database.
+    pub fn new(
+        db: DbState,
+        device_name: Option<String>,
+        on_change: impl Fn(&MergeStats) + Send + Sync + 'static,
+    ) -> anyhow::Result<Arc<Self>> {
+        let identity = SyncIdentity::load_or_create(db.data_dir())?;
+        let device_name = device_name
+            .filter(|name| !name.trim().is_empty())
+            .or_else(|| std::env::var("HOSTNAME").ok())
+            .or_else(|| std::env::var("COMPUTERNAME").ok())
+            .unwrap_or_else(|| format!("VibeClip {}", &identity.device_id[..6]));
+        Ok(Arc::new(Self {
+            db,
+            identity,
+            device_name,
+            pairing: Mutex::new(None),
+            discovered: Arc::new(Mutex::new(HashMap::new())),
+            server: Mutex::new(None),
+            on_change: Box::new(on_change),
+        }))
+    }
+
+    pub fn device_id(&self) -> &str {
+        &self.identity.device_id
+    }
+
+    pub fn port(&self) -> Option<u16> {
+        self.server
+            .lock()
+            .ok()
+            .and_then(|server| server.as_ref().map(|handle| handle.port))
+    }
+
+    /// Accepts sync connections on `port` (`0` picks a free one) and, with
+    /// `advertise`, announces the device and browses for others over mDNS.
+    pub fn start(self: &Arc<Self>, port: u16, advertise: bool) -> anyhow::Result<u16> {
+        let mut server = self
+            .server
+            .lock()
+            .map_err(|_| anyhow::anyhow!("sync server lock poisoned"))?;
+        if let Some(handle) = server.as_ref() {
+            return Ok(handle.port);
+        }
+        let listener = TcpListener::bind(("0.0.0.0", port))
+            .with_context(|| format!("无法监听同步端口 {port}"))?;
+        let port = listener.local_addr()?.port();
+        let stop = Arc::new(AtomicBool::new(false));
+
+        let engine = self.clone();
+        let stop_flag = stop.clone();
+        let active = Arc::new(AtomicUsize::new(0));
+        std::thread::Builder::new()
+            .name("lan-sync".into())
+            .spawn(move || {
+                for stream in listener.incoming() {
+                    if stop_flag.load(Ordering::SeqCst) {
+                        break;
+                    }
+                    let Ok(stream) = stream else {
+                        continue;
+                    };
+                    let Some(slot) = ConnectionSlot::acquire(&active, MAX_CONNECTIONS) else {
+                        warn!(
+                            "lan sync busy, dropping connection from {:?}",
+                            stream.peer_addr().ok()
+                        );
+                        continue;
+                    };
+                    let engine = engine.clone();
+                    std::thread::spawn(move || {
+                        let _slot = slot;
+                        let peer = stream.peer_addr().ok();
+                        if let Err(err) = engine.serve(stream) {
+                            warn!("lan sync session from {peer:?} failed: {err:#}");
+                        }
+                    });
+                }
+            })?;
+
+        let mdns = if advertise {
+            match self.start_mdns(port) {
+                Ok(daemon) => Some(daemon),
+                Err(err) => {
+                    warn!("mdns unavailable, peers must be added by address: {err:#}");
+                    None
+                }
+            }
+        } else {
+            None
+        };
+        info!("lan sync listening on port {port}");
+        *server = Some(ServerHandle { port, stop, mdns });
+        Ok(port)
+    }
+
+    pub fn stop(&self) {
+        let Ok(mut server) = self.server.lock() else {
+            return;
+        };
+        if let Some(handle) = server.take() {
+            handle.stop.store(true, Ordering::SeqCst);
+            // Wake the accept loop so it notices the flag.
+            let _ = TcpStream::connect(("127.0.0.1", handle.port));
+            if let Some(mdns) = handle.mdns {
+                let _ = mdns.shutdown();
+            }
+            if let Ok(mut discovered) = self.discovered.lock() {
+                discovered.clear();
+            }
+            info!("lan sync stopped");
+        }
+    }
+
+    fn start_mdns(&self, port: u16) -> anyhow::Result<ServiceDaemon> {
+        let daemon = ServiceDaemon::new()?;
+        let device_id = self.identity.device_id.clone();
+        let host = format!("{device_id}.local.");
+        let properties = [
+            ("id", device_id.as_str()),
+            ("name", self.device_name.as_str()),
+        ];
+        let service = ServiceInfo::new(SERVICE_TYPE, &device_id, &host, "", port, &properties[..])?
+            .enable_addr_auto();
+        daemon.register(service)?;
+
+        let receiver = daemon.browse(SERVICE_TYPE)?;
+        let discovered = self.discovered.clone();
+        std::thread::Builder::new()
+            .name("lan-sync-mdns".into())
+            .spawn(move || {
+                while let Ok(event) = receiver.recv() {
+                    let Ok(mut peers) = discovered.lock() else {
+                        break;
+                    };
+                    match event {
+                        ServiceEvent::ServiceResolved(service) => {
+                            let Some(id) = service.get_property_val_str("id") else {
+                                continue;
+                            };
+                            if id == device_id {
+                                continue;
+                            }
+                            let mut addresses: Vec<String> = service
+                                .get_addresses()
+                                .iter()
+                                .map(|ip| SocketAddr::new(*ip, service.get_port()).to_string())
+                                .collect();
+                            addresses.sort();
+                            peers.insert(
+                                id.to_string(),
+                                DiscoveredPeer {
+                                    device_id: id.to_string(),
+                                    name: service
+                                        .get_property_val_str("name")
+                                        .unwrap_or(id)
+                                        .to_string(),
+                                    addresses,
+                                    paired: false,
+                                },
+                            );
+                        }
+                        ServiceEvent::ServiceRemoved(_, fullname) => {
+                            peers.retain(|id, _| !fullname.starts_with(id.as_str()));
+                        }
+                        _ => {}
+                    }
+                }
+            })?;
+        Ok(daemon)
+    }
+
+    /// Opens a pairing window and returns the code to type on the other device.
+    pub fn begin_pairing(&self) -> anyhow::Result<String> {
+        let code = format!("{:06}", OsRng.next_u32() % 1_000_000);
+        let mut pairing = self
+            .pairing
+            .lock()
+            .map_err(|_| anyhow::anyhow!("pairing lock poisoned"))?;
+        *pairing = Some(PairingWindow {
+            code: code.clone(),
+            expires_at: Instant::now() + PAIRING_TTL,
+            expires_at_utc: Utc::now()
+                + chrono::Duration::from_std(PAIRING_TTL).unwrap_or_default(),
+            failures: 0,
+        });
+        Ok(code)
+    }
+
+    pub fn status(&self) -> anyhow::Result<LanSyncStatus> {
+        let peers = self.db.list_sync_peers()?;
+        let (pairing_code, pairing_expires_at) = match self.pairing.lock() {
+            Ok(pairing) => match pairing.as_ref() {
+                Some(window) if window.expires_at > Instant::now() => {
+                    (Some(window.code.clone()), Some(window.expires_at_utc))
+                }
+                _ => (None, None),
+            },
+            Err(_) => (None, None),
+        };
+        let mut discovered: Vec<DiscoveredPeer> = self
+            .discovered
+            .lock()
+            .map(|peers| peers.values().cloned().collect())
+            .unwrap_or_default();
+        for peer in &mut discovered {
+            peer.paired = peers.iter().any(|known| known.device_id == peer.device_id);
+        }
+        discovered.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(LanSyncStatus {
+            device_id: self.identity.device_id.clone(),
+            device_name: self.device_name.clone(),
+            port: self.port(),
+            pairing_code,
+            pairing_expires_at,
+            peers,
+            discovered,
+        })
+    }
+
+    /// Pairs with the device listening on `address` using the code it shows.
+    pub fn pair(&self, address: &str, code: &str) -> anyhow::Result<SyncPeer> {
+        let (stream, address) = connect(address)?;
+        let session = self.initiate(stream, Some(code.trim()), None)?;
+        let mut channel = session.channel;
+        channel.send(&SyncMessage::Bye)?;
+        let peer = self.db.save_sync_peer(
+            &session.device_id,
+            &session.name,
+            &session.public_key,
+            Some(&address),
+        )?;
+        info!("paired with {} ({})", peer.name, peer.device_id);
+        Ok(peer)
+    }
+
+    /// Syncs with a paired device, trying its discovered addresses first.
+    pub fn sync_peer(&self, device_id: &str) -> anyhow::Result<SyncReport> {
+        let peer = self
+            .db
+            .get_sync_peer(device_id)?
+            .with_context(|| format!("设备 {device_id} 尚未配对"))?;
+        let mut candidates: Vec<String> = self
+            .discovered
+            .lock()
+            .ok()
+            .and_then(|peers| peers.get(device_id).map(|peer| peer.addresses.clone()))
+            .unwrap_or_default();
+        if let Some(address) = &peer.last_address {
+            candidates.push(address.clone());
+        }
+        let mut last_error = anyhow::anyhow!("设备 {} 没有可用地址", peer.name);
+        for candidate in candidates {
+            match connect(&candidate) {
+                Ok((stream, address)) => return self.sync_over(stream, &address, Some(&peer)),
+                Err(err) => last_error = err,
+            }
+        }
+        Err(last_error)
+    }
+
+    /// Syncs with whichever paired device listens on `address`.
+    pub fn sync_address(&self, address: &str) -> anyhow::Result<SyncReport> {
+        let (stream, address) = connect(address)?;
+        self.sync_over(stream, &address, None)
+    }
+
+    /// Syncs with every paired device, logging the ones that are unreachable.
+    pub fn sync_all(&self) -> anyhow::Result<Vec<SyncReport>> {
+        let mut reports = Vec::new();
+        for peer in self.db.list_sync_peers()? {
+            match self.sync_peer(&peer.device_id) {
+                Ok(report) => reports.push(report),
+                Err(err) => warn!("lan sync with {} failed: {err:#}", peer.name),
+            }
+        }
+        Ok(reports)
+    }
+
+    fn sync_over(
+        &self,
+        stream: TcpStream,
+        address: &str,
+        expected: Option<&SyncPeer>,
+    ) -> anyhow::Result<SyncReport> {
+        let session = self.initiate(stream, None, expected)?;
+        let peer = self
+            .db
+            .get_sync_peer(&session.device_id)?
+            .context("peer is not paired")?;
+        let mut channel = session.channel;
+
+        let mut received = MergeStats::default();
+        // Clips we just took over unchanged don't need to travel back.
+        let mut echoes: HashSet<ClipVersion> = HashSet::new();
+        let mut pulled_cursor = peer.pulled_cursor;
+        loop {
+            channel.send(&SyncMessage::Pull {
+                since: pulled_cursor,
+            })?;
+            let (clips, cursor, more) = match channel.recv()? {
+                SyncMessage::Changes {
+                    clips,
+                    cursor,
+                    more,
+                } => (clips, cursor, more),
+                other => anyhow::bail!("unexpected sync message {other:?}"),
+            };
+            echoes.extend(clips.iter().map(SyncClip::version));
+            let stats = self.db.apply_sync_clips(clips, peer.pushed_cursor)?;
+            received.inserted += stats.inserted;
+            received.updated += stats.updated;
+            received.conflicts += stats.conflicts;
+            let advanced = cursor > pulled_cursor;
+            pulled_cursor = pulled_cursor.max(cursor);
+            if !more || !advanced {
+                break;
+            }
+        }
+        if received.changed() {
+            (self.on_change)(&received);
+        }
+
+        let mut sent = 0;
+        let mut remote = MergeStats::default();
+        let mut pushed_cursor = peer.pushed_cursor;
+        loop {
+            let (mut outgoing, cursor, more) = self
+                .db
+                .sync_changes_batch(pushed_cursor, SYNC_BATCH_BYTES)?;
+            outgoing.retain(|clip| !echoes.contains(&clip.version()));
+            sent += outgoing.len();
+            channel.send(&SyncMessage::Push {
+                clips: outgoing,
+                cursor,
+            })?;
+            let stats = match channel.recv()? {
+                SyncMessage::Applied { stats } => stats,
+                other => anyhow::bail!("unexpected sync message {other:?}"),
+            };
+            remote.inserted += stats.inserted;
+            remote.updated += stats.updated;
+            remote.conflicts += stats.conflicts;
+            pushed_cursor = cursor;
+            if !more {
+                break;
+            }
+        }
+        channel.send(&SyncMessage::Bye)?;
+
+        self.db.record_sync_progress(
+            &peer.device_id,
+            Some(address),
+            Some(pulled_cursor),
+            Some(pushed_cursor),
+        )?;
+        info!(
+            "synced with {}: received {:?}, sent {}, remote {:?}",
+            peer.name, received, sent, remote
+        );
+        Ok(SyncReport {
+            device_id: peer.device_id,
+            name: peer.name,
+            received,
+            sent,
+            remote,
+        })
+    }
+
+    /// Handles one incoming connection: pairing or a sync session.
+    fn serve(&self, stream: TcpStream) -> anyhow::Result<()> {
+        let remote_ip = stream.peer_addr()?.ip();
+        let (session, listen_port) = self.respond(stream)?;
+        let mut channel = session.channel;
+
+        if session.pairing {
+            let address = listen_port.map(|port| SocketAddr::new(remote_ip, port).to_string());
+            let peer = self.db.save_sync_peer(
+                &session.device_id,
+                &session.name,
+                &session.public_key,
+                address.as_deref(),
+            )?;
+            info!("paired with {} ({})", peer.name, peer.device_id);
+            let _ = channel.recv::<SyncMessage>();
+            return Ok(());
+        }
+
+        let peer = self
+            .db
+            .get_sync_peer(&session.device_id)?
+            .context("peer is not paired")?;
+        let address = listen_port.map(|port| SocketAddr::new(remote_ip, port).to_string());
+        loop {
+            match channel.recv()? {
+                SyncMessage::Pull { since } => {
+                    let (clips, cursor, more) =
+                        self.db.sync_changes_batch(since, SYNC_BATCH_BYTES)?;
+                    channel.send(&SyncMessage::Changes {
+                        clips,
+                        cursor,
+                        more,
+                    })?;
+                    self.db.record_sync_progress(
+                        &peer.device_id,
+                        address.as_deref(),
+                        None,
+                        Some(cursor),
+                    )?;
+                }
+                SyncMessage::Push { clips, cursor } => {
+                    let stats = self.db.apply_sync_clips(clips, peer.pushed_cursor)?;
+                    if stats.changed() {
+                        (self.on_change)(&stats);
+                    }
+                    channel.send(&SyncMessage::Applied { stats })?;
+                    self.db.record_sync_progress(
+                        &peer.device_id,
+                        address.as_deref(),
+                        Some(cursor),
+                        None,
+                    )?;
+                }
+                SyncMessage::Bye => return Ok(()),
+                other => anyhow::bail!("unexpected sync message {other:?}"),
+            }
+        }
+    }
+
+    fn initiate(
+        &self,
+        mut stream: TcpStream,
+        pairing_code: Option<&str>,
+        expected: Option<&SyncPeer>,
+    ) -> anyhow::Result<Session> {
+        let nonce = random_nonce();
+        write_json(
+            &mut stream,
+            &Handshake::Hello {
+                device_id: self.identity.device_id.clone(),
+                name: self.device_name.clone(),
+                public_key: to_hex(self.identity.public.as_bytes()),
+                nonce: to_hex(&nonce),
+                pairing: pairing_code.is_some(),
+                listen_port: self.port(),
+            },
+        )?;
+        let (device_id, name, public_key, remote_nonce, remote_share) =
+            match read_json(&mut stream)? {
+                Handshake::Welcome {
+                    device_id,
+                    name,
+                    public_key,
+                    nonce,
+                    share,
+                } => (
+                    device_id,
+                    name,
+                    parse_key(&public_key)?,
+                    parse_key(&nonce)?,
+                    share.as_deref().map(parse_key).transpose()?,
+                ),
+                Handshake::Rejected { reason } => anyhow::bail!("对方拒绝连接：{reason}"),
+                _ => anyhow::bail!("unexpected handshake message"),
+            };
+        if device_id != device_id_for(&public_key) {
+            anyhow::bail!("peer device id does not match its key");
+        }
+        if let Some(expected) = expected {
+            if expected.device_id != device_id {
+                anyhow::bail!("address now belongs to another device ({device_id})");
+            }
+        }
+        if pairing_code.is_none() {
+            match self.db.get_sync_peer(&device_id)? {
+                Some(peer) if peer.public_key == public_key => {}
+                Some(_) => anyhow::bail!("设备 {name} 的密钥已变化，请重新配对"),
+                None => anyhow::bail!("设备 {name} 尚未配对"),
+            }
+        }
+
+        let transcript = transcript(
+            self.identity.public.as_bytes(),
+            &public_key,
+            &nonce,
+            &remote_nonce,
+            pairing_code.is_some(),
+        );
+        let (share, pairing_key) = match pairing_code {
+            Some(code) => {
+                let remote_share =
+                    remote_share.context("peer did not answer the pairing exchange")?;
+                let pake = PakeShare::new(code, &transcript);
+                let key = pake.finish(&remote_share, true)?;
+                (Some(to_hex(&pake.share)), Some(key))
+            }
+            None => (None, None),
+        };
+        let shared = self
+            .identity
+            .secret
+            .diffie_hellman(&PublicKey::from(public_key));
+        let keys = SessionKeys::derive(shared.as_bytes(), &transcript, pairing_key.as_ref(), true);
+        write_json(
+            &mut stream,
+            &Handshake::Proof {
+                mac: keys.proof(b"initiator", &transcript).to_hex().to_string(),
+                share,
+            },
+        )?;
+        match read_json(&mut stream)? {
+            Handshake::Proof { mac, .. } => {
+                let expected = keys.proof(b"responder", &transcript);
+                if blake3::Hash::from_hex(mac.as_bytes()).ok() != Some(expected) {
+                    anyhow::bail!("peer failed to authenticate");
+                }
+            }
+            Handshake::Rejected { reason } => anyhow::bail!("对方拒绝连接：{reason}"),
+            _ => anyhow::bail!("unexpected handshake message"),
+        }
+        Ok(Session {
+            device_id,
+            name,
+            public_key,
+            pairing: pairing_code.is_some(),
+            channel: SecureChannel::new(stream, keys),
+        })
+    }
+
+    fn respond(&self, mut stream: TcpStream) -> anyhow::Result<(Session, Option<u16>)> {
+        stream.set_read_timeout(Some(IO_TIMEOUT))?;
+        stream.set_write_timeout(Some(IO_TIMEOUT))?;
+        let (device_id, name, public_key, remote_nonce, pairing, listen_port) =
+            match read_json(&mut stream)? {
+                Handshake::Hello {
+                    device_id,
+                    name,
+                    public_key,
+                    nonce,
+                    pairing,
+                    listen_port,
+                } => (
+                    device_id,
+                    name,
+                    parse_key(&public_key)?,
+                    parse_key(&nonce)?,
+                    pairing,
+                    listen_port,
+                ),
+                _ => anyhow::bail!("unexpected handshake message"),
+            };
+        let reject = |stream: &mut TcpStream, reason: &str| -> anyhow::Error {
+            let _ = write_json(
+                stream,
+                &Handshake::Rejected {
+                    reason: reason.to_string(),
+                },
+            );
+            anyhow::anyhow!("rejected {device_id}: {reason}")
+        };
+        if device_id != device_id_for(&public_key) {
+            return Err(reject(&mut stream, "device id does not match key"));
+        }
+
+        let pairing_code = if pairing {
+            match self.active_pairing_code() {
+                Some(code) => Some(code),
+                None => return Err(reject(&mut stream, "本机未开启配对")),
+            }
+        } else {
+            match self.db.get_sync_peer(&device_id)? {
+                Some(peer) if peer.public_key == public_key => None,
+                Some(_) => return Err(reject(&mut stream, "密钥已变化，请重新配对")),
+                None => return Err(reject(&mut stream, "设备尚未配对")),
+            }
+        };
+
+        let nonce = random_nonce();
+        let transcript = transcript(
+            &public_key,
+            self.identity.public.as_bytes(),
+            &remote_nonce,
+            &nonce,
+            pairing,
+        );
+        let pake = pairing_code
+            .as_deref()
+            .map(|code| PakeShare::new(code, &transcript));
+        write_json(
+            &mut stream,
+            &Handshake::Welcome {
+                device_id: self.identity.device_id.clone(),
+                name: self.device_name.clone(),
+                public_key: to_hex(self.identity.public.as_bytes()),
+                nonce: to_hex(&nonce),
+                share: pake.as_ref().map(|pake| to_hex(&pake.share)),
+            },
+        )?;
+        let shared = self
+            .identity
+            .secret
+            .diffie_hellman(&PublicKey::from(public_key));
+        let keys = match read_json(&mut stream)? {
+            Handshake::Proof { mac, share } => {
+                let pairing_key = match (&pake, share) {
+                    (Some(pake), Some(share)) => parse_key(&share)
+                        .and_then(|share| pake.finish(&share, false))
+                        .ok(),
+                    _ => None,
+                };
+                if pake.is_some() && pairing_key.is_none() {
+                    None
+                } else {
+                    let keys = SessionKeys::derive(
+                        shared.as_bytes(),
+                        &transcript,
+                        pairing_key.as_ref(),
+                        false,
+                    );
+                    let valid = blake3::Hash::from_hex(mac.as_bytes()).ok()
+                        == Some(keys.proof(b"initiator", &transcript));
+                    valid.then_some(keys)
+                }
+            }
+            _ => None,
+        };
+        let Some(keys) = keys else {
+            if pairing {
+                self.record_pairing_failure();
+                return Err(reject(&mut stream, "配对码错误"));
+            }
+            return Err(reject(&mut stream, "authentication failed"));
+        };
+        write_json(
+            &mut stream,
+            &Handshake::Proof {
+                mac: keys.proof(b"responder", &transcript).to_hex().to_string(),
+                share: None,
+            },
+        )?;
+        if pairing {
+            // A code pairs exactly one device.
+            if let Ok(mut window) = self.pairing.lock() {
+                *window = None;
+            }
+        }
+        Ok((
+            Session {
+                device_id,
+                name,
+                public_key,
+                pairing,
+                channel: SecureChannel::new(stream, keys),
+            },
+            listen_port,
+        ))
+    }
+
+    fn active_pairing_code(&self) -> Option<String> {
+        let pairing = self.pairing.lock().ok()?;
+        pairing
+            .as_ref()
+            .filter(|window| window.expires_at > Instant::now())
+            .map(|window| window.code.clone())
+    }
+
+    fn record_pairing_failure(&self) {
+        if let Ok(mut pairing) = self.pairing.lock() {
+            let exhausted = match pairing.as_mut() {
+                Some(window) => {
+                    window.failures += 1;
+                    window.failures >= MAX_PAIRING_FAILURES
+                }
+                None => false,
+            };
+            if exhausted {
+                // Stop online guessing; the user has to start pairing again.
+                *pairing = None;
+            }
+        }
+    }
+}
+
+struct Session {
+    device_id: String,
+    name: String,
+    public_key: [u8; 32],
+    pairing: bool,
+    channel: SecureChannel,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "camelCase")]
+enum Handshake {
+    #[serde(rename_all = "camelCase")]
+    Hello {
+        device_id: String,
+        name: String,
+        public_key: String,
+        nonce: String,
+        pairing: bool,
+        listen_port: Option<u16>,
+    },
+    #[serde(rename_all = "camelCase")]
+    Welcome {
+        device_id: String,
+        name: String,
+        public_key: String,
+        nonce: String,
+        /// The responder's pairing share, only when pairing.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        share: Option<String>,
+    },
+    Proof {
+        mac: String,
+        /// The initiator's pairing share, only when pairing.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        share: Option<String>,
+    },
+    Rejected {
+        reason: String,
+    },
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "camelCase")]
+enum SyncMessage {
+    Pull {
+        since: i64,
+    },
+    Changes {
+        clips: Vec<SyncClip>,
+        cursor: i64,
+        /// Another batch follows from `cursor`.
+        #[serde(default)]
+        more: bool,
+    },
+    Push {
+        clips: Vec<SyncClip>,
+        cursor: i64,
+    },
+    Applied {
+        stats: MergeStats,
+    },
+    Bye,
+}
+
+struct SessionKeys {
+    mac: [u8; 32],
+    send: ChaCha20Poly1305,
+    recv: ChaCha20Poly1305,
+}
+
+impl SessionKeys {
+    /// Pairing mixes in the key of the code exchange so only a peer that
+    /// knew the code can produce a valid proof.
+    fn derive(
+        shared: &[u8; 32],
+        transcript: &[u8],
+        pairing_key: Option<&[u8; 32]>,
+        initiator: bool,
+    ) -> Self {
+        let mut material = Vec::with_capacity(64 + transcript.len());
+        material.extend_from_slice(shared);
+        material.extend_from_slice(transcript);
+        let context = match pairing_key {
+            Some(key) => {
+                material.extend_from_slice(key);
+                "VibeClip Pro LAN sync v2 pairing"
+            }
+            None => "VibeClip Pro LAN sync v1 session",
+        };
+        let root = blake3::derive_key(context, &material);
+        let mac = blake3::derive_key("VibeClip Pro LAN sync v1 proof", &root);
+        let forward = blake3::derive_key("VibeClip Pro LAN sync v1 initiator->responder", &root);
+        let backward = blake3::derive_key("VibeClip Pro LAN sync v1 responder->initiator", &root);
+        let (send, recv) = if initiator {
+            (forward, backward)
+        } else {
+            (backward, forward)
+        };
+        Self {
+            mac,
+            send: ChaCha20Poly1305::new(Key::from_slice(&send)),
+            recv: ChaCha20Poly1305::new(Key::from_slice(&recv)),
+        }
+    }
+
+    fn proof(&self, role: &[u8], transcript: &[u8]) -> blake3::Hash {
+        let mut hasher = blake3::Hasher::new_keyed(&self.mac);
+        hasher.update(role);
+        hasher.update(transcript);
+        hasher.finalize()
+    }
+}
+
+/// One side of a CPace exchange: the generator is derived from the pairing
+/// code and the session transcript, so a share only verifies against the same
+/// code and each connection tests a single guess.
+struct PakeShare {
+    secret: Scalar,
+    share: [u8; 32],
+}
+
+impl PakeShare {
+    fn new(code: &str, transcript: &[u8]) -> Self {
+        let mut hasher = blake3::Hasher::new_derive_key("VibeClip Pro LAN sync v2 pake generator");
+        hasher.update(transcript);
+        hasher.update(code.as_bytes());
+        let mut uniform = [0u8; 64];
+        hasher.finalize_xof().fill(&mut uniform);
+        let generator = RistrettoPoint::from_uniform_bytes(&uniform);
+        let mut wide = [0u8; 64];
+        OsRng.fill_bytes(&mut wide);
+        let secret = Scalar::from_bytes_mod_order_wide(&wide);
+        Self {
+            secret,
+            share: (generator * secret).compress().to_bytes(),
+        }
+    }
+
+    /// The shared key, bound to both shares in initiator, responder order.
+    fn finish(&self, remote: &[u8; 32], initiator: bool) -> anyhow::Result<[u8; 32]> {
+        let point = CompressedRistretto(*remote)
+            .decompress()
+            .context("invalid pairing share")?;
+        let shared = point * self.secret;
+        if shared.is_identity() {
+            anyhow::bail!("invalid pairing share");
+        }
+        let (first, second) = if initiator {
+            (&self.share, remote)
+        } else {
+            (remote, &self.share)
+        };
+        let mut material = Vec::with_capacity(96);
+        material.extend_from_slice(shared.compress().as_bytes());
+        material.extend_from_slice(first);
+        material.extend_from_slice(second);
+        Ok(blake3::derive_key(
+            "VibeClip Pro LAN sync v2 pake key",
+            &material,
+        ))
+    }
+}
+
+/// Length-prefixed frames encrypted with per-direction keys and counter nonces.
+struct SecureChannel {
+    stream: TcpStream,
+    keys: SessionKeys,
+    sent: u64,
+    received: u64,
+}
+
+impl SecureChannel {
+    fn new(stream: TcpStream, keys: SessionKeys) -> Self {
+        let _ = stream.set_read_timeout(Some(IO_TIMEOUT));
+        let _ = stream.set_write_timeout(Some(IO_TIMEOUT));
+        Self {
+            stream,
+            keys,
+            sent: 0,
+            received: 0,
+        }
+    }
+
+    fn send<T: Serialize>(&mut self, message: &T) -> anyhow::Result<()> {
+        let plaintext = serde_json::to_vec(message)?;
+        let nonce = counter_nonce(self.sent);
+        self.sent += 1;
+        let ciphertext = self
+            .keys
+            .send
+            .encrypt(Nonce::from_slice(&nonce), plaintext.as_slice())
+            .map_err(|_| anyhow::anyhow!("failed to encrypt sync frame"))?;
+        write_frame(&mut self.stream, &ciphertext)
+    }
+
+    fn recv<T: DeserializeOwned>(&mut self) -> anyhow::Result<T> {
+        let ciphertext = read_frame(&mut self.stream, MAX_FRAME_BYTES)?;
+        let nonce = counter_nonce(self.received);
+        self.received += 1;
+        let plaintext = self
+            .keys
+            .recv
+            .decrypt(Nonce::from_slice(&nonce), ciphertext.as_slice())
+            .map_err(|_| anyhow::anyhow!("sync frame failed authentication"))?;
+        Ok(serde_json::from_slice(&plaintext)?)
+    }
+}
+
+fn counter_nonce(counter: u64) -> [u8; 12] {
+    let mut nonce = [0u8; 12];
+    nonce[4..].copy_from_slice(&counter.to_be_bytes());
+    nonce
+}
+
+fn transcript(
+    initiator_key: &[u8; 32],
+    responder_key: &[u8; 32],
+    initiator_nonce: &[u8; 32],
+    responder_nonce: &[u8; 32],
+    pairing: bool,
+) -> Vec<u8> {
+    let mut transcript = Vec::with_capacity(PROTOCOL_LABEL.len() + 129);
+    transcript.extend_from_slice(PROTOCOL_LABEL);
+    transcript.extend_from_slice(initiator_key);
+    transcript.extend_from_slice(responder_key);
+    transcript.extend_from_slice(initiator_nonce);
+    transcript.extend_from_slice(responder_nonce);
+    transcript.push(pairing as u8);
+    transcript
+}
+
+fn connect(address: &str) -> anyhow::Result<(TcpStream, String)> {
+    let target = address
+        .to_socket_addrs()
+        .with_context(|| format!("invalid address {address}"))?
+        .next()
+        .with_context(|| format!("cannot resolve {address}"))?;
+    let stream = TcpStream::connect_timeout(&target, Duration::from_secs(5))
+        .with_context(|| format!("无法连接 {target}"))?;
+    stream.set_read_timeout(Some(IO_TIMEOUT))?;
+    stream.set_write_timeout(Some(IO_TIMEOUT))?;
+    Ok((stream, target.to_string()))
+}
+
+fn random_nonce() -> [u8; 32] {
+    let mut nonce = [0u8; 32];
+    OsRng.fill_bytes(&mut nonce);
+    nonce
+}
+
+fn parse_key(value: &str) -> anyhow::Result<[u8; 32]> {
+    if value.len() != 64 || !value.is_ascii() {
+        anyhow::bail!("invalid key encoding");
+    }
+    let mut key = [0u8; 32];
+    for (index, byte) in key.iter_mut().enumerate() {
+        *byte = u8::from_str_radix(&value[index * 2..index * 2 + 2], 16)
+            .context("invalid key encoding")?;
+    }
+    Ok(key)
+}
+
+fn write_frame(stream: &mut TcpStream, payload: &[u8]) -> anyhow::Result<()> {
+    let length = u32::try_from(payload.len()).context("sync frame too large")?;
+    stream.write_all(&length.to_be_bytes())?;
+    stream.write_all(payload)?;
+    stream.flush()?;
+    Ok(())
+}
+
+/// Reads one frame of at most `limit` bytes. The buffer grows with the data
+/// that actually arrives, so a bogus length cannot reserve memory up front.
+fn read_frame(stream: &mut TcpStream, limit: usize) -> anyhow::Result<Vec<u8>> {
+    let mut length = [0u8; 4];
+    stream.read_exact(&mut length)?;
+    let length = u32::from_be_bytes(length) as usize;
+    if length > limit {
+        anyhow::bail!("sync frame of {length} bytes exceeds the limit");
+    }
+    let mut payload = Vec::with_capacity(length.min(MAX_HANDSHAKE_BYTES));
+    stream.take(length as u64).read_to_end(&mut payload)?;
+    if payload.len() != length {
+        anyhow::bail!("sync frame truncated");
+    }
+    Ok(payload)
+}
+
+fn write_json<T: Serialize>(stream: &mut TcpStream, message: &T) -> anyhow::Result<()> {
+    write_frame(stream, &serde_json::to_vec(message)?)
+}
+
+fn read_json<T: DeserializeOwned>(stream: &mut TcpStream) -> anyhow::Result<T> {
+    let frame = read_frame(stream, MAX_HANDSHAKE_BYTES)?;
+    Ok(serde_json::from_slice(&frame)?)
+}
+
+/// A place among the sessions served at once (`MAX_CONNECTIONS` for sync),
+/// released on drop.
+pub(crate) struct ConnectionSlot(Arc<AtomicUsize>);
+
+impl ConnectionSlot {
+    pub(crate) fn acquire(active: &Arc<AtomicUsize>, limit: usize) -> Option<Self> {
+        active
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |count| {
+                (count < limit).then_some(count + 1)
+            })
+            .ok()
+            .map(|_| Self(active.clone()))
+    }
+}
+
+impl Drop for ConnectionSlot {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+/// The desktop app's sync engine, replaced whenever its settings change.
+#[derive(Default)]
+pub struct LanSyncState {
+    running: Mutex<Option<(LanSyncConfig, Arc<LanSync>)>>,
+}
+
+impl LanSyncState {
+    pub fn engine(&self) -> Option<Arc<LanSync>> {
+        self.running
+            .lock()
+            .ok()
+            .and_then(|running| running.as_ref().map(|(_, engine)| engine.clone()))
+    }
+}
+
+/// Starts, restarts or stops sync to match `config`.
+pub fn apply(app: &AppHandle, config: &LanSyncConfig) -> anyhow::Result<()> {
+    let state = app.state::<LanSyncState>();
+    let mut running = state
+        .running
+        .lock()
+        .map_err(|_| anyhow::anyhow!("lan sync lock poisoned"))?;
+    if let Some((current, _)) = running.as_ref() {
+        if config.enabled
+            && current.port == config.port
+            && current.device_name == config.device_name
+            && current.interval_minutes == config.interval_minutes
+        {
+            return Ok(());
+        }
+    }
+    if let Some((_, previous)) = running.take() {
+        previous.stop();
+    }
+    if !config.enabled {
+        return Ok(());
+    }
+
+    let handle = app.clone();
+    let engine = LanSync::new(
+        app.state::<DbState>().clone_for_thread(),
+        config.device_name.clone(),
+        move |stats| {
+            let _ = handle.emit("sync://merged", stats);
+        },
+    )?;
+    engine.start(config.port, true)?;
+    if config.interval_minutes > 0 {
+        spawn_auto_sync(
+            engine.clone(),
+            Duration::from_secs(u64::from(config.interval_minutes) * 60),
+        );
+    }
+    *running = Some((config.clone(), engine));
+    Ok(())
+}
+
+/// Syncs with all paired devices every `interval` until the engine stops.
+fn spawn_auto_sync(engine: Arc<LanSync>, interval: Duration) {
+    std::thread::spawn(move || {
+        let mut next = Instant::now() + interval;
+        while engine.port().is_some() {
+            std::thread::sleep(Duration::from_secs(1));
+            if Instant::now() < next {
+                continue;
+            }
+            next = Instant::now() + interval;
+            if let Err(err) = engine.sync_all() {
+                warn!("automatic lan sync failed: {err:#}");
+            }
+        }
+    });
+}
@@ -0,0 +1,257 @@
+//! Offline language detection for text clips.
+//!
+//! Non-Latin scripts are identified from Unicode ranges alone. Latin text is
+//! scored against small trigram profiles of the most common European
+//! languages, which is enough to pick a translation direction.
+
+/// Clips shorter than this many letters are too ambiguous to classify.
+const MIN_LETTERS: usize = 3;
+/// Only the head of long clips is inspected.
+const MAX_CHARS: usize = 2000;
+
+#[derive(Default)]
+struct ScriptCounts {
+    han: usize,
+    kana: usize,
+    hangul: usize,
+    cyrillic: usize,
+    arabic: usize,
+    hebrew: usize,
+    greek: usize,
+    thai: usize,
+    devanagari: usize,
+    latin: usize,
+}
+
+impl ScriptCounts {
+    fn total(&self) -> usize {
+        self.han
+            + self.kana
+            + self.hangul
+            + self.cyrillic
+            + self.arabic
+            + self.hebrew
+            + self.greek
+            + self.thai
+            + self.devanagari
+            + self.latin
+    }
+}
+
+/// Returns a language tag such as `zh-CN`, `en` or `ja`, or `None` when the
+/// text has too few letters or no profile matches.
+pub fn detect(text: &str) -> Option<String> {
+    let mut counts = ScriptCounts::default();
+    for ch in text.chars().take(MAX_CHARS) {
+        match ch as u32 {
+            0x4E00..=0x9FFF | 0x3400..=0x4DBF | 0xF900..=0xFAFF => counts.han += 1,
+            0x3040..=0x30FF | 0x31F0..=0x31FF | 0xFF66..=0xFF9D => counts.kana += 1,
+            0xAC00..=0xD7AF | 0x1100..=0x11FF | 0x3130..=0x318F => counts.hangul += 1,
+            0x0400..=0x04FF => counts.cyrillic += 1,
+            0x0600..=0x06FF | 0x0750..=0x077F => counts.arabic += 1,
+            0x0590..=0x05FF => counts.hebrew += 1,
+            0x0370..=0x03FF => counts.greek += 1,
+            0x0E00..=0x0E7F => counts.thai += 1,
+            0x0900..=0x097F => counts.devanagari += 1,
+            _ if ch.is_alphabetic() && ch.is_ascii() => counts.latin += 1,
+            0x00C0..=0x024F => counts.latin += 1,
+            _ => {}
+        }
+    }
+
+    let total = counts.total();
+    if total < MIN_LETTERS {
+        return None;
+    }
+
+    // Japanese mixes kanji with kana; a modest share of kana is decisive.
+    if counts.kana * 10 >= total && counts.kana > 0 {
+        return Some("ja".to_string());
+    }
+    let scripts = [
+        (counts.han, "zh-CN"),
+        (counts.hangul, "ko"),
+        (counts.cyrillic, "ru"),
+        (counts.arabic, "ar"),
+        (counts.hebrew, "he"),
+        (counts.greek, "el"),
+        (counts.thai, "th"),
+        (counts.devanagari, "hi"),
+    ];
+    // CJK characters carry far more information than Latin letters, so a
+    // Chinese sentence with a few English words still counts as Chinese.
+    for (count, tag) in scripts {
+        let weight = if tag == "zh-CN" || tag == "ko" { 3 } else { 1 };
+        if count * weight * 2 >= total {
+            return Some(tag.to_string());
+        }
+    }
+    if counts.latin * 2 >= total {
+        return detect_latin(text);
+    }
+    None
+}
+
+const PROFILES: &[(&str, &[&str])] = &[
+    (
+        "en",
+        &[
+            " th", "the", "he ", "ing", "nd ", " an", "and", "ng ", " to", "to ", "ed ", " of",
+            "of ", "ion", " in", "is ", "in ", "er ", "es ", " is", "re ", "at ", "on ", "tio",
+            "ent", " it", "for", " fo", "you", "hat",
+        ],
+    ),
+    (
+        "fr",
+        &[
+            "es ", " de", "de ", "ent", " le", "le ", "nt ", "ion", "les", " la", "la ", "re ",
+            " et", "et ", "que", " qu", "ue ", "e d", " pa", "our", " po", "ous", "des", " un",
+            "est", "ait", " co", "eme", "tio", " vo",
+        ],
+    ),
+    (
+        "de",
+        &[
+            "en ", "er ", "ch ", "der", " de", "ie ", "ein", "die", " di", "ich", "sch", "che",
+            "nd ", "und", " un", "cht", "te ", " ei", "in ", "ung", "den", "es ", "ht ", "ist",
+            " is", "gen", " zu", "nde", " ni", "auf",
+        ],
+    ),
+    (
+        "es",
+        &[
+            "de ", " de", "os ", "la ", " la", " qu", "que", "ue ", "el ", " el", "en ", " en",
+            "es ", "as ", "ent", " co", "ion", "con", " lo", "los", "ado", "par", " pa", "na ",
+            "o d", "a d", "una", " un", "ara", "ció",
+        ],
+    ),
+    (
+        "pt",
+        &[
+            "de ", " de", "os ", " qu", "que", "ue ", "do ", " do", "ão ", "da ", " da", " co",
+            "ent", "com", " pa", "as ", "nte", "ção", " se", "ara", "par", "não", " nã", "em ",
+            " em", "um ", " um", "uma", "dos", "est",
+        ],
+    ),
+    (
+        "it",
+        &[
+            "di ", " di", "che", " ch", "he ", "la ", " la", "to ", "re ", "del", " de", "ell",
+            "lla", "no ", "one", "il ", " il", "per", " pe", "ent", " co", "ion", "zio", "e d",
+            "ato", "na ", "non", " no", "gli", "ono",
+        ],
+    ),
+];
+
+fn detect_latin(text: &str) -> Option<String> {
+    let normalized: String = text
+        .chars()
+        .take(MAX_CHARS)
+        .map(|ch| {
+            if ch.is_alphabetic() {
+                ch.to_lowercase().next().unwrap_or(ch)
+            } else {
+                ' '
+            }
+        })
+        .collect();
+    let normalized = format!(
+        " {} ",
+        normalized.split_whitespace().collect::<Vec<_>>().join(" ")
+    );
+    let chars: Vec<char> = normalized.chars().collect();
+    if chars.len() < 3 {
+        return None;
+    }
+
+    let mut best: Option<(&str, usize)> = None;
+    let mut runner_up = 0usize;
+    for (tag, trigrams) in PROFILES {
+        let mut score = 0usize;
+        for window in chars.windows(3) {
+            let gram: String = window.iter().collect();
+            if let Some(rank) = trigrams.iter().position(|candidate| *candidate == gram) {
+                // Earlier entries are more frequent and weigh more.
+                score += trigrams.len() - rank;
+            }
+        }
+        match best {
+            Some((_, top)) if score <= top => runner_up = runner_up.max(score),
+            _ => {
+                runner_up = best.map(|(_, top)| top).unwrap_or(0);
+                best = Some((tag, score));
+            }
+        }
+    }
+
+    // A tie means the text is too short or too mixed to tell.
+    match best {
+        Some((tag, score)) if score > 0 && score > runner_up => Some(tag.to_string()),
+        _ => None,
+    }
+}
+
+/// Compares the primary subtags, so `zh` matches `zh-CN` and `en-US` matches `en`.
+pub fn same_language(a: &str, b: &str) -> bool {
+    fn primary(tag: &str) -> String {
+        tag.trim()
+            .split(['-', '_'])
+            .next()
+            .unwrap_or_default()
+            .to_ascii_lowercase()
+    }
+    primary(a) == primary(b)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn detect_table() {
+        let cases: &[(&str, Option<&str>)] = &[
+            ("今天天气很好，我们去公园散步吧。", Some("zh-CN")),
+            ("これは日本語の文章です。", Some("ja")),
+            ("안녕하세요, 만나서 반갑습니다.", Some("ko")),
+            ("Привет, как дела у тебя сегодня?", Some("ru")),
+            (
+                "The quick brown fox jumps over the lazy dog and then it runs into the forest.",
+                Some("en"),
+            ),
+            (
+                "Le chat est sur la table et les enfants jouent dans le jardin avec leur chien.",
+                Some("fr"),
+            ),
+            (
+                "Der Hund und die Katze schlafen in der Küche, weil es draußen sehr kalt ist.",
+                Some("de"),
+            ),
+            // A few English words inside a Chinese sentence.
+            ("请把这个 pull request 合并到主分支里面", Some("zh-CN")),
+            // Too few letters to say anything.
+            ("ok", None),
+            ("好", None),
+            ("12345 !!! ???", None),
+            ("", None),
+        ];
+        for (text, expected) in cases {
+            assert_eq!(detect(text).as_deref(), *expected, "detect({text:?})");
+        }
+    }
+
+    #[test]
+    fn same_language_table() {
+        let cases = [
+            ("zh", "zh-CN", true),
+            ("en-US", "en", true),
+            ("EN_gb", "en-US", true),
+            (" ja ", "ja-JP", true),
+            ("zh-CN", "en", false),
+            ("pt-BR", "es", false),
+            ("", "en", false),
+        ];
+        for (a, b, expected) in cases {
+            assert_eq!(same_language(a, b), expected, "same_language({a:?}, {b:?})");
+        }
+    }
+}
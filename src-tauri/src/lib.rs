@@ -1,36 +1,86 @@
+mod ai_batch;
+mod ai_cache;
 mod ai_client;
+mod ai_service;
+mod backup;
+pub mod cli;
+mod clip_events;
 mod clipboard;
 mod clipboard_watcher;
 mod db;
+mod db_pool;
+mod foreground_app;
 mod hash;
+mod history_export;
+mod history_import;
+mod import_sources;
+mod ipc;
+mod json_output;
+mod lan_sync;
+mod lang_detect;
+mod local_api;
+mod prompt_templates;
+mod provider_profiles;
+mod recovery;
+mod remote_sync;
 mod runtime_config;
+mod secret_store;
 mod state;
+mod sync_store;
+mod transforms;
 mod tray;
+mod undo;
+mod usage;
+mod workflows;
 
+use ai_batch::{AiBatchRegistry, AiBatchReport, AiBatchRequest};
 use ai_client::{AiActionRequest, AiActionResponse};
+use ai_service::AiContext;
+use backup::{BackupInfo, BackupState};
+use clip_events::{ClipEvent, ClipEventQuery, ClipOrigin};
 use clipboard::ClipboardDraft;
-use db::{ClipItem, ClipKind, DbState};
+use db::{ClipItem, ClipKind, ClipProvenance, DbState, DerivedClip};
+use history_export::{
+    render_export, ExportFilter, ExportFormat, ExportSummary, HistoryExportPayload,
+};
+use history_import::{load_history_file, ImportFormat, ImportOptions, ImportReport, ParsedImport};
+use lan_sync::{LanSync, LanSyncState, LanSyncStatus, SyncReport};
+use local_api::{LocalApiState, LocalApiStatus};
+use prompt_templates::{PromptTemplate, PromptTemplateDraft, TemplateFormat};
+use provider_profiles::{ProviderProfile, ProviderProfileDraft};
+use recovery::{RecoveryReport, RecoveryState};
+use remote_sync::{RemoteSyncReport, RemoteSyncState, RemoteSyncStatus};
 use runtime_config::{RuntimeConfigState, RuntimePreferences};
+use secret_store::SecretName;
 use state::AppStatus;
+use sync_store::SyncPeer;
+use transforms::{TransformRequest, TransformResponse};
+use undo::{UndoChange, UndoEntry, UndoState, UndoStatus};
+use usage::{BudgetStatus, UsageGrouping, UsageSummaryRow};
+use workflows::{Workflow, WorkflowConnection, WorkflowDraft, WorkflowRun};
+
+use std::path::Path;
+use std::sync::Arc;
 
 use chrono::Utc;
 use enigo::{Direction, Enigo, Key, Keyboard, Settings};
 use serde::Serialize;
 use tauri::{AppHandle, Emitter, Manager, Runtime, State};
 use tauri_plugin_autostart::MacosLauncher;
-use tauri_plugin_global_shortcut::{GlobalShortcutExt, Shortcut};
+use tauri_plugin_clipboard_manager::ClipboardExt;
+use tauri_plugin_global_shortcut::{GlobalShortcutExt, Shortcut, ShortcutState};
 use tauri_plugin_store::StoreExt;
 use tracing::{error, info, warn};
 use tracing_subscriber::{fmt, layer::SubscriberExt, util::SubscriberInitExt, EnvFilter};
 
 const DEFAULT_SHORTCUT: &str = "CmdOrControl+Shift+V";
 const HISTORY_LIMIT: u32 = 200;
+const PREFERENCES_FILE_NAME: &str = "preferences.json";
 
-#[derive(Debug, Serialize)]
-struct HistoryExportPayload {
-    exported_at: i64,
-    items: Vec<ClipItem>,
-}
+/// Global shortcuts currently bound to workflows, so they can be replaced
+/// without touching the history shortcut.
+#[derive(Default)]
+struct WorkflowShortcuts(std::sync::Mutex<Vec<Shortcut>>);
 
 #[tauri::command]
 async fn insert_clip(
@@ -48,7 +98,7 @@ async fn insert_clip(
     let prefs = config.get();
     let db_clone = db.clone_for_thread();
     let result = tauri::async_runtime::spawn_blocking(move || {
-        let item = db_clone.upsert(payload)?;
+        let item = db_clone.upsert(payload, ClipOrigin::Ui)?;
         db_clone.apply_retention(&prefs)?;
         Ok::<_, anyhow::Error>(item)
     })
@@ -64,18 +114,35 @@ async fn insert_clip(
 
 #[tauri::command]
 async fn update_clip_content(
+    app: AppHandle,
     db: State<'_, DbState>,
     id: i64,
     payload: ClipboardDraft,
 ) -> Result<(), String> {
     let clip_payload = payload.into_payload().map_err(|err| err.to_string())?;
     let db_clone = db.clone_for_thread();
-    tauri::async_runtime::spawn_blocking(move || {
-        db_clone.update_content(id, clip_payload.content, clip_payload.preview)
+    let before = tauri::async_runtime::spawn_blocking(move || {
+        let before = db_clone.get(id)?;
+        db_clone.update_content(
+            id,
+            clip_payload.content.clone(),
+            clip_payload.preview.clone(),
+            ClipOrigin::Ui,
+        )?;
+        anyhow::Ok(before.map(|clip| {
+            (
+                (clip.content, clip.preview),
+                (clip_payload.content, clip_payload.preview),
+            )
+        }))
     })
     .await
     .map_err(|err| err.to_string())?
-    .map_err(|err| err.to_string())
+    .map_err(|err| err.to_string())?;
+    if let Some((before, after)) = before {
+        push_undo(&app, "编辑内容", UndoChange::Content { id, before, after });
+    }
+    Ok(())
 }
 
 #[tauri::command]
@@ -120,11 +187,14 @@ async fn update_clip_flags(
 
     let db_clone = db.clone_for_thread();
     let result = tauri::async_runtime::spawn_blocking(move || {
-        let res = db_clone.update_flags(id, pinned, favorite);
+        let before = db_clone
+            .get(id)?
+            .map(|clip| (clip.is_pinned, clip.is_favorite));
+        let res = db_clone.update_flags(id, pinned, favorite, ClipOrigin::Ui);
         if let Err(ref e) = res {
             error!("Database update_flags error: {:?}", e);
         }
-        res
+        res.map(|_| before)
     })
     .await
     .map_err(|err| {
@@ -136,6 +206,11 @@ async fn update_clip_flags(
         err.to_string()
     });
 
+    if let Ok(Some(before)) = result {
+        let after = (pinned.unwrap_or(before.0), favorite.unwrap_or(before.1));
+        push_undo(&app, "修改标记", UndoChange::Flags { id, before, after });
+    }
+    let result = result.map(|_| ());
     if result.is_ok() {
         info!("update_clip_flags completed successfully");
         // Emit event to all windows to sync state
@@ -149,30 +224,43 @@ async fn update_clip_flags(
 }
 
 #[tauri::command]
-async fn remove_clip(app: AppHandle, db: State<'_, DbState>, id: i64) -> Result<(), String> {
-    let db_clone = db.clone_for_thread();
-    let result = tauri::async_runtime::spawn_blocking(move || db_clone.delete(id))
+async fn remove_clip(app: AppHandle, id: i64) -> Result<(), String> {
+    tauri::async_runtime::spawn_blocking(move || delete_clip(&app, id, ClipOrigin::Ui))
         .await
         .map_err(|err| err.to_string())?
-        .map_err(|err| err.to_string());
-
-    if result.is_ok() {
-        // Emit event to all windows to sync state
-        let _ = app.emit("clip-removed", serde_json::json!({ "id": id }));
-    }
+        .map_err(|err| err.to_string())
+}
 
-    result
+/// Deletes a clip the way the UI does: the removal can be undone and every
+/// window is told about it.
+pub(crate) fn delete_clip(app: &AppHandle, id: i64, origin: ClipOrigin) -> anyhow::Result<()> {
+    let removed = app.state::<DbState>().delete(id, origin)?;
+    // Emit event to all windows to sync state
+    let _ = app.emit("clip-removed", serde_json::json!({ "id": id }));
+    push_undo(app, "删除剪贴板条目", UndoChange::Removed(removed));
+    Ok(())
 }
 
 #[tauri::command]
-async fn clear_history(db: State<'_, DbState>) -> Result<(), String> {
-    let db_clone = db.clone_for_thread();
-    tauri::async_runtime::spawn_blocking(move || db_clone.clear())
+async fn clear_history(app: AppHandle) -> Result<(), String> {
+    tauri::async_runtime::spawn_blocking(move || clear_clips(&app, ClipOrigin::Ui))
         .await
         .map_err(|err| err.to_string())?
         .map_err(|err| err.to_string())
 }
 
+/// Clears the history the way the UI does: undoable, and every window
+/// refreshes as after a single removal.
+pub(crate) fn clear_clips(app: &AppHandle, origin: ClipOrigin) -> anyhow::Result<()> {
+    let removed = app.state::<DbState>().clear(origin)?;
+    let _ = app.emit(
+        "clip-removed",
+        serde_json::json!({ "id": null, "count": removed.len() }),
+    );
+    push_undo(app, "清空历史", UndoChange::Removed(removed));
+    Ok(())
+}
+
 #[tauri::command]
 async fn export_history(db: State<'_, DbState>) -> Result<HistoryExportPayload, String> {
     let db_clone = db.clone_for_thread();
@@ -187,23 +275,176 @@ async fn export_history(db: State<'_, DbState>) -> Result<HistoryExportPayload,
 }
 
 #[tauri::command]
-async fn import_history(db: State<'_, DbState>, items: Vec<ClipItem>) -> Result<usize, String> {
+async fn export_history_to(
+    db: State<'_, DbState>,
+    path: String,
+    format: ExportFormat,
+    filter: Option<ExportFilter>,
+) -> Result<ExportSummary, String> {
+    let db_clone = db.clone_for_thread();
+    let filter = filter.unwrap_or_default();
+    tauri::async_runtime::spawn_blocking(move || {
+        let items = db_clone.export_filtered(&filter)?;
+        let clips = items.len();
+        let body = render_export(items, format)?;
+        std::fs::write(&path, &body)?;
+        anyhow::Ok(ExportSummary {
+            format,
+            path,
+            clips,
+            bytes: body.len(),
+        })
+    })
+    .await
+    .map_err(|err| err.to_string())?
+    .map_err(|err| err.to_string())
+}
+
+#[tauri::command]
+async fn import_history(
+    db: State<'_, DbState>,
+    items: Vec<serde_json::Value>,
+    options: Option<ImportOptions>,
+) -> Result<ImportReport, String> {
     let db_clone = db.clone_for_thread();
-    tauri::async_runtime::spawn_blocking(move || db_clone.import_many(items))
+    let parsed = ParsedImport::from_values(ImportFormat::ClipArray, items);
+    let options = options.unwrap_or_default();
+    tauri::async_runtime::spawn_blocking(move || db_clone.import_history(parsed, &options))
         .await
         .map_err(|err| err.to_string())?
         .map_err(|err| err.to_string())
 }
 
 #[tauri::command]
-async fn prune_history(db: State<'_, DbState>, keep_latest: usize) -> Result<usize, String> {
+async fn import_history_file(
+    db: State<'_, DbState>,
+    path: String,
+    options: Option<ImportOptions>,
+) -> Result<ImportReport, String> {
     let db_clone = db.clone_for_thread();
-    tauri::async_runtime::spawn_blocking(move || db_clone.prune_older_than(keep_latest))
+    let options = options.unwrap_or_default();
+    tauri::async_runtime::spawn_blocking(move || {
+        let parsed = load_history_file(Path::new(&path))?;
+        db_clone.import_history(parsed, &options)
+    })
+    .await
+    .map_err(|err| err.to_string())?
+    .map_err(|err| err.to_string())
+}
+
+#[tauri::command]
+async fn prune_history(
+    app: AppHandle,
+    db: State<'_, DbState>,
+    keep_latest: usize,
+) -> Result<usize, String> {
+    let db_clone = db.clone_for_thread();
+    let removed = tauri::async_runtime::spawn_blocking(move || {
+        db_clone.prune_older_than(keep_latest, ClipOrigin::Ui)
+    })
+    .await
+    .map_err(|err| err.to_string())?
+    .map_err(|err| err.to_string())?;
+    let count = removed.len();
+    push_undo(&app, "清理历史", UndoChange::Removed(removed));
+    Ok(count)
+}
+
+/// Records an undoable operation and tells every window the stack changed.
+pub(crate) fn push_undo(app: &AppHandle, label: &str, change: UndoChange) {
+    let depth = app.state::<RuntimeConfigState>().get().undo_depth;
+    let undo = app.state::<UndoState>();
+    undo.push(
+        UndoEntry {
+            label: label.to_string(),
+            change,
+        },
+        depth,
+    );
+    let _ = app.emit("undo://changed", undo.status());
+}
+
+#[tauri::command]
+async fn undo_history_change(
+    app: AppHandle,
+    db: State<'_, DbState>,
+) -> Result<Option<String>, String> {
+    let db_clone = db.clone_for_thread();
+    let undo_app = app.clone();
+    let label =
+        tauri::async_runtime::spawn_blocking(move || undo_app.state::<UndoState>().undo(&db_clone))
+            .await
+            .map_err(|err| err.to_string())?
+            .map_err(|err| err.to_string())?;
+    emit_undo_applied(&app, "undo", label.as_deref());
+    Ok(label)
+}
+
+#[tauri::command]
+async fn redo_history_change(
+    app: AppHandle,
+    db: State<'_, DbState>,
+) -> Result<Option<String>, String> {
+    let db_clone = db.clone_for_thread();
+    let redo_app = app.clone();
+    let label =
+        tauri::async_runtime::spawn_blocking(move || redo_app.state::<UndoState>().redo(&db_clone))
+            .await
+            .map_err(|err| err.to_string())?
+            .map_err(|err| err.to_string())?;
+    emit_undo_applied(&app, "redo", label.as_deref());
+    Ok(label)
+}
+
+#[tauri::command]
+async fn get_undo_status(undo: State<'_, UndoState>) -> Result<UndoStatus, String> {
+    Ok(undo.status())
+}
+
+fn emit_undo_applied(app: &AppHandle, action: &str, label: Option<&str>) {
+    if label.is_some() {
+        let _ = app.emit(
+            "undo://applied",
+            serde_json::json!({ "action": action, "label": label }),
+        );
+    }
+    let _ = app.emit("undo://changed", app.state::<UndoState>().status());
+}
+
+#[tauri::command]
+async fn list_backups(db: State<'_, DbState>) -> Result<Vec<BackupInfo>, String> {
+    let db_clone = db.clone_for_thread();
+    tauri::async_runtime::spawn_blocking(move || db_clone.list_backups())
         .await
         .map_err(|err| err.to_string())?
         .map_err(|err| err.to_string())
 }
 
+#[tauri::command]
+async fn create_backup(app: AppHandle) -> Result<BackupInfo, String> {
+    tauri::async_runtime::spawn_blocking(move || backup::backup_now(&app))
+        .await
+        .map_err(|err| err.to_string())?
+        .map_err(|err| format!("{err:#}"))
+}
+
+/// Returns the copy of the replaced database, if one could be taken.
+#[tauri::command]
+async fn restore_backup(app: AppHandle, file_name: String) -> Result<Option<BackupInfo>, String> {
+    tauri::async_runtime::spawn_blocking(move || backup::restore(&app, &file_name))
+        .await
+        .map_err(|err| err.to_string())?
+        .map_err(|err| format!("{err:#}"))
+}
+
+/// What startup recovery did to the database, reported only once.
+#[tauri::command]
+async fn take_recovery_report(
+    recovery: State<'_, RecoveryState>,
+) -> Result<Option<RecoveryReport>, String> {
+    Ok(recovery.take())
+}
+
 #[tauri::command]
 async fn vacuum_database(db: State<'_, DbState>) -> Result<(), String> {
     let db_clone = db.clone_for_thread();
@@ -213,26 +454,475 @@ async fn vacuum_database(db: State<'_, DbState>) -> Result<(), String> {
         .map_err(|err| err.to_string())
 }
 
+fn ai_context(app: &AppHandle) -> AiContext {
+    let offline = app.state::<AppStatus>().offline()
+        || app
+            .store("store.bin")
+            .ok()
+            .and_then(|store| store.get("offlineMode"))
+            .and_then(|value| value.as_bool())
+            .unwrap_or(false);
+    AiContext {
+        db: app.state::<DbState>().clone_for_thread(),
+        prefs: app.state::<RuntimeConfigState>().get(),
+        offline,
+    }
+}
+
 #[tauri::command]
 async fn perform_ai_action(
     app: AppHandle,
-    status: State<'_, AppStatus>,
     request: AiActionRequest,
 ) -> Result<AiActionResponse, String> {
-    if status.offline() {
-        return Err("离线模式已开启，无法调用 AI 服务".into());
+    let ctx = ai_context(&app);
+    let response = ai_service::run(&ctx, request)
+        .await
+        .map_err(|err| err.to_string())?;
+    if let Some(derived) = &response.saved_clip {
+        let _ = app.emit("clip-inserted", &derived.clip);
+    }
+    Ok(response)
+}
+
+#[tauri::command]
+async fn perform_ai_batch(
+    app: AppHandle,
+    registry: State<'_, AiBatchRegistry>,
+    request: AiBatchRequest,
+) -> Result<AiBatchReport, String> {
+    let ctx = ai_context(&app);
+    let policy = ctx.prefs.ai_batch.clone();
+    let progress_app = app.clone();
+    let report = ai_batch::run(&ctx, &registry, request, &policy, move |progress| {
+        let _ = progress_app.emit("ai-batch://progress", &progress);
+    })
+    .await
+    .map_err(|err| err.to_string())?;
+    for item in &report.items {
+        if let Some(clip) = &item.saved_clip {
+            let _ = app.emit("clip-inserted", clip);
+        }
+    }
+    Ok(report)
+}
+
+#[tauri::command]
+async fn cancel_ai_batch(
+    registry: State<'_, AiBatchRegistry>,
+    batch_id: String,
+) -> Result<bool, String> {
+    Ok(registry.cancel(&batch_id))
+}
+
+#[tauri::command]
+async fn apply_text_transform(
+    app: AppHandle,
+    db: State<'_, DbState>,
+    config: State<'_, RuntimeConfigState>,
+    request: TransformRequest,
+) -> Result<TransformResponse, String> {
+    let db_clone = db.clone_for_thread();
+    let prefs = config.get();
+    let response =
+        tauri::async_runtime::spawn_blocking(move || transforms::run(&db_clone, &prefs, request))
+            .await
+            .map_err(|err| err.to_string())?
+            .map_err(|err| err.to_string())?;
+    if let Some(derived) = &response.saved_clip {
+        let _ = app.emit("clip-inserted", &derived.clip);
     }
-    let store = app.store("store.bin").map_err(|e| e.to_string())?;
-    if store
-        .get("offlineMode")
-        .and_then(|value| value.as_bool())
-        .unwrap_or(false)
-    {
-        return Err("离线模式已开启，无法调用 AI 服务".into());
+    Ok(response)
+}
+
+#[tauri::command]
+async fn list_ai_workflows(db: State<'_, DbState>) -> Result<Vec<Workflow>, String> {
+    let db_clone = db.clone_for_thread();
+    tauri::async_runtime::spawn_blocking(move || db_clone.list_workflows())
+        .await
+        .map_err(|err| err.to_string())?
+        .map_err(|err| err.to_string())
+}
+
+#[tauri::command]
+async fn save_ai_workflow(
+    app: AppHandle,
+    db: State<'_, DbState>,
+    id: Option<i64>,
+    workflow: WorkflowDraft,
+) -> Result<Workflow, String> {
+    let db_clone = db.clone_for_thread();
+    let saved = tauri::async_runtime::spawn_blocking(move || db_clone.save_workflow(id, workflow))
+        .await
+        .map_err(|err| err.to_string())?
+        .map_err(|err| err.to_string())?;
+    register_workflow_shortcuts(&app);
+    Ok(saved)
+}
+
+#[tauri::command]
+async fn delete_ai_workflow(app: AppHandle, db: State<'_, DbState>, id: i64) -> Result<(), String> {
+    let db_clone = db.clone_for_thread();
+    tauri::async_runtime::spawn_blocking(move || db_clone.delete_workflow(id))
+        .await
+        .map_err(|err| err.to_string())?
+        .map_err(|err| err.to_string())?;
+    register_workflow_shortcuts(&app);
+    Ok(())
+}
+
+#[tauri::command]
+async fn run_ai_workflow(
+    app: AppHandle,
+    id: i64,
+    input: String,
+    source_clip_id: Option<i64>,
+    connection: Option<WorkflowConnection>,
+) -> Result<WorkflowRun, String> {
+    execute_workflow(
+        &app,
+        id,
+        input,
+        source_clip_id,
+        connection.unwrap_or_default(),
+    )
+    .await
+    .map_err(|err| err.to_string())
+}
+
+async fn execute_workflow(
+    app: &AppHandle,
+    id: i64,
+    input: String,
+    source_clip_id: Option<i64>,
+    connection: WorkflowConnection,
+) -> anyhow::Result<WorkflowRun> {
+    let ctx = ai_context(app);
+    let db_clone = ctx.db.clone_for_thread();
+    let workflow = tauri::async_runtime::spawn_blocking(move || db_clone.get_workflow(id))
+        .await??
+        .ok_or_else(|| anyhow::anyhow!("workflow {} does not exist", id))?;
+    let progress_app = app.clone();
+    let run = workflows::run(
+        &ctx,
+        &workflow,
+        input,
+        source_clip_id,
+        connection,
+        move |progress| {
+            if let workflows::WorkflowProgress::Finished {
+                saved_clip: Some(clip),
+                ..
+            } = &progress
+            {
+                let _ = progress_app.emit("clip-inserted", clip);
+            }
+            let _ = progress_app.emit("ai-workflow://progress", &progress);
+        },
+    )
+    .await?;
+    let _ = app.emit("ai-workflow://finished", &run);
+    Ok(run)
+}
+
+/// Runs a workflow from its global shortcut on the most recent clip and puts
+/// the final output on the clipboard.
+async fn run_workflow_from_shortcut(app: AppHandle, id: i64) -> anyhow::Result<()> {
+    let db_clone = app.state::<DbState>().clone_for_thread();
+    let (latest, connection) = tauri::async_runtime::spawn_blocking(move || {
+        let latest = db_clone
+            .latest()?
+            .ok_or_else(|| anyhow::anyhow!("剪贴板历史为空"))?;
+        let workflow = db_clone
+            .get_workflow(id)?
+            .ok_or_else(|| anyhow::anyhow!("workflow {} does not exist", id))?;
+        // There is no webview to supply a connection, so the credentials come
+        // from the workflow's provider profile, or the only stored one.
+        let provider_id = match workflow.provider_id {
+            Some(provider_id) => provider_id,
+            None => match db_clone.list_providers()?.as_slice() {
+                [only] => only.id,
+                _ => anyhow::bail!("请为工作流「{}」选择 AI 服务", workflow.name),
+            },
+        };
+        let connection = WorkflowConnection {
+            provider_id: Some(provider_id),
+            ..Default::default()
+        };
+        Ok::<_, anyhow::Error>((latest, connection))
+    })
+    .await??;
+    let input = match latest.kind {
+        ClipKind::Image => String::new(),
+        _ => latest.content.clone(),
+    };
+    let run = execute_workflow(&app, id, input, Some(latest.id), connection).await?;
+    let hash = crate::hash::compute_content_hash(ClipKind::Text, &run.output);
+    app.state::<AppStatus>().mark_self_copy(hash);
+    app.clipboard().write_text(run.output)?;
+    Ok(())
+}
+
+fn register_workflow_shortcuts(app: &AppHandle) {
+    let registry = app.state::<WorkflowShortcuts>();
+    let Ok(mut registered) = registry.0.lock() else {
+        return;
+    };
+    for shortcut in registered.drain(..) {
+        let _ = app.global_shortcut().unregister(shortcut);
+    }
+    let workflows = match app.state::<DbState>().list_workflows() {
+        Ok(workflows) => workflows,
+        Err(err) => {
+            error!("failed to load workflows for shortcuts: {err:?}");
+            return;
+        }
+    };
+    for workflow in workflows {
+        let Some(binding) = workflow.shortcut else {
+            continue;
+        };
+        let parsed = match binding.parse::<Shortcut>() {
+            Ok(parsed) => parsed,
+            Err(err) => {
+                warn!(
+                    "workflow {} has an invalid shortcut {binding}: {err}",
+                    workflow.id
+                );
+                continue;
+            }
+        };
+        if app.global_shortcut().is_registered(parsed) {
+            warn!(
+                "shortcut {binding} for workflow {} is already in use",
+                workflow.id
+            );
+            continue;
+        }
+        let workflow_id = workflow.id;
+        let result =
+            app.global_shortcut()
+                .on_shortcut(parsed, move |app_handle, _shortcut, event| {
+                    if event.state() != ShortcutState::Pressed {
+                        return;
+                    }
+                    let app_clone = app_handle.clone();
+                    tauri::async_runtime::spawn(async move {
+                        if let Err(err) = run_workflow_from_shortcut(app_clone, workflow_id).await {
+                            error!("workflow {workflow_id} failed from shortcut: {err:?}");
+                        }
+                    });
+                });
+        match result {
+            Ok(()) => {
+                info!("registered workflow shortcut {binding} for workflow {workflow_id}");
+                registered.push(parsed);
+            }
+            Err(err) => warn!("failed to register workflow shortcut {binding}: {err}"),
+        }
+    }
+}
+
+#[tauri::command]
+async fn list_ai_providers(db: State<'_, DbState>) -> Result<Vec<ProviderProfile>, String> {
+    let db_clone = db.clone_for_thread();
+    tauri::async_runtime::spawn_blocking(move || db_clone.list_providers())
+        .await
+        .map_err(|err| err.to_string())?
+        .map_err(|err| err.to_string())
+}
+
+#[tauri::command]
+async fn save_ai_provider(
+    db: State<'_, DbState>,
+    id: Option<i64>,
+    profile: ProviderProfileDraft,
+) -> Result<ProviderProfile, String> {
+    let db_clone = db.clone_for_thread();
+    tauri::async_runtime::spawn_blocking(move || db_clone.save_provider(id, profile))
+        .await
+        .map_err(|err| err.to_string())?
+        .map_err(|err| err.to_string())
+}
+
+#[tauri::command]
+async fn delete_ai_provider(db: State<'_, DbState>, id: i64) -> Result<(), String> {
+    let db_clone = db.clone_for_thread();
+    tauri::async_runtime::spawn_blocking(move || db_clone.delete_provider(id))
+        .await
+        .map_err(|err| err.to_string())?
+        .map_err(|err| err.to_string())
+}
+
+#[tauri::command]
+async fn get_ai_usage_summary(
+    db: State<'_, DbState>,
+    group_by: UsageGrouping,
+    since_days: Option<u32>,
+) -> Result<Vec<UsageSummaryRow>, String> {
+    let since = since_days.map(|days| Utc::now() - chrono::Duration::days(days as i64));
+    let db_clone = db.clone_for_thread();
+    tauri::async_runtime::spawn_blocking(move || db_clone.usage_summary(group_by, since))
+        .await
+        .map_err(|err| err.to_string())?
+        .map_err(|err| err.to_string())
+}
+
+#[tauri::command]
+async fn get_ai_budget_status(
+    db: State<'_, DbState>,
+    config: State<'_, RuntimeConfigState>,
+) -> Result<BudgetStatus, String> {
+    let pricing = config.get().ai_pricing;
+    let db_clone = db.clone_for_thread();
+    tauri::async_runtime::spawn_blocking(move || db_clone.budget_status(&pricing))
+        .await
+        .map_err(|err| err.to_string())?
+        .map_err(|err| err.to_string())
+}
+
+#[tauri::command]
+async fn probe_local_ai(
+    db: State<'_, DbState>,
+    config: State<'_, RuntimeConfigState>,
+) -> Result<ai_client::LocalEndpointProbe, String> {
+    let profile = config.get().local_ai;
+    let db_clone = db.clone_for_thread();
+    let api_key =
+        tauri::async_runtime::spawn_blocking(move || db_clone.secret(SecretName::LocalAiApiKey))
+            .await
+            .map_err(|err| err.to_string())?
+            .map_err(|err| err.to_string())?;
+    Ok(ai_client::probe_local_endpoint(&profile.base_url, api_key.as_deref()).await)
+}
+
+#[tauri::command]
+async fn list_stored_secrets(db: State<'_, DbState>) -> Result<Vec<SecretName>, String> {
+    let db_clone = db.clone_for_thread();
+    tauri::async_runtime::spawn_blocking(move || db_clone.stored_secrets())
+        .await
+        .map_err(|err| err.to_string())?
+        .map_err(|err| err.to_string())
+}
+
+/// Stores or, with an empty value, removes a secret. Remote sync is restarted
+/// when one of its credentials changes.
+#[tauri::command]
+async fn set_stored_secret(
+    app: AppHandle,
+    db: State<'_, DbState>,
+    config: State<'_, RuntimeConfigState>,
+    name: SecretName,
+    value: Option<String>,
+) -> Result<(), String> {
+    let db_clone = db.clone_for_thread();
+    tauri::async_runtime::spawn_blocking(move || db_clone.set_secret(name, value.as_deref()))
+        .await
+        .map_err(|err| err.to_string())?
+        .map_err(|err| err.to_string())?;
+    if name.is_remote_sync() {
+        remote_sync::apply(&app, &config.get().remote_sync).map_err(|err| err.to_string())?;
     }
+    Ok(())
+}
+
+#[tauri::command]
+async fn list_prompt_templates(db: State<'_, DbState>) -> Result<Vec<PromptTemplate>, String> {
+    let db_clone = db.clone_for_thread();
+    tauri::async_runtime::spawn_blocking(move || db_clone.list_prompt_templates())
+        .await
+        .map_err(|err| err.to_string())?
+        .map_err(|err| err.to_string())
+}
+
+#[tauri::command]
+async fn create_prompt_template(
+    db: State<'_, DbState>,
+    template: PromptTemplateDraft,
+) -> Result<PromptTemplate, String> {
+    let db_clone = db.clone_for_thread();
+    tauri::async_runtime::spawn_blocking(move || db_clone.create_prompt_template(template))
+        .await
+        .map_err(|err| err.to_string())?
+        .map_err(|err| err.to_string())
+}
+
+#[tauri::command]
+async fn update_prompt_template(
+    db: State<'_, DbState>,
+    id: i64,
+    template: PromptTemplateDraft,
+) -> Result<PromptTemplate, String> {
+    let db_clone = db.clone_for_thread();
+    tauri::async_runtime::spawn_blocking(move || db_clone.update_prompt_template(id, template))
+        .await
+        .map_err(|err| err.to_string())?
+        .map_err(|err| err.to_string())
+}
+
+#[tauri::command]
+async fn delete_prompt_template(db: State<'_, DbState>, id: i64) -> Result<(), String> {
+    let db_clone = db.clone_for_thread();
+    tauri::async_runtime::spawn_blocking(move || db_clone.delete_prompt_template(id))
+        .await
+        .map_err(|err| err.to_string())?
+        .map_err(|err| err.to_string())
+}
+
+#[tauri::command]
+async fn export_prompt_templates(
+    db: State<'_, DbState>,
+    format: TemplateFormat,
+) -> Result<String, String> {
+    let db_clone = db.clone_for_thread();
+    tauri::async_runtime::spawn_blocking(move || db_clone.export_prompt_templates(format))
+        .await
+        .map_err(|err| err.to_string())?
+        .map_err(|err| err.to_string())
+}
 
-    ai_client::perform(request)
+#[tauri::command]
+async fn import_prompt_templates(
+    db: State<'_, DbState>,
+    content: String,
+    format: TemplateFormat,
+) -> Result<usize, String> {
+    let db_clone = db.clone_for_thread();
+    tauri::async_runtime::spawn_blocking(move || db_clone.import_prompt_templates(&content, format))
+        .await
+        .map_err(|err| err.to_string())?
+        .map_err(|err| err.to_string())
+}
+
+#[tauri::command]
+async fn clear_ai_cache(db: State<'_, DbState>) -> Result<usize, String> {
+    let db_clone = db.clone_for_thread();
+    tauri::async_runtime::spawn_blocking(move || db_clone.clear_ai_cache())
+        .await
+        .map_err(|err| err.to_string())?
+        .map_err(|err| err.to_string())
+}
+
+#[tauri::command]
+async fn list_clip_derivations(
+    db: State<'_, DbState>,
+    id: i64,
+) -> Result<Vec<DerivedClip>, String> {
+    let db_clone = db.clone_for_thread();
+    tauri::async_runtime::spawn_blocking(move || db_clone.list_derivations(id))
+        .await
+        .map_err(|err| err.to_string())?
+        .map_err(|err| err.to_string())
+}
+
+#[tauri::command]
+async fn get_clip_provenance(
+    db: State<'_, DbState>,
+    id: i64,
+) -> Result<Option<ClipProvenance>, String> {
+    let db_clone = db.clone_for_thread();
+    tauri::async_runtime::spawn_blocking(move || db_clone.provenance(id))
         .await
+        .map_err(|err| err.to_string())?
         .map_err(|err| err.to_string())
 }
 
@@ -245,8 +935,8 @@ async fn get_app_status(status: State<'_, AppStatus>) -> Result<AppStatusSnapsho
 }
 
 #[tauri::command]
-async fn set_listening(status: State<'_, AppStatus>, listening: bool) -> Result<(), String> {
-    status.set_listening(listening);
+async fn set_listening(app: AppHandle, listening: bool) -> Result<(), String> {
+    tray::set_listening(&app, listening);
     Ok(())
 }
 
@@ -282,13 +972,145 @@ async fn ignore_next_clipboard_capture(
     Ok(())
 }
 
+#[tauri::command]
+async fn get_runtime_preferences(
+    config: State<'_, RuntimeConfigState>,
+) -> Result<RuntimePreferences, String> {
+    Ok(config.get())
+}
+
+/// Merges the given fields into the saved preferences; fields that are left
+/// out keep their current value.
 #[tauri::command]
 async fn update_runtime_preferences(
+    app: AppHandle,
     config: State<'_, RuntimeConfigState>,
-    preferences: RuntimePreferences,
+    preferences: serde_json::Value,
 ) -> Result<(), String> {
-    config.update(preferences);
-    Ok(())
+    let prefs = config
+        .merge(preferences)
+        .map_err(|err| format!("{err:#}"))?;
+    apply_background_services(&app, &prefs).map_err(|err| err.to_string())
+}
+
+/// Starts, restarts or stops the local API and both sync services to match
+/// `prefs`. Each is applied even if another fails; the first error is returned.
+fn apply_background_services(app: &AppHandle, prefs: &RuntimePreferences) -> anyhow::Result<()> {
+    [
+        local_api::apply(app, &prefs.local_api),
+        lan_sync::apply(app, &prefs.lan_sync),
+        remote_sync::apply(app, &prefs.remote_sync),
+    ]
+    .into_iter()
+    .collect()
+}
+
+fn lan_sync_engine(state: &LanSyncState) -> Result<Arc<LanSync>, String> {
+    state.engine().ok_or_else(|| "局域网同步未开启".to_string())
+}
+
+#[tauri::command]
+async fn get_lan_sync_status(
+    lan_sync: State<'_, LanSyncState>,
+) -> Result<Option<LanSyncStatus>, String> {
+    match lan_sync.engine() {
+        Some(engine) => tauri::async_runtime::spawn_blocking(move || engine.status())
+            .await
+            .map_err(|err| err.to_string())?
+            .map(Some)
+            .map_err(|err| err.to_string()),
+        None => Ok(None),
+    }
+}
+
+#[tauri::command]
+async fn start_lan_sync_pairing(lan_sync: State<'_, LanSyncState>) -> Result<String, String> {
+    lan_sync_engine(&lan_sync)?
+        .begin_pairing()
+        .map_err(|err| err.to_string())
+}
+
+#[tauri::command]
+async fn pair_lan_sync_peer(
+    lan_sync: State<'_, LanSyncState>,
+    address: String,
+    code: String,
+) -> Result<SyncPeer, String> {
+    let engine = lan_sync_engine(&lan_sync)?;
+    tauri::async_runtime::spawn_blocking(move || engine.pair(&address, &code))
+        .await
+        .map_err(|err| err.to_string())?
+        .map_err(|err| err.to_string())
+}
+
+#[tauri::command]
+async fn remove_lan_sync_peer(db: State<'_, DbState>, device_id: String) -> Result<(), String> {
+    let db = db.clone_for_thread();
+    tauri::async_runtime::spawn_blocking(move || db.delete_sync_peer(&device_id))
+        .await
+        .map_err(|err| err.to_string())?
+        .map_err(|err| err.to_string())
+}
+
+#[tauri::command]
+async fn sync_lan_now(
+    lan_sync: State<'_, LanSyncState>,
+    device_id: Option<String>,
+) -> Result<Vec<SyncReport>, String> {
+    let engine = lan_sync_engine(&lan_sync)?;
+    tauri::async_runtime::spawn_blocking(move || match device_id {
+        Some(device_id) => engine.sync_peer(&device_id).map(|report| vec![report]),
+        None => engine.sync_all(),
+    })
+    .await
+    .map_err(|err| err.to_string())?
+    .map_err(|err| err.to_string())
+}
+
+#[tauri::command]
+async fn get_remote_sync_status(
+    remote_sync: State<'_, RemoteSyncState>,
+) -> Result<RemoteSyncStatus, String> {
+    Ok(remote_sync.status())
+}
+
+#[tauri::command]
+async fn sync_remote_now(app: AppHandle) -> Result<RemoteSyncReport, String> {
+    remote_sync::sync_now(&app).await
+}
+
+#[tauri::command]
+async fn list_clip_events(
+    db: State<'_, DbState>,
+    query: Option<ClipEventQuery>,
+) -> Result<Vec<ClipEvent>, String> {
+    let db = db.clone_for_thread();
+    let query = query.unwrap_or_default();
+    tauri::async_runtime::spawn_blocking(move || db.list_clip_events(&query))
+        .await
+        .map_err(|err| err.to_string())?
+        .map_err(|err| err.to_string())
+}
+
+#[tauri::command]
+async fn get_local_api_status(
+    db: State<'_, DbState>,
+    local_api: State<'_, LocalApiState>,
+) -> Result<LocalApiStatus, String> {
+    local_api
+        .status(db.data_dir())
+        .map_err(|err| err.to_string())
+}
+
+#[tauri::command]
+async fn rotate_local_api_token(
+    db: State<'_, DbState>,
+    local_api: State<'_, LocalApiState>,
+) -> Result<LocalApiStatus, String> {
+    local_api
+        .rotate_token(db.data_dir())
+        .and_then(|_| local_api.status(db.data_dir()))
+        .map_err(|err| err.to_string())
 }
 
 #[tauri::command]
@@ -359,6 +1181,8 @@ async fn register_history_shortcut(app: AppHandle, shortcut: Option<String>) ->
             err.to_string()
         })?;
     info!("registered history shortcut: {parsed_shortcut}");
+    // unregister_all above also dropped the workflow bindings.
+    register_workflow_shortcuts(&app);
     Ok(())
 }
 
@@ -553,11 +1377,8 @@ pub fn run() {
         .plugin(tauri_plugin_fs::init())
         .plugin(tauri_plugin_process::init())
         .plugin(tauri_plugin_store::Builder::new().build())
-        .plugin(tauri_plugin_single_instance::init(|app, _args, _cwd| {
-            if let Some(window) = app.get_webview_window("main") {
-                let _ = window.show();
-                let _ = window.set_focus();
-            }
+        .plugin(tauri_plugin_single_instance::init(|app, args, _cwd| {
+            ipc::handle_second_instance(app, args);
         }))
         .plugin(tauri_plugin_autostart::init(
             MacosLauncher::LaunchAgent,
@@ -578,11 +1399,28 @@ pub fn run() {
                 }
             }
             app.manage(status);
-            let config_state = RuntimeConfigState::default();
+            app.manage(WorkflowShortcuts::default());
+            app.manage(AiBatchRegistry::default());
+            app.manage(LocalApiState::default());
+            app.manage(LanSyncState::default());
+            app.manage(RemoteSyncState::default());
+            app.manage(UndoState::default());
+            app.manage(BackupState::default());
+            let (db_state, recovery) = DbState::initialize(&handle)?;
+            let config_state =
+                RuntimeConfigState::load(db_state.data_dir().join(PREFERENCES_FILE_NAME));
             app.manage(config_state.clone());
-            let db_state = DbState::initialize(&handle)?;
             app.manage(db_state);
+            let recovery_state = RecoveryState::default();
+            if let Some(report) = recovery {
+                recovery_state.set(report);
+            }
+            app.manage(recovery_state);
+            if let Err(err) = apply_background_services(handle, &config_state.get()) {
+                warn!("failed to start background services: {err:#}");
+            }
             clipboard_watcher::spawn_clipboard_watcher(handle.clone());
+            backup::spawn_scheduler(handle.clone());
             tray::create_tray(&handle)?;
 
             #[cfg(debug_assertions)]
@@ -602,6 +1440,23 @@ pub fn run() {
                     let _ = window.show();
                 }
             }
+            match ipc::parse_args(&args) {
+                Ok(commands) => {
+                    let app_handle = handle.clone();
+                    tauri::async_runtime::spawn(async move {
+                        for command in commands {
+                            if let Err(err) = ipc::dispatch(&app_handle, command).await {
+                                error!("startup command failed: {err:#}");
+                            }
+                        }
+                    });
+                }
+                Err(err) => error!("ignoring startup arguments: {err:#}"),
+            }
+            #[cfg(unix)]
+            if let Err(err) = ipc::spawn_listener(handle) {
+                warn!("ipc socket unavailable: {err:#}");
+            }
             let db_handle = handle.state::<DbState>().clone();
             let prefs = config_state.get();
             if prefs.retention.vacuum_on_start {
@@ -627,9 +1482,35 @@ pub fn run() {
             remove_clip,
             clear_history,
             export_history,
+            export_history_to,
             import_history,
+            import_history_file,
             prune_history,
             perform_ai_action,
+            apply_text_transform,
+            perform_ai_batch,
+            cancel_ai_batch,
+            list_ai_workflows,
+            save_ai_workflow,
+            delete_ai_workflow,
+            run_ai_workflow,
+            probe_local_ai,
+            list_stored_secrets,
+            set_stored_secret,
+            get_ai_usage_summary,
+            get_ai_budget_status,
+            list_ai_providers,
+            save_ai_provider,
+            delete_ai_provider,
+            clear_ai_cache,
+            list_prompt_templates,
+            create_prompt_template,
+            update_prompt_template,
+            delete_prompt_template,
+            export_prompt_templates,
+            import_prompt_templates,
+            list_clip_derivations,
+            get_clip_provenance,
             get_app_status,
             set_listening,
             set_offline,
@@ -639,7 +1520,25 @@ pub fn run() {
             set_value_to_store,
             register_history_shortcut,
             vacuum_database,
+            list_backups,
+            create_backup,
+            restore_backup,
+            take_recovery_report,
+            get_runtime_preferences,
             update_runtime_preferences,
+            get_local_api_status,
+            rotate_local_api_token,
+            list_clip_events,
+            get_lan_sync_status,
+            start_lan_sync_pairing,
+            pair_lan_sync_peer,
+            remove_lan_sync_peer,
+            sync_lan_now,
+            get_remote_sync_status,
+            sync_remote_now,
+            undo_history_change,
+            redo_history_change,
+            get_undo_status,
             ignore_next_clipboard_capture,
             get_runtime_summary,
             show_quick_panel,
@@ -0,0 +1,457 @@
+//! Optional HTTP/JSON API on 127.0.0.1 for scripts and editor plugins.
+//!
+//! Every request must carry `Authorization: Bearer <token>`. The token lives
+//! in `local_api.token` next to the database and can be rotated from the UI.
+
+use std::io::{Cursor, Read, Write};
+use std::path::Path;
+use std::sync::atomic::AtomicUsize;
+use std::sync::{Arc, Mutex};
+
+use anyhow::Context;
+use chacha20poly1305::aead::{rand_core::RngCore, OsRng};
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Emitter, Manager};
+use tiny_http::{Header, Method, Request, Response, Server};
+use tracing::{error, info, warn};
+
+use crate::ai_client::{AiActionKind, AiActionRequest};
+use crate::ai_service;
+use crate::clip_events::ClipOrigin;
+use crate::clipboard::finalize_payload;
+use crate::db::{ClipKind, ClipPayload, DbState};
+use crate::lan_sync::ConnectionSlot;
+use crate::runtime_config::{LocalApiConfig, RuntimeConfigState};
+
+const TOKEN_FILE_NAME: &str = "local_api.token";
+const MAX_BODY_BYTES: u64 = 16 * 1024 * 1024;
+const DEFAULT_PAGE_SIZE: u32 = 50;
+/// Authorized requests handled at once; more get a 503 right away.
+const MAX_IN_FLIGHT: usize = 8;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct LocalApiStatus {
+    pub running: bool,
+    pub url: Option<String>,
+    pub token: String,
+}
+
+struct RunningServer {
+    server: Arc<Server>,
+    port: u16,
+}
+
+#[derive(Default)]
+pub struct LocalApiState {
+    running: Mutex<Option<RunningServer>>,
+    token: Mutex<Option<String>>,
+}
+
+impl LocalApiState {
+    fn token(&self, data_dir: &Path) -> anyhow::Result<String> {
+        let mut guard = self
+            .token
+            .lock()
+            .map_err(|_| anyhow::anyhow!("local api token lock poisoned"))?;
+        if let Some(token) = guard.as_ref() {
+            return Ok(token.clone());
+        }
+        let path = data_dir.join(TOKEN_FILE_NAME);
+        let token = match std::fs::read_to_string(&path) {
+            Ok(token) if !token.trim().is_empty() => token.trim().to_string(),
+            _ => write_new_token(&path)?,
+        };
+        *guard = Some(token.clone());
+        Ok(token)
+    }
+
+    pub fn rotate_token(&self, data_dir: &Path) -> anyhow::Result<String> {
+        let token = write_new_token(&data_dir.join(TOKEN_FILE_NAME))?;
+        if let Ok(mut guard) = self.token.lock() {
+            *guard = Some(token.clone());
+        }
+        Ok(token)
+    }
+
+    pub fn status(&self, data_dir: &Path) -> anyhow::Result<LocalApiStatus> {
+        let port = self
+            .running
+            .lock()
+            .ok()
+            .and_then(|running| running.as_ref().map(|server| server.port));
+        Ok(LocalApiStatus {
+            running: port.is_some(),
+            url: port.map(|port| format!("http://127.0.0.1:{port}/v1")),
+            token: self.token(data_dir)?,
+        })
+    }
+}
+
+fn write_new_token(path: &Path) -> anyhow::Result<String> {
+    let mut bytes = [0u8; 32];
+    OsRng.fill_bytes(&mut bytes);
+    let token: String = bytes.iter().map(|byte| format!("{byte:02x}")).collect();
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    let mut file = options
+        .open(path)
+        .context("failed to write local api token file")?;
+    file.write_all(token.as_bytes())?;
+    Ok(token)
+}
+
+/// Starts, stops or restarts the server so it matches `config`.
+pub fn apply(app: &AppHandle, config: &LocalApiConfig) -> anyhow::Result<()> {
+    let state = app.state::<LocalApiState>();
+    let mut running = state
+        .running
+        .lock()
+        .map_err(|_| anyhow::anyhow!("local api lock poisoned"))?;
+    if let Some(current) = running.as_ref() {
+        if config.enabled && current.port == config.port {
+            return Ok(());
+        }
+    }
+    if let Some(previous) = running.take() {
+        previous.server.unblock();
+        info!("local api stopped on port {}", previous.port);
+    }
+    if !config.enabled {
+        return Ok(());
+    }
+
+    let data_dir = app.state::<DbState>().data_dir().to_path_buf();
+    state.token(&data_dir)?;
+    let server = Server::http(("127.0.0.1", config.port))
+        .map_err(|err| anyhow::anyhow!("无法监听 127.0.0.1:{}：{}", config.port, err))?;
+    let server = Arc::new(server);
+    let accept = server.clone();
+    let app_handle = app.clone();
+    let port = config.port;
+    std::thread::Builder::new()
+        .name("local-api".into())
+        .spawn(move || {
+            let active = Arc::new(AtomicUsize::new(0));
+            for request in accept.incoming_requests() {
+                // Unauthorized requests are answered here, so they cannot
+                // tie up worker threads.
+                if let Err(err) = authorize(&app_handle, port, &request) {
+                    respond(request, Err(err));
+                    continue;
+                }
+                let Some(slot) = ConnectionSlot::acquire(&active, MAX_IN_FLIGHT) else {
+                    respond(
+                        request,
+                        Err(ApiError::new(503, "too many requests in flight")),
+                    );
+                    continue;
+                };
+                let app_handle = app_handle.clone();
+                // AI requests can take a while; keep the accept loop free.
+                std::thread::spawn(move || {
+                    let _slot = slot;
+                    handle(&app_handle, request);
+                });
+            }
+        })?;
+    info!("local api listening on 127.0.0.1:{}", config.port);
+    *running = Some(RunningServer {
+        server,
+        port: config.port,
+    });
+    Ok(())
+}
+
+struct ApiError {
+    status: u16,
+    message: String,
+}
+
+impl ApiError {
+    fn new(status: u16, message: impl Into<String>) -> Self {
+        Self {
+            status,
+            message: message.into(),
+        }
+    }
+}
+
+impl From<anyhow::Error> for ApiError {
+    fn from(err: anyhow::Error) -> Self {
+        Self::new(500, format!("{err:#}"))
+    }
+}
+
+type ApiResult = Result<(u16, serde_json::Value), ApiError>;
+
+fn handle(app: &AppHandle, mut request: Request) {
+    let outcome = route(app, &mut request);
+    respond(request, outcome);
+}
+
+fn respond(request: Request, outcome: ApiResult) {
+    let (status, body) = match outcome {
+        Ok(ok) => ok,
+        Err(err) => {
+            if err.status >= 500 {
+                error!(
+                    "local api {} {} failed: {}",
+                    request.method(),
+                    request.url(),
+                    err.message
+                );
+            }
+            (err.status, serde_json::json!({ "error": err.message }))
+        }
+    };
+    let response = json_response(status, &body);
+    if let Err(err) = request.respond(response) {
+        warn!("local api failed to respond: {err}");
+    }
+}
+
+fn json_response(status: u16, body: &serde_json::Value) -> Response<Cursor<Vec<u8>>> {
+    let header = Header::from_bytes(
+        &b"Content-Type"[..],
+        &b"application/json; charset=utf-8"[..],
+    )
+    .expect("static header is valid");
+    Response::from_data(body.to_string().into_bytes())
+        .with_status_code(status)
+        .with_header(header)
+}
+
+fn header<'a>(request: &'a Request, name: &'static str) -> Option<&'a str> {
+    request
+        .headers()
+        .iter()
+        .find(|header| header.field.equiv(name))
+        .map(|header| header.value.as_str())
+}
+
+/// Checks the bearer token and rejects requests whose Host is not loopback,
+/// which keeps web pages from reaching the API through DNS rebinding.
+fn authorize(app: &AppHandle, port: u16, request: &Request) -> Result<(), ApiError> {
+    let host = header(request, "Host").unwrap_or_default();
+    if host != format!("127.0.0.1:{port}") && host != format!("localhost:{port}") {
+        return Err(ApiError::new(403, "host not allowed"));
+    }
+    let data_dir = app.state::<DbState>().data_dir().to_path_buf();
+    let expected = app.state::<LocalApiState>().token(&data_dir)?;
+    let provided = header(request, "Authorization")
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .unwrap_or_default()
+        .trim();
+    if !constant_time_eq(provided.as_bytes(), expected.as_bytes()) {
+        return Err(ApiError::new(401, "invalid or missing token"));
+    }
+    Ok(())
+}
+
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    if a.len() != b.len() {
+        return false;
+    }
+    a.iter().zip(b).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+fn route(app: &AppHandle, request: &mut Request) -> ApiResult {
+    let url = request.url().to_string();
+    let (path, query) = url.split_once('?').unwrap_or((url.as_str(), ""));
+    let segments: Vec<&str> = path.trim_matches('/').split('/').collect();
+    let method = request.method().clone();
+    let db = app.state::<DbState>().clone_for_thread();
+
+    match (&method, segments.as_slice()) {
+        (Method::Get, ["v1", "clips"]) => {
+            let params = parse_query(query)?;
+            list_clips(&db, param(&params, "q"), &params)
+        }
+        (Method::Get, ["v1", "search"]) => {
+            let params = parse_query(query)?;
+            let term = param(&params, "q").ok_or_else(|| ApiError::new(400, "missing q"))?;
+            list_clips(&db, Some(term), &params)
+        }
+        (Method::Get, ["v1", "clips", id]) => {
+            let id = parse_id(id)?;
+            let clip = db
+                .get(id)?
+                .ok_or_else(|| ApiError::new(404, format!("clip {id} not found")))?;
+            Ok((
+                200,
+                serde_json::to_value(clip).map_err(anyhow::Error::from)?,
+            ))
+        }
+        (Method::Post, ["v1", "clips"]) => {
+            let body: InsertBody = read_json(request)?;
+            insert_clip(app, &db, body)
+        }
+        (Method::Delete, ["v1", "clips", id]) => {
+            let id = parse_id(id)?;
+            db.get(id)?
+                .ok_or_else(|| ApiError::new(404, format!("clip {id} not found")))?;
+            crate::delete_clip(app, id, ClipOrigin::Api)?;
+            Ok((200, serde_json::json!({ "id": id, "deleted": true })))
+        }
+        (Method::Post, ["v1", "clips", id, "pin"]) => {
+            let id = parse_id(id)?;
+            let body: PinBody = read_json(request)?;
+            db.get(id)?
+                .ok_or_else(|| ApiError::new(404, format!("clip {id} not found")))?;
+            db.update_flags(id, Some(body.pinned), None, ClipOrigin::Api)?;
+            let _ = app.emit(
+                "clip-updated",
+                serde_json::json!({ "id": id, "pinned": body.pinned, "favorite": null }),
+            );
+            Ok((200, serde_json::json!({ "id": id, "pinned": body.pinned })))
+        }
+        (Method::Post, ["v1", "ai"]) => {
+            let body: AiBody = read_json(request)?;
+            let request = ai_request(&db, body)?;
+            let ctx = crate::ai_context(app);
+            let response = tauri::async_runtime::block_on(ai_service::run(&ctx, request))
+                .map_err(|err| ApiError::new(502, format!("{err:#}")))?;
+            Ok((
+                200,
+                serde_json::to_value(response).map_err(anyhow::Error::from)?,
+            ))
+        }
+        (_, ["v1", ..]) => Err(ApiError::new(404, format!("no route for {method} {path}"))),
+        _ => Err(ApiError::new(404, "not found")),
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct InsertBody {
+    text: String,
+    #[serde(default)]
+    pinned: bool,
+}
+
+/// The subset of an AI request scripts may send. Connection settings come
+/// from a stored provider profile or the app's own configuration, never from
+/// the request, so the API cannot be used to send a key elsewhere.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+struct AiBody {
+    action: AiActionKind,
+    #[serde(default)]
+    input: String,
+    #[serde(default)]
+    clip_id: Option<i64>,
+    #[serde(default)]
+    provider_id: Option<i64>,
+}
+
+#[derive(Debug, Deserialize)]
+struct PinBody {
+    #[serde(default = "default_pinned")]
+    pinned: bool,
+}
+
+fn default_pinned() -> bool {
+    true
+}
+
+fn list_clips(db: &DbState, term: Option<String>, params: &[(String, String)]) -> ApiResult {
+    let limit = match param(params, "limit") {
+        Some(value) => value
+            .parse::<u32>()
+            .map_err(|_| ApiError::new(400, "invalid limit"))?,
+        None => DEFAULT_PAGE_SIZE,
+    };
+    let offset = match param(params, "offset") {
+        Some(value) => value
+            .parse::<u32>()
+            .map_err(|_| ApiError::new(400, "invalid offset"))?,
+        None => 0,
+    };
+    let items = db.list(term, Some(limit), offset, false)?;
+    Ok((200, serde_json::json!({ "items": items })))
+}
+
+fn insert_clip(app: &AppHandle, db: &DbState, body: InsertBody) -> ApiResult {
+    if body.text.trim().is_empty() {
+        return Err(ApiError::new(400, "text must not be empty"));
+    }
+    let payload = finalize_payload(ClipPayload {
+        kind: ClipKind::Text,
+        preview: Some(body.text.chars().take(120).collect()),
+        content: body.text,
+        extra: None,
+        content_hash: None,
+        is_pinned: body.pinned,
+        is_favorite: false,
+        source_app: None,
+    });
+    let clip = db.upsert(payload, ClipOrigin::Api)?;
+    db.apply_retention(&app.state::<RuntimeConfigState>().get())?;
+    let _ = app.emit("clip-inserted", &clip);
+    Ok((
+        201,
+        serde_json::to_value(clip).map_err(anyhow::Error::from)?,
+    ))
+}
+
+/// Text actions on a clip read its content unless `input` is given.
+fn ai_request(db: &DbState, body: AiBody) -> Result<AiActionRequest, ApiError> {
+    let mut input = body.input;
+    if let Some(id) = body.clip_id {
+        let clip = db
+            .get(id)?
+            .ok_or_else(|| ApiError::new(404, format!("clip {id} not found")))?;
+        if input.trim().is_empty() && !matches!(clip.kind, ClipKind::Image) {
+            input = clip.content;
+        }
+    }
+    Ok(AiActionRequest {
+        action: body.action,
+        input,
+        provider_id: body.provider_id,
+        source_clip_id: body.clip_id,
+        ..Default::default()
+    })
+}
+
+fn read_json<T: serde::de::DeserializeOwned>(request: &mut Request) -> Result<T, ApiError> {
+    let mut body = String::new();
+    request
+        .as_reader()
+        .take(MAX_BODY_BYTES)
+        .read_to_string(&mut body)
+        .map_err(|err| ApiError::new(400, format!("failed to read body: {err}")))?;
+    serde_json::from_str(&body).map_err(|err| ApiError::new(400, format!("invalid JSON: {err}")))
+}
+
+fn parse_id(raw: &str) -> Result<i64, ApiError> {
+    raw.parse()
+        .map_err(|_| ApiError::new(400, format!("invalid clip id {raw}")))
+}
+
+fn parse_query(query: &str) -> Result<Vec<(String, String)>, ApiError> {
+    query
+        .split('&')
+        .filter(|pair| !pair.is_empty())
+        .map(|pair| {
+            let (key, value) = pair.split_once('=').unwrap_or((pair, ""));
+            let decode = |raw: &str| {
+                crate::transforms::url_decode(raw)
+                    .map_err(|err| ApiError::new(400, err.to_string()))
+            };
+            Ok((decode(key)?, decode(value)?))
+        })
+        .collect()
+}
+
+fn param(params: &[(String, String)], name: &str) -> Option<String> {
+    params
+        .iter()
+        .find(|(key, _)| key == name)
+        .map(|(_, value)| value.clone())
+        .filter(|value| !value.trim().is_empty())
+}
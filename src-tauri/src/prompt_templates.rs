@@ -0,0 +1,356 @@
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use rusqlite::{params, Connection, OptionalExtension, Row};
+use serde::{Deserialize, Serialize};
+
+use crate::db::{datetime_to_timestamp, timestamp_to_datetime, ClipItem, ClipKind, DbState};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PromptTemplate {
+    pub id: i64,
+    pub name: String,
+    pub description: Option<String>,
+    pub system_prompt: String,
+    pub user_prompt: String,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PromptTemplateDraft {
+    pub name: String,
+    #[serde(default)]
+    pub description: Option<String>,
+    #[serde(default)]
+    pub system_prompt: String,
+    pub user_prompt: String,
+}
+
+/// A template with its variables substituted, ready to be sent to a model.
+#[derive(Debug, Clone)]
+pub struct RenderedPrompt {
+    pub template_name: String,
+    pub system_prompt: String,
+    pub user_prompt: String,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum TemplateFormat {
+    Json,
+    Toml,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct TemplateBundle {
+    templates: Vec<PromptTemplateDraft>,
+}
+
+pub(crate) fn migrate(conn: &Connection) -> Result<()> {
+    conn.execute_batch(
+        r#"
+        CREATE TABLE IF NOT EXISTS prompt_templates (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            name TEXT NOT NULL UNIQUE,
+            description TEXT,
+            system_prompt TEXT NOT NULL DEFAULT '',
+            user_prompt TEXT NOT NULL,
+            created_at INTEGER NOT NULL,
+            updated_at INTEGER NOT NULL
+        );
+        "#,
+    )?;
+    Ok(())
+}
+
+impl PromptTemplateDraft {
+    fn validate(&self) -> Result<()> {
+        if self.name.trim().is_empty() {
+            anyhow::bail!("prompt template name must not be empty");
+        }
+        if self.user_prompt.trim().is_empty() {
+            anyhow::bail!("prompt template `{}` has an empty user prompt", self.name);
+        }
+        Ok(())
+    }
+}
+
+/// The `clip.*` variables `render` knows about.
+const CLIP_FIELDS: &[&str] = &["id", "kind", "preview", "created_at", "source_app"];
+
+impl PromptTemplate {
+    /// Substitutes `{{input}}`, `{{language}}` and `{{clip.*}}` variables.
+    /// Known `clip.*` variables resolve to an empty string when no source clip
+    /// is given or the clip does not carry that information; unknown ones are
+    /// left in place like any other unknown name.
+    pub fn render(&self, input: &str, language: &str, clip: Option<&ClipItem>) -> RenderedPrompt {
+        let lookup = |name: &str| -> Option<String> {
+            match name {
+                "input" => Some(input.trim().to_string()),
+                "language" => Some(language.to_string()),
+                _ => {
+                    let field = name.strip_prefix("clip.")?;
+                    let value = |clip: &ClipItem| -> Option<String> {
+                        Some(match field {
+                            "id" => clip.id.to_string(),
+                            "kind" => match clip.kind {
+                                ClipKind::Text => "text",
+                                ClipKind::Image => "image",
+                                ClipKind::File => "file",
+                            }
+                            .to_string(),
+                            "preview" => clip.preview.clone().unwrap_or_default(),
+                            "created_at" => clip.created_at.to_rfc3339(),
+                            "source_app" => clip.source_app.clone().unwrap_or_default(),
+                            _ => return None,
+                        })
+                    };
+                    match clip {
+                        Some(clip) => value(clip),
+                        None => CLIP_FIELDS.contains(&field).then(String::new),
+                    }
+                }
+            }
+        };
+        RenderedPrompt {
+            template_name: self.name.clone(),
+            system_prompt: substitute(&self.system_prompt, &lookup),
+            user_prompt: substitute(&self.user_prompt, &lookup),
+        }
+    }
+}
+
+/// Replaces `{{ name }}` placeholders. Unknown names are left untouched so
+/// literal braces in prompts survive rendering.
+fn substitute(template: &str, lookup: &dyn Fn(&str) -> Option<String>) -> String {
+    let mut output = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(start) = rest.find("{{") {
+        output.push_str(&rest[..start]);
+        let after = &rest[start + 2..];
+        let Some(end) = after.find("}}") else {
+            output.push_str(&rest[start..]);
+            return output;
+        };
+        let name = after[..end].trim();
+        match lookup(name) {
+            Some(value) => output.push_str(&value),
+            None => output.push_str(&rest[start..start + 2 + end + 2]),
+        }
+        rest = &after[end + 2..];
+    }
+    output.push_str(rest);
+    output
+}
+
+impl DbState {
+    pub fn list_prompt_templates(&self) -> Result<Vec<PromptTemplate>> {
+        self.read(|conn| {
+            let mut stmt = conn.prepare(
+                "SELECT id, name, description, system_prompt, user_prompt, created_at, updated_at FROM prompt_templates ORDER BY name COLLATE NOCASE",
+            )?;
+            let rows = stmt.query_map([], map_template_row)?;
+            Ok(rows.collect::<Result<Vec<_>, _>>()?)
+        })
+    }
+
+    pub fn get_prompt_template(&self, id: i64) -> Result<Option<PromptTemplate>> {
+        self.read(|conn| load_template(conn, id))
+    }
+
+    pub fn create_prompt_template(&self, draft: PromptTemplateDraft) -> Result<PromptTemplate> {
+        draft.validate()?;
+        self.write(move |conn| {
+            let now = datetime_to_timestamp(Utc::now());
+            conn.execute(
+                "INSERT INTO prompt_templates (name, description, system_prompt, user_prompt, created_at, updated_at) VALUES (?1, ?2, ?3, ?4, ?5, ?5)",
+                params![
+                    draft.name.trim(),
+                    draft.description,
+                    draft.system_prompt,
+                    draft.user_prompt,
+                    now
+                ],
+            )
+            .with_context(|| format!("failed to create prompt template `{}`", draft.name))?;
+            let id = conn.last_insert_rowid();
+            load_template(conn, id)?.context("failed to load created prompt template")
+        })
+    }
+
+    pub fn update_prompt_template(
+        &self,
+        id: i64,
+        draft: PromptTemplateDraft,
+    ) -> Result<PromptTemplate> {
+        draft.validate()?;
+        self.write(move |conn| {
+            let updated = conn.execute(
+                "UPDATE prompt_templates SET name = ?1, description = ?2, system_prompt = ?3, user_prompt = ?4, updated_at = ?5 WHERE id = ?6",
+                params![
+                    draft.name.trim(),
+                    draft.description,
+                    draft.system_prompt,
+                    draft.user_prompt,
+                    datetime_to_timestamp(Utc::now()),
+                    id
+                ],
+            )?;
+            if updated == 0 {
+                anyhow::bail!("prompt template {} does not exist", id);
+            }
+            load_template(conn, id)?.context("failed to load updated prompt template")
+        })
+    }
+
+    pub fn delete_prompt_template(&self, id: i64) -> Result<()> {
+        self.write(move |conn| {
+            conn.execute("DELETE FROM prompt_templates WHERE id = ?1", params![id])?;
+            Ok(())
+        })
+    }
+
+    pub fn export_prompt_templates(&self, format: TemplateFormat) -> Result<String> {
+        let bundle = TemplateBundle {
+            templates: self
+                .list_prompt_templates()?
+                .into_iter()
+                .map(|template| PromptTemplateDraft {
+                    name: template.name,
+                    description: template.description,
+                    system_prompt: template.system_prompt,
+                    user_prompt: template.user_prompt,
+                })
+                .collect(),
+        };
+        match format {
+            TemplateFormat::Json => Ok(serde_json::to_string_pretty(&bundle)?),
+            TemplateFormat::Toml => Ok(toml::to_string_pretty(&bundle)?),
+        }
+    }
+
+    /// Imports templates, replacing existing ones with the same name.
+    /// JSON input may be either `{ "templates": [...] }` or a bare array.
+    pub fn import_prompt_templates(&self, content: &str, format: TemplateFormat) -> Result<usize> {
+        let drafts = match format {
+            TemplateFormat::Json => {
+                let value: serde_json::Value =
+                    serde_json::from_str(content).context("invalid template JSON")?;
+                if value.is_array() {
+                    serde_json::from_value::<Vec<PromptTemplateDraft>>(value)?
+                } else {
+                    serde_json::from_value::<TemplateBundle>(value)?.templates
+                }
+            }
+            TemplateFormat::Toml => {
+                toml::from_str::<TemplateBundle>(content)
+                    .context("invalid template TOML")?
+                    .templates
+            }
+        };
+        for draft in &drafts {
+            draft.validate()?;
+        }
+
+        self.write(move |conn| {
+            let tx = conn.transaction()?;
+            let now = datetime_to_timestamp(Utc::now());
+            for draft in &drafts {
+                tx.execute(
+                    "INSERT INTO prompt_templates (name, description, system_prompt, user_prompt, created_at, updated_at) VALUES (?1, ?2, ?3, ?4, ?5, ?5) \
+                    ON CONFLICT(name) DO UPDATE SET description = excluded.description, system_prompt = excluded.system_prompt, user_prompt = excluded.user_prompt, updated_at = excluded.updated_at",
+                    params![
+                        draft.name.trim(),
+                        draft.description,
+                        draft.system_prompt,
+                        draft.user_prompt,
+                        now
+                    ],
+                )?;
+            }
+            tx.commit()?;
+            Ok(drafts.len())
+        })
+    }
+}
+
+fn load_template(conn: &Connection, id: i64) -> Result<Option<PromptTemplate>> {
+    Ok(conn
+        .query_row(
+            "SELECT id, name, description, system_prompt, user_prompt, created_at, updated_at FROM prompt_templates WHERE id = ?1",
+            params![id],
+            map_template_row,
+        )
+        .optional()?)
+}
+
+fn map_template_row(row: &Row<'_>) -> rusqlite::Result<PromptTemplate> {
+    let created_at_ts: i64 = row.get(5)?;
+    let updated_at_ts: i64 = row.get(6)?;
+    Ok(PromptTemplate {
+        id: row.get(0)?,
+        name: row.get(1)?,
+        description: row.get(2)?,
+        system_prompt: row.get(3)?,
+        user_prompt: row.get(4)?,
+        created_at: timestamp_to_datetime(created_at_ts),
+        updated_at: timestamp_to_datetime(updated_at_ts),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn template(user_prompt: &str) -> PromptTemplate {
+        PromptTemplate {
+            id: 1,
+            name: "test".to_string(),
+            description: None,
+            system_prompt: String::new(),
+            user_prompt: user_prompt.to_string(),
+            created_at: Utc::now(),
+            updated_at: Utc::now(),
+        }
+    }
+
+    fn clip() -> ClipItem {
+        ClipItem {
+            id: 7,
+            kind: ClipKind::Text,
+            content: "hello".to_string(),
+            content_hash: String::new(),
+            preview: Some("hello".to_string()),
+            extra: None,
+            is_pinned: false,
+            is_favorite: false,
+            created_at: Utc::now(),
+            updated_at: Utc::now(),
+            language: None,
+            source_app: Some("firefox".to_string()),
+        }
+    }
+
+    #[test]
+    fn render_clip_variables() {
+        let rendered = template("{{input}} from {{ clip.source_app }} #{{clip.id}}").render(
+            " hi ",
+            "en",
+            Some(&clip()),
+        );
+        assert_eq!(rendered.user_prompt, "hi from firefox #7");
+
+        let rendered = template("[{{clip.source_app}}]").render("hi", "en", None);
+        assert_eq!(rendered.user_prompt, "[]");
+    }
+
+    #[test]
+    fn render_keeps_unknown_variables() {
+        let text = "{{clip.owner}} {{other}} {{input";
+        for clip in [Some(clip()), None] {
+            let rendered = template(text).render("hi", "en", clip.as_ref());
+            assert_eq!(rendered.user_prompt, text);
+        }
+    }
+}
@@ -0,0 +1,229 @@
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use rusqlite::{params, Connection, OptionalExtension, Row};
+use serde::{Deserialize, Serialize};
+
+use crate::db::{datetime_to_timestamp, timestamp_to_datetime, DbState};
+
+/// Provider profile as exposed to the webview. The API key itself is never
+/// serialized; `has_api_key` tells the UI whether one is stored.
+#[derive(Debug, Clone, Serialize)]
+pub struct ProviderProfile {
+    pub id: i64,
+    pub name: String,
+    pub base_url: String,
+    pub model: Option<String>,
+    pub has_api_key: bool,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ProviderProfileDraft {
+    pub name: String,
+    pub base_url: String,
+    #[serde(default)]
+    pub model: Option<String>,
+    /// `None` keeps the stored key, an empty string removes it. A stored key
+    /// is also dropped when the base URL changes, so it is never sent to an
+    /// endpoint it was not entered for.
+    #[serde(default)]
+    pub api_key: Option<String>,
+}
+
+/// Decrypted credentials, only used inside the backend to build a request.
+#[derive(Clone)]
+pub struct ProviderCredentials {
+    pub base_url: String,
+    pub model: Option<String>,
+    pub api_key: String,
+}
+
+pub(crate) fn migrate(conn: &Connection) -> Result<()> {
+    conn.execute_batch(
+        r#"
+        CREATE TABLE IF NOT EXISTS ai_providers (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            name TEXT NOT NULL,
+            base_url TEXT NOT NULL,
+            model TEXT,
+            api_key_cipher BLOB,
+            api_key_nonce BLOB,
+            created_at INTEGER NOT NULL,
+            updated_at INTEGER NOT NULL
+        );
+        "#,
+    )?;
+    Ok(())
+}
+
+impl DbState {
+    pub fn list_providers(&self) -> Result<Vec<ProviderProfile>> {
+        self.read(|conn| {
+            let mut stmt = conn.prepare(
+                "SELECT id, name, base_url, model, api_key_cipher IS NOT NULL, created_at, updated_at FROM ai_providers ORDER BY name COLLATE NOCASE",
+            )?;
+            let rows = stmt.query_map([], map_profile_row)?;
+            Ok(rows.collect::<Result<Vec<_>, _>>()?)
+        })
+    }
+
+    /// Creates a profile, or updates profile `id` when given.
+    pub fn save_provider(
+        &self,
+        id: Option<i64>,
+        draft: ProviderProfileDraft,
+    ) -> Result<ProviderProfile> {
+        if draft.name.trim().is_empty() {
+            anyhow::bail!("provider name must not be empty");
+        }
+        if draft.base_url.trim().is_empty() {
+            anyhow::bail!("provider base URL must not be empty");
+        }
+        let encrypted = match draft.api_key.as_deref().map(str::trim) {
+            Some("") => Some(None),
+            Some(key) => Some(Some(self.encrypt_secret(key)?)),
+            None => None,
+        };
+        let now = datetime_to_timestamp(Utc::now());
+        self.write(move |conn| {
+            let model = draft
+                .model
+                .as_deref()
+                .map(str::trim)
+                .filter(|model| !model.is_empty());
+            let id = match id {
+                Some(id) => {
+                    let updated = conn.execute(
+                        "UPDATE ai_providers SET name = ?1, base_url = ?2, model = ?3, updated_at = ?4, \
+                            api_key_cipher = CASE WHEN base_url = ?2 THEN api_key_cipher END, \
+                            api_key_nonce = CASE WHEN base_url = ?2 THEN api_key_nonce END \
+                         WHERE id = ?5",
+                        params![draft.name.trim(), draft.base_url.trim(), model, now, id],
+                    )?;
+                    if updated == 0 {
+                        anyhow::bail!("provider profile {} does not exist", id);
+                    }
+                    id
+                }
+                None => {
+                    conn.execute(
+                        "INSERT INTO ai_providers (name, base_url, model, created_at, updated_at) VALUES (?1, ?2, ?3, ?4, ?4)",
+                        params![draft.name.trim(), draft.base_url.trim(), model, now],
+                    )?;
+                    conn.last_insert_rowid()
+                }
+            };
+            if let Some(secret) = encrypted {
+                let (cipher, nonce) = secret.unzip();
+                conn.execute(
+                    "UPDATE ai_providers SET api_key_cipher = ?1, api_key_nonce = ?2 WHERE id = ?3",
+                    params![cipher, nonce, id],
+                )?;
+            }
+            load_profile(conn, id)?.context("failed to load saved provider profile")
+        })
+    }
+
+    pub fn delete_provider(&self, id: i64) -> Result<()> {
+        self.write(move |conn| {
+            conn.execute("DELETE FROM ai_providers WHERE id = ?1", params![id])?;
+            Ok(())
+        })
+    }
+
+    pub fn provider_credentials(&self, id: i64) -> Result<ProviderCredentials> {
+        let (base_url, model, cipher, nonce) = self.read(|conn| {
+            conn.query_row(
+                "SELECT base_url, model, api_key_cipher, api_key_nonce FROM ai_providers WHERE id = ?1",
+                params![id],
+                |row| {
+                    Ok((
+                        row.get::<_, String>(0)?,
+                        row.get::<_, Option<String>>(1)?,
+                        row.get::<_, Option<Vec<u8>>>(2)?,
+                        row.get::<_, Option<Vec<u8>>>(3)?,
+                    ))
+                },
+            )
+            .optional()?
+            .with_context(|| format!("provider profile {} does not exist", id))
+        })?;
+        let api_key = match (cipher, nonce) {
+            (Some(cipher), Some(nonce)) => self.decrypt_secret(&cipher, &nonce)?,
+            _ => String::new(),
+        };
+        Ok(ProviderCredentials {
+            base_url,
+            model,
+            api_key,
+        })
+    }
+}
+
+fn load_profile(conn: &Connection, id: i64) -> Result<Option<ProviderProfile>> {
+    Ok(conn
+        .query_row(
+            "SELECT id, name, base_url, model, api_key_cipher IS NOT NULL, created_at, updated_at FROM ai_providers WHERE id = ?1",
+            params![id],
+            map_profile_row,
+        )
+        .optional()?)
+}
+
+fn map_profile_row(row: &Row<'_>) -> rusqlite::Result<ProviderProfile> {
+    let created_at_ts: i64 = row.get(5)?;
+    let updated_at_ts: i64 = row.get(6)?;
+    Ok(ProviderProfile {
+        id: row.get(0)?,
+        name: row.get(1)?,
+        base_url: row.get(2)?,
+        model: row.get(3)?,
+        has_api_key: row.get(4)?,
+        created_at: timestamp_to_datetime(created_at_ts),
+        updated_at: timestamp_to_datetime(updated_at_ts),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::TestDb;
+
+    fn draft(base_url: &str, api_key: Option<&str>) -> ProviderProfileDraft {
+        ProviderProfileDraft {
+            name: "test".to_string(),
+            base_url: base_url.to_string(),
+            model: None,
+            api_key: api_key.map(str::to_string),
+        }
+    }
+
+    #[test]
+    fn changing_base_url_drops_the_stored_key() {
+        let db = TestDb::new();
+        let id = db
+            .save_provider(None, draft("https://a.example/v1", Some("sk-a")))
+            .unwrap()
+            .id;
+        let saved = db
+            .save_provider(Some(id), draft("https://a.example/v1", None))
+            .unwrap();
+        assert!(saved.has_api_key);
+        assert_eq!(db.provider_credentials(id).unwrap().api_key, "sk-a");
+
+        let moved = db
+            .save_provider(Some(id), draft("https://b.example/v1", None))
+            .unwrap();
+        assert!(!moved.has_api_key);
+
+        let rekeyed = db
+            .save_provider(Some(id), draft("https://c.example/v1", Some("sk-c")))
+            .unwrap();
+        assert!(rekeyed.has_api_key);
+        let credentials = db.provider_credentials(id).unwrap();
+        assert_eq!(credentials.base_url, "https://c.example/v1");
+        assert_eq!(credentials.api_key, "sk-c");
+    }
+}
@@ -0,0 +1,361 @@
+//! Startup recovery of a damaged history database.
+//!
+//! Before the app uses the database it runs a quick check. A database that
+//! fails it is moved into `quarantine/`, the newest intact backup (or an
+//! empty database) takes its place, and whatever rows can still be read from
+//! the damaged file are copied back on top. Rows salvaged from the damaged
+//! file win over the backup's, since they are newer.
+
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use anyhow::Context;
+use chrono::Utc;
+use rusqlite::types::Value;
+use rusqlite::{Connection, ErrorCode, OpenFlags};
+use serde::Serialize;
+use tracing::{info, warn};
+
+use crate::backup::{integrity_check, quick_check, CheckFailed};
+use crate::db::DbState;
+
+const QUARANTINE_DIR: &str = "quarantine";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum RecoveryOutcome {
+    /// Another process kept the database locked. Nothing was changed.
+    Locked,
+    /// The damaged database was replaced by a backup.
+    RestoredBackup,
+    /// The damaged database was replaced by an empty one.
+    Fresh,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct RecoveryReport {
+    pub outcome: RecoveryOutcome,
+    pub reason: String,
+    /// Where the damaged database was moved.
+    pub quarantined: Option<String>,
+    /// File name of the backup that was restored.
+    pub backup: Option<String>,
+    pub salvaged_clips: usize,
+    pub salvaged_rows: usize,
+}
+
+/// Holds the startup report until the UI picks it up.
+#[derive(Default)]
+pub struct RecoveryState {
+    report: Mutex<Option<RecoveryReport>>,
+}
+
+impl RecoveryState {
+    pub fn set(&self, report: RecoveryReport) {
+        *self.report.lock().unwrap() = Some(report);
+    }
+
+    /// Returns the report once, so only the first window shows it.
+    pub fn take(&self) -> Option<RecoveryReport> {
+        self.report.lock().unwrap().take()
+    }
+}
+
+enum Health {
+    Ok,
+    Locked(String),
+    Damaged(String),
+}
+
+fn sqlite_code(err: &anyhow::Error) -> Option<ErrorCode> {
+    err.chain()
+        .find_map(|cause| match cause.downcast_ref::<rusqlite::Error>() {
+            Some(rusqlite::Error::SqliteFailure(failure, _)) => Some(failure.code),
+            _ => None,
+        })
+}
+
+/// Quick-checks and migrates the database. Only the check's findings and
+/// SQLite's own corruption codes count as damage; anything else, such as a
+/// missing permission or a full disk, says nothing about the file and is
+/// returned as is.
+fn probe(state: &DbState) -> anyhow::Result<Health> {
+    let result = state
+        .connect()
+        .and_then(|conn| quick_check(&conn))
+        .and_then(|()| state.migrate());
+    let Err(err) = result else {
+        return Ok(Health::Ok);
+    };
+    if err.chain().any(|cause| cause.is::<CheckFailed>()) {
+        return Ok(Health::Damaged(format!("{err:#}")));
+    }
+    match sqlite_code(&err) {
+        Some(ErrorCode::DatabaseBusy | ErrorCode::DatabaseLocked) => {
+            Ok(Health::Locked(format!("{err:#}")))
+        }
+        Some(ErrorCode::DatabaseCorrupt | ErrorCode::NotADatabase) => {
+            Ok(Health::Damaged(format!("{err:#}")))
+        }
+        _ => Err(err),
+    }
+}
+
+impl DbState {
+    /// Opens the database inside `dir`, recovering it when it is damaged.
+    pub fn open_or_recover(dir: &Path) -> anyhow::Result<(Self, Option<RecoveryReport>)> {
+        let state = Self::locate(dir)?;
+        let reason = match probe(&state)? {
+            Health::Ok => return Ok((state, None)),
+            Health::Damaged(reason) => reason,
+            // Connections already wait out short locks. The file is probably
+            // fine and only cannot be checked now; quarantining it would
+            // throw good history away.
+            Health::Locked(reason) => {
+                warn!("database stayed locked during startup: {reason}");
+                let report = RecoveryReport {
+                    outcome: RecoveryOutcome::Locked,
+                    reason,
+                    quarantined: None,
+                    backup: None,
+                    salvaged_clips: 0,
+                    salvaged_rows: 0,
+                };
+                return Ok((state, Some(report)));
+            }
+        };
+        warn!("database is damaged, recovering: {reason}");
+        let report = state.recover(reason)?;
+        info!(
+            "database recovered ({:?}), {} clips salvaged",
+            report.outcome, report.salvaged_clips
+        );
+        Ok((state, Some(report)))
+    }
+
+    fn recover(&self, reason: String) -> anyhow::Result<RecoveryReport> {
+        let quarantined = self.quarantine()?;
+        let backup = self.restore_latest_intact_backup();
+        if backup.is_none() {
+            // Leftovers of a failed copy would be mistaken for a database.
+            let _ = std::fs::remove_file(self.path());
+        }
+        self.migrate()
+            .context("failed to set up a replacement database")?;
+        let (salvaged_clips, salvaged_rows) = match self.salvage_from(&quarantined) {
+            Ok(counts) => counts,
+            Err(err) => {
+                warn!("could not salvage rows from the damaged database: {err:#}");
+                (0, 0)
+            }
+        };
+        Ok(RecoveryReport {
+            outcome: if backup.is_some() {
+                RecoveryOutcome::RestoredBackup
+            } else {
+                RecoveryOutcome::Fresh
+            },
+            reason,
+            quarantined: Some(quarantined.display().to_string()),
+            backup,
+            salvaged_clips,
+            salvaged_rows,
+        })
+    }
+
+    /// Moves the database and its WAL files out of the way. Returns the new
+    /// path of the database.
+    fn quarantine(&self) -> anyhow::Result<PathBuf> {
+        let dir = self.data_dir().join(QUARANTINE_DIR);
+        std::fs::create_dir_all(&dir)
+            .with_context(|| format!("failed to create {}", dir.display()))?;
+        let stamp = Utc::now().format("%Y%m%d-%H%M%S");
+        let mut target = dir.join(format!("vibeclip_pro-{stamp}.db"));
+        let mut counter = 1;
+        while target.exists() {
+            counter += 1;
+            target = dir.join(format!("vibeclip_pro-{stamp}-{counter}.db"));
+        }
+        std::fs::rename(self.path(), &target)
+            .with_context(|| format!("failed to quarantine {}", self.path().display()))?;
+        // Committed changes may still live in the WAL, and it only applies
+        // next to a database of the same name.
+        for suffix in ["-wal", "-shm"] {
+            let mut companion = self.path().as_os_str().to_owned();
+            companion.push(suffix);
+            let mut moved = target.as_os_str().to_owned();
+            moved.push(suffix);
+            match std::fs::rename(&companion, &moved) {
+                Ok(()) => {}
+                Err(err) if err.kind() == std::io::ErrorKind::NotFound => {}
+                Err(err) => warn!("failed to quarantine {}: {err}", suffix),
+            }
+        }
+        Ok(target)
+    }
+
+    /// Copies the newest backup that passes an integrity check into place.
+    /// Returns its file name.
+    fn restore_latest_intact_backup(&self) -> Option<String> {
+        let backups = match self.list_backups() {
+            Ok(backups) => backups,
+            Err(err) => {
+                warn!("could not list backups: {err:#}");
+                return None;
+            }
+        };
+        for backup in backups {
+            let path = self.backup_dir().join(&backup.file_name);
+            let intact = Connection::open_with_flags(&path, OpenFlags::SQLITE_OPEN_READ_ONLY)
+                .map_err(anyhow::Error::from)
+                .and_then(|conn| integrity_check(&conn));
+            if let Err(err) = intact {
+                warn!("skipping backup {}: {err:#}", backup.file_name);
+                continue;
+            }
+            match std::fs::copy(&path, self.path()) {
+                Ok(_) => return Some(backup.file_name),
+                Err(err) => warn!("failed to copy backup {}: {err}", backup.file_name),
+            }
+        }
+        None
+    }
+
+    /// Copies every readable row of every table both databases share from
+    /// `source` into the live database. Returns the number of clips and of
+    /// rows in total.
+    fn salvage_from(&self, source: &Path) -> anyhow::Result<(usize, usize)> {
+        let conn = self.connect()?;
+        // Salvaged rows may point at rows that could not be read.
+        conn.execute_batch("PRAGMA foreign_keys = OFF;")?;
+        conn.execute("ATTACH DATABASE ?1 AS salvage", [source.to_string_lossy()])?;
+        let result = salvage_tables(&conn);
+        conn.execute_batch("DETACH DATABASE salvage;")?;
+        result
+    }
+}
+
+fn salvage_tables(conn: &Connection) -> anyhow::Result<(usize, usize)> {
+    let tables = conn
+        .prepare(
+            "SELECT name FROM salvage.sqlite_master WHERE type = 'table' AND name NOT LIKE 'sqlite_%'",
+        )?
+        .query_map([], |row| row.get::<_, String>(0))?
+        .collect::<Result<Vec<_>, _>>()?;
+    let mut clips = 0;
+    let mut rows = 0;
+    for table in tables {
+        let columns = shared_columns(conn, &table)?;
+        if columns.is_empty() {
+            continue;
+        }
+        let copied = match copy_table(conn, &table, &columns) {
+            Ok(copied) => copied,
+            Err(err) => {
+                warn!("failed to salvage {table}: {err:#}");
+                0
+            }
+        };
+        if copied > 0 {
+            info!("salvaged {copied} rows of {table}");
+        }
+        if table == "clips" {
+            clips = copied;
+        }
+        rows += copied;
+    }
+    Ok((clips, rows))
+}
+
+/// Columns of `table` in the live database that the damaged one has too;
+/// empty when the live database has no such table.
+fn shared_columns(conn: &Connection, table: &str) -> anyhow::Result<Vec<String>> {
+    let columns_of = |schema: &str| -> anyhow::Result<Vec<String>> {
+        Ok(conn
+            .prepare("SELECT name FROM pragma_table_info(?1, ?2)")?
+            .query_map([table, schema], |row| row.get::<_, String>(0))?
+            .collect::<Result<Vec<_>, _>>()?)
+    };
+    let salvage = columns_of("salvage")?;
+    Ok(columns_of("main")?
+        .into_iter()
+        .filter(|column| salvage.contains(column))
+        .collect())
+}
+
+fn quote(name: &str) -> String {
+    format!("\"{}\"", name.replace('"', "\"\""))
+}
+
+/// Copies `table` in one statement, or row by row when a damaged page gets
+/// in the way: forwards up to the damage, then backwards down to it.
+fn copy_table(conn: &Connection, table: &str, columns: &[String]) -> anyhow::Result<usize> {
+    let table = quote(table);
+    let list = columns
+        .iter()
+        .map(|column| quote(column))
+        .collect::<Vec<_>>()
+        .join(", ");
+    let bulk =
+        format!("INSERT OR REPLACE INTO main.{table} ({list}) SELECT {list} FROM salvage.{table}");
+    match conn.execute(&bulk, []) {
+        Ok(copied) => return Ok(copied),
+        Err(err) => warn!("copying {table} row by row: {err}"),
+    }
+    // Reads run outside any transaction: once SQLite hits a damaged page
+    // inside one, every later read in it fails as well.
+    let mut rows = read_rows(
+        conn,
+        &format!("SELECT rowid, {list} FROM salvage.{table} ORDER BY rowid"),
+        None,
+        columns.len(),
+    );
+    let floor = rows.last().map_or(i64::MIN, |(rowid, _)| *rowid);
+    rows.extend(read_rows(
+        conn,
+        &format!("SELECT rowid, {list} FROM salvage.{table} WHERE rowid > ?1 ORDER BY rowid DESC"),
+        Some(floor),
+        columns.len(),
+    ));
+    let placeholders = vec!["?"; columns.len()].join(", ");
+    let tx = conn.unchecked_transaction()?;
+    {
+        let mut insert = tx.prepare(&format!(
+            "INSERT OR REPLACE INTO main.{table} ({list}) VALUES ({placeholders})"
+        ))?;
+        for (_, values) in &rows {
+            insert.execute(rusqlite::params_from_iter(values))?;
+        }
+    }
+    tx.commit()?;
+    Ok(rows.len())
+}
+
+/// Reads rows of `select` until one cannot be read. Each row is its rowid
+/// followed by `width` values.
+fn read_rows(
+    conn: &Connection,
+    select: &str,
+    after: Option<i64>,
+    width: usize,
+) -> Vec<(i64, Vec<Value>)> {
+    let mut read = Vec::new();
+    let result = (|| -> rusqlite::Result<()> {
+        let mut select = conn.prepare(select)?;
+        let mut rows = match after {
+            Some(after) => select.query([after])?,
+            None => select.query([])?,
+        };
+        while let Some(row) = rows.next()? {
+            let values = (1..=width)
+                .map(|index| row.get::<_, Value>(index))
+                .collect::<Result<Vec<_>, _>>()?;
+            read.push((row.get::<_, i64>(0)?, values));
+        }
+        Ok(())
+    })();
+    if let Err(err) = result {
+        warn!("stopped reading after {} rows: {err}", read.len());
+    }
+    read
+}
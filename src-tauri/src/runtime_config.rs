@@ -1,9 +1,12 @@
+use std::path::PathBuf;
 use std::sync::{Arc, RwLock};
 
+use anyhow::Context;
 use serde::{Deserialize, Serialize};
+use tracing::warn;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
-#[serde(default)]
+#[serde(default, rename_all = "camelCase")]
 pub struct RuntimePreferences {
     pub dedupe_enabled: bool,
     pub debounce_interval_ms: u64,
@@ -11,6 +14,17 @@ pub struct RuntimePreferences {
     pub ignored_keywords: Vec<String>,
     pub retention: RetentionPolicy,
     pub log_level: String,
+    pub ai_cache: AiCachePolicy,
+    pub local_ai: LocalAiProfile,
+    pub ai_pricing: AiPricing,
+    pub ai_batch: AiBatchPolicy,
+    pub translation_pair: TranslationPair,
+    pub local_api: LocalApiConfig,
+    pub lan_sync: LanSyncConfig,
+    pub remote_sync: RemoteSyncConfig,
+    /// How many operations the undo stack keeps; `0` disables undo.
+    pub undo_depth: usize,
+    pub backup: BackupPolicy,
 }
 
 impl Default for RuntimePreferences {
@@ -22,12 +36,22 @@ impl Default for RuntimePreferences {
             ignored_keywords: Vec::new(),
             retention: RetentionPolicy::default(),
             log_level: "info".to_string(),
+            ai_cache: AiCachePolicy::default(),
+            local_ai: LocalAiProfile::default(),
+            ai_pricing: AiPricing::default(),
+            ai_batch: AiBatchPolicy::default(),
+            translation_pair: TranslationPair::default(),
+            local_api: LocalApiConfig::default(),
+            lan_sync: LanSyncConfig::default(),
+            remote_sync: RemoteSyncConfig::default(),
+            undo_depth: 20,
+            backup: BackupPolicy::default(),
         }
     }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
-#[serde(default)]
+#[serde(default, rename_all = "camelCase")]
 pub struct RetentionPolicy {
     pub max_entries: Option<usize>,
     pub max_age_days: Option<u32>,
@@ -44,20 +68,268 @@ impl Default for RetentionPolicy {
     }
 }
 
-#[derive(Debug, Clone)]
-pub struct RuntimeConfigState {
-    inner: Arc<RwLock<RuntimePreferences>>,
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct AiCachePolicy {
+    pub enabled: bool,
+    /// Entries older than this are treated as misses; `0` keeps them forever.
+    pub ttl_hours: u32,
+    pub max_entries: usize,
+}
+
+impl Default for AiCachePolicy {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            ttl_hours: 24 * 7,
+            max_entries: 500,
+        }
+    }
+}
+
+impl AiCachePolicy {
+    pub fn max_age(&self) -> Option<chrono::Duration> {
+        (self.ttl_hours > 0).then(|| chrono::Duration::hours(self.ttl_hours as i64))
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct AiBatchPolicy {
+    pub concurrency: usize,
+    /// Upper bound for request starts within a batch; `0` disables the limit.
+    pub requests_per_minute: u32,
+}
+
+impl Default for AiBatchPolicy {
+    fn default() -> Self {
+        Self {
+            concurrency: 3,
+            requests_per_minute: 30,
+        }
+    }
+}
+
+/// Languages used when a translation targets `auto`: text detected as
+/// `primary` is translated to `secondary`, anything else to `primary`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct TranslationPair {
+    pub primary: String,
+    pub secondary: String,
+}
+
+impl Default for TranslationPair {
+    fn default() -> Self {
+        Self {
+            primary: "zh-CN".to_string(),
+            secondary: "en".to_string(),
+        }
+    }
+}
+
+/// Automatic database backups.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct BackupPolicy {
+    pub enabled: bool,
+    pub interval_hours: u32,
+    /// Newest backup of each of this many recent days is kept.
+    pub keep_daily: usize,
+    /// Newest backup of each of this many recent weeks is kept.
+    pub keep_weekly: usize,
+}
+
+impl Default for BackupPolicy {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            interval_hours: 24,
+            keep_daily: 7,
+            keep_weekly: 4,
+        }
+    }
+}
+
+/// HTTP API for scripts, bound to 127.0.0.1 only.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct LocalApiConfig {
+    pub enabled: bool,
+    pub port: u16,
+}
+
+impl Default for LocalApiConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            port: 38217,
+        }
+    }
 }
 
-impl Default for RuntimeConfigState {
+/// Encrypted sync with paired devices on the local network.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct LanSyncConfig {
+    pub enabled: bool,
+    /// `0` lets the OS pick a port; peers find it through mDNS.
+    pub port: u16,
+    pub device_name: Option<String>,
+    /// Minutes between automatic syncs with all paired devices, `0` disables.
+    pub interval_minutes: u32,
+}
+
+impl Default for LanSyncConfig {
     fn default() -> Self {
         Self {
-            inner: Arc::new(RwLock::new(RuntimePreferences::default())),
+            enabled: false,
+            port: 0,
+            device_name: None,
+            interval_minutes: 15,
         }
     }
 }
 
+/// End-to-end encrypted sync through a WebDAV server or S3-compatible bucket.
+/// The passphrase and backend credentials are kept in the secret store.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct RemoteSyncConfig {
+    pub enabled: bool,
+    pub backend: RemoteBackendConfig,
+    /// Minutes between automatic syncs, `0` only syncs on demand.
+    pub interval_minutes: u32,
+}
+
+impl Default for RemoteSyncConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            backend: RemoteBackendConfig::default(),
+            interval_minutes: 30,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(
+    tag = "type",
+    rename_all = "lowercase",
+    rename_all_fields = "camelCase"
+)]
+pub enum RemoteBackendConfig {
+    WebDav {
+        url: String,
+        #[serde(default)]
+        username: String,
+    },
+    S3 {
+        endpoint: String,
+        #[serde(default)]
+        region: String,
+        bucket: String,
+        #[serde(default)]
+        prefix: String,
+        access_key_id: String,
+        /// `https://endpoint/bucket/key` instead of `https://bucket.endpoint/key`;
+        /// MinIO and most self-hosted servers need this.
+        #[serde(default = "default_path_style")]
+        path_style: bool,
+    },
+}
+
+impl Default for RemoteBackendConfig {
+    fn default() -> Self {
+        RemoteBackendConfig::WebDav {
+            url: String::new(),
+            username: String::new(),
+        }
+    }
+}
+
+fn default_path_style() -> bool {
+    true
+}
+
+/// OpenAI-compatible server on this machine (Ollama, llama.cpp server, ...)
+/// used for AI actions while offline mode is on. An API key, if the server
+/// needs one, is kept in the secret store.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct LocalAiProfile {
+    pub enabled: bool,
+    pub base_url: String,
+    pub model: String,
+}
+
+impl Default for LocalAiProfile {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            base_url: "http://127.0.0.1:11434".to_string(),
+            model: "llama3.2".to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct AiPricing {
+    pub models: Vec<ModelPrice>,
+    /// Remote AI calls are refused once this month's cost reaches the limit.
+    pub monthly_budget_usd: Option<f64>,
+}
+
+/// USD per one million tokens. `model` matches exactly or as a prefix, the
+/// longest match wins (`gpt-4o-mini` before `gpt-4o`).
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ModelPrice {
+    pub model: String,
+    pub prompt_per_million: f64,
+    pub completion_per_million: f64,
+}
+
+impl AiPricing {
+    pub fn price_for(&self, model: &str) -> Option<&ModelPrice> {
+        self.models
+            .iter()
+            .filter(|price| !price.model.is_empty() && model.starts_with(price.model.as_str()))
+            .max_by_key(|price| price.model.len())
+    }
+}
+
+/// Preferences shared by every part of the backend. They are saved next to
+/// the database, so the servers they configure come back after a restart
+/// before any window has loaded.
+#[derive(Debug, Clone)]
+pub struct RuntimeConfigState {
+    inner: Arc<RwLock<RuntimePreferences>>,
+    path: PathBuf,
+}
+
 impl RuntimeConfigState {
+    /// Reads the saved preferences; a missing or unreadable file yields the
+    /// defaults.
+    pub fn load(path: PathBuf) -> Self {
+        let prefs = match std::fs::read(&path) {
+            Ok(raw) => serde_json::from_slice(&raw).unwrap_or_else(|err| {
+                warn!("ignoring invalid {}: {err}", path.display());
+                RuntimePreferences::default()
+            }),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => RuntimePreferences::default(),
+            Err(err) => {
+                warn!("failed to read {}: {err}", path.display());
+                RuntimePreferences::default()
+            }
+        };
+        Self {
+            inner: Arc::new(RwLock::new(prefs)),
+            path,
+        }
+    }
+
     pub fn get(&self) -> RuntimePreferences {
         self.inner
             .read()
@@ -65,9 +337,39 @@ impl RuntimeConfigState {
             .unwrap_or_default()
     }
 
-    pub fn update(&self, prefs: RuntimePreferences) {
-        if let Ok(mut state) = self.inner.write() {
-            *state = prefs;
+    /// Applies a partial update: objects are merged key by key, everything
+    /// else replaces the current value. The result is saved and returned.
+    pub fn merge(&self, patch: serde_json::Value) -> anyhow::Result<RuntimePreferences> {
+        let mut state = self
+            .inner
+            .write()
+            .map_err(|_| anyhow::anyhow!("runtime preferences lock poisoned"))?;
+        let mut merged = serde_json::to_value(&*state)?;
+        merge_json(&mut merged, patch);
+        let prefs: RuntimePreferences =
+            serde_json::from_value(merged).context("invalid runtime preferences")?;
+        let partial = self.path.with_extension("json.partial");
+        std::fs::write(&partial, serde_json::to_vec_pretty(&prefs)?)
+            .with_context(|| format!("failed to write {}", partial.display()))?;
+        std::fs::rename(&partial, &self.path)
+            .with_context(|| format!("failed to save {}", self.path.display()))?;
+        *state = prefs.clone();
+        Ok(prefs)
+    }
+}
+
+fn merge_json(target: &mut serde_json::Value, patch: serde_json::Value) {
+    match (target, patch) {
+        (serde_json::Value::Object(target), serde_json::Value::Object(patch)) => {
+            for (key, value) in patch {
+                match target.get_mut(&key) {
+                    Some(current) => merge_json(current, value),
+                    None => {
+                        target.insert(key, value);
+                    }
+                }
+            }
         }
+        (target, patch) => *target = patch,
     }
 }
@@ -0,0 +1,165 @@
+//! Secrets encrypted at rest next to the history.
+//!
+//! API keys, passwords and the sync passphrase are sealed with a key kept in
+//! a separate owner-only file, so a copied database or backup does not leak
+//! them. The webview can set or clear a secret but never read it back.
+
+use std::io::Write;
+use std::path::Path;
+
+use anyhow::{Context, Result};
+use chacha20poly1305::aead::{Aead, AeadCore, KeyInit, OsRng};
+use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce};
+use chrono::Utc;
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::{Deserialize, Serialize};
+
+use crate::db::{datetime_to_timestamp, DbState};
+
+const KEY_FILE_NAME: &str = "provider_secrets.key";
+
+/// Secrets that belong to runtime preferences but are kept out of them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum SecretName {
+    LocalAiApiKey,
+    RemoteSyncPassphrase,
+    WebDavPassword,
+    S3SecretAccessKey,
+}
+
+impl SecretName {
+    const ALL: [SecretName; 4] = [
+        SecretName::LocalAiApiKey,
+        SecretName::RemoteSyncPassphrase,
+        SecretName::WebDavPassword,
+        SecretName::S3SecretAccessKey,
+    ];
+
+    fn as_str(&self) -> &'static str {
+        match self {
+            SecretName::LocalAiApiKey => "local_ai.api_key",
+            SecretName::RemoteSyncPassphrase => "remote_sync.passphrase",
+            SecretName::WebDavPassword => "remote_sync.webdav_password",
+            SecretName::S3SecretAccessKey => "remote_sync.s3_secret_access_key",
+        }
+    }
+
+    pub fn is_remote_sync(&self) -> bool {
+        !matches!(self, SecretName::LocalAiApiKey)
+    }
+}
+
+pub(crate) fn migrate(conn: &Connection) -> Result<()> {
+    conn.execute_batch(
+        r#"
+        CREATE TABLE IF NOT EXISTS secrets (
+            name TEXT PRIMARY KEY,
+            cipher BLOB NOT NULL,
+            nonce BLOB NOT NULL,
+            updated_at INTEGER NOT NULL
+        );
+        "#,
+    )?;
+    Ok(())
+}
+
+impl DbState {
+    pub fn secret(&self, name: SecretName) -> Result<Option<String>> {
+        let sealed = self.read(|conn| {
+            Ok(conn
+                .query_row(
+                    "SELECT cipher, nonce FROM secrets WHERE name = ?1",
+                    params![name.as_str()],
+                    |row| Ok((row.get::<_, Vec<u8>>(0)?, row.get::<_, Vec<u8>>(1)?)),
+                )
+                .optional()?)
+        })?;
+        sealed
+            .map(|(cipher, nonce)| self.decrypt_secret(&cipher, &nonce))
+            .transpose()
+    }
+
+    /// Stores `value`; `None` or an empty string removes the secret.
+    pub fn set_secret(&self, name: SecretName, value: Option<&str>) -> Result<()> {
+        let sealed = match value.map(str::trim).filter(|value| !value.is_empty()) {
+            Some(value) => Some(self.encrypt_secret(value)?),
+            None => None,
+        };
+        let now = datetime_to_timestamp(Utc::now());
+        self.write(move |conn| {
+            match sealed {
+                Some((cipher, nonce)) => conn.execute(
+                    "INSERT OR REPLACE INTO secrets (name, cipher, nonce, updated_at) VALUES (?1, ?2, ?3, ?4)",
+                    params![name.as_str(), cipher, nonce, now],
+                )?,
+                None => conn.execute(
+                    "DELETE FROM secrets WHERE name = ?1",
+                    params![name.as_str()],
+                )?,
+            };
+            Ok(())
+        })
+    }
+
+    /// Which secrets are set, for the settings UI.
+    pub fn stored_secrets(&self) -> Result<Vec<SecretName>> {
+        let names = self.read(|conn| {
+            let mut stmt = conn.prepare("SELECT name FROM secrets")?;
+            let rows = stmt.query_map([], |row| row.get::<_, String>(0))?;
+            Ok(rows.collect::<Result<Vec<_>, _>>()?)
+        })?;
+        Ok(SecretName::ALL
+            .into_iter()
+            .filter(|secret| names.iter().any(|name| name == secret.as_str()))
+            .collect())
+    }
+
+    pub(crate) fn encrypt_secret(&self, secret: &str) -> Result<(Vec<u8>, Vec<u8>)> {
+        let cipher = ChaCha20Poly1305::new(&load_or_create_key(self.data_dir())?);
+        let nonce = ChaCha20Poly1305::generate_nonce(&mut OsRng);
+        let encrypted = cipher
+            .encrypt(&nonce, secret.as_bytes())
+            .map_err(|_| anyhow::anyhow!("failed to encrypt secret"))?;
+        Ok((encrypted, nonce.to_vec()))
+    }
+
+    pub(crate) fn decrypt_secret(&self, encrypted: &[u8], nonce: &[u8]) -> Result<String> {
+        if nonce.len() != 12 {
+            anyhow::bail!("stored secret is corrupted");
+        }
+        let cipher = ChaCha20Poly1305::new(&load_or_create_key(self.data_dir())?);
+        let plain = cipher
+            .decrypt(Nonce::from_slice(nonce), encrypted)
+            .map_err(|_| anyhow::anyhow!("failed to decrypt secret; was the key file replaced?"))?;
+        String::from_utf8(plain).context("stored secret is not valid UTF-8")
+    }
+}
+
+/// Reads the 256-bit encryption key next to the database, creating it with
+/// owner-only permissions on first use.
+fn load_or_create_key(data_dir: &Path) -> Result<Key> {
+    let path = data_dir.join(KEY_FILE_NAME);
+    if path.exists() {
+        let bytes = std::fs::read(&path).context("failed to read secret key file")?;
+        if bytes.len() != 32 {
+            anyhow::bail!("secret key file {} is corrupted", path.display());
+        }
+        return Ok(*Key::from_slice(&bytes));
+    }
+
+    let key = ChaCha20Poly1305::generate_key(&mut OsRng);
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    let mut file = options
+        .open(&path)
+        .context("failed to create secret key file")?;
+    file.write_all(key.as_slice())?;
+    file.sync_all()?;
+    Ok(key)
+}
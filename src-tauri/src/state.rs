@@ -7,6 +7,8 @@ use std::sync::{
 pub struct AppStatus {
     listening: AtomicBool,
     offline: AtomicBool,
+    /// Set while the database is being swapped, independent of `listening`.
+    capture_suspended: AtomicBool,
     self_copies: Mutex<VecDeque<String>>,
 }
 
@@ -15,6 +17,7 @@ impl Default for AppStatus {
         Self {
             listening: AtomicBool::new(true),
             offline: AtomicBool::new(false),
+            capture_suspended: AtomicBool::new(false),
             self_copies: Mutex::new(VecDeque::with_capacity(8)),
         }
     }
@@ -37,6 +40,14 @@ impl AppStatus {
         self.offline.store(value, Ordering::SeqCst);
     }
 
+    pub fn capture_suspended(&self) -> bool {
+        self.capture_suspended.load(Ordering::SeqCst)
+    }
+
+    pub fn set_capture_suspended(&self, value: bool) {
+        self.capture_suspended.store(value, Ordering::SeqCst);
+    }
+
     pub fn toggle_listening(&self) -> bool {
         let previous = self.listening.fetch_xor(true, Ordering::SeqCst);
         !previous
@@ -0,0 +1,672 @@
+//! Database side of LAN sync: paired peers, change feeds and merging.
+//!
+//! A clip is identified across devices by its sync id, which is the content
+//! hash it was first captured with. Editing a clip freezes that id in the
+//! `sync_id` column so the edited version still replaces the original on the
+//! other devices.
+
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use rusqlite::{params, Connection, OptionalExtension, Row};
+use serde::{Deserialize, Serialize};
+
+use crate::clip_events::{self, ClipOp, ClipOrigin};
+use crate::db::{datetime_to_timestamp, timestamp_to_datetime, ClipKind, DbState};
+use crate::hash::compute_content_hash;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SyncPeer {
+    pub device_id: String,
+    pub name: String,
+    #[serde(skip)]
+    pub public_key: [u8; 32],
+    pub last_address: Option<String>,
+    pub paired_at: DateTime<Utc>,
+    pub last_synced_at: Option<DateTime<Utc>>,
+    #[serde(skip)]
+    pub pulled_cursor: i64,
+    #[serde(skip)]
+    pub pushed_cursor: i64,
+}
+
+/// A clip as exchanged between devices.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SyncClip {
+    pub sync_id: String,
+    pub kind: ClipKind,
+    pub content: String,
+    pub content_hash: String,
+    pub preview: Option<String>,
+    pub extra: Option<String>,
+    pub is_pinned: bool,
+    pub is_favorite: bool,
+    pub created_at: i64,
+    pub updated_at: i64,
+    pub flags_modified_at: i64,
+    pub content_modified_at: i64,
+    pub language: Option<String>,
+}
+
+/// Identity of one version of a clip: sync id, content and flags.
+pub type ClipVersion = (String, String, bool, bool);
+
+impl SyncClip {
+    /// Used to leave out clips that would only echo back what was just received.
+    pub fn version(&self) -> ClipVersion {
+        (
+            self.sync_id.clone(),
+            self.content_hash.clone(),
+            self.is_pinned,
+            self.is_favorite,
+        )
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+pub struct MergeStats {
+    pub inserted: usize,
+    pub updated: usize,
+    /// Concurrent edits; the losing version is kept as a separate clip.
+    pub conflicts: usize,
+}
+
+impl MergeStats {
+    pub fn changed(&self) -> bool {
+        self.inserted + self.updated + self.conflicts > 0
+    }
+}
+
+pub(crate) fn migrate(conn: &Connection) -> Result<()> {
+    conn.execute_batch(
+        r#"
+        CREATE TABLE IF NOT EXISTS sync_peers (
+            device_id TEXT PRIMARY KEY,
+            name TEXT NOT NULL,
+            public_key BLOB NOT NULL,
+            last_address TEXT,
+            pulled_cursor INTEGER NOT NULL DEFAULT 0,
+            pushed_cursor INTEGER NOT NULL DEFAULT 0,
+            paired_at INTEGER NOT NULL,
+            last_synced_at INTEGER
+        );
+        "#,
+    )?;
+    let mut stmt = conn.prepare("PRAGMA table_info(clips)")?;
+    let columns = stmt
+        .query_map([], |row| row.get::<_, String>(1))?
+        .collect::<Result<Vec<_>, _>>()?;
+    for (name, definition) in [
+        ("sync_id", "TEXT"),
+        ("flags_modified_at", "INTEGER NOT NULL DEFAULT 0"),
+        ("content_modified_at", "INTEGER NOT NULL DEFAULT 0"),
+    ] {
+        if !columns.iter().any(|column| column == name) {
+            conn.execute(
+                &format!("ALTER TABLE clips ADD COLUMN {name} {definition}"),
+                [],
+            )?;
+        }
+    }
+    conn.execute(
+        "CREATE INDEX IF NOT EXISTS idx_clips_sync_id ON clips(sync_id)",
+        [],
+    )?;
+    Ok(())
+}
+
+const SYNC_CLIP_COLUMNS: &str = "COALESCE(sync_id, content_hash), kind, content, content_hash, preview, extra, is_pinned, is_favorite, created_at, updated_at, flags_modified_at, content_modified_at, language";
+
+impl DbState {
+    pub fn list_sync_peers(&self) -> Result<Vec<SyncPeer>> {
+        self.read(|conn| {
+            let mut stmt = conn.prepare(
+                "SELECT device_id, name, public_key, last_address, paired_at, last_synced_at, pulled_cursor, pushed_cursor FROM sync_peers ORDER BY name",
+            )?;
+            let rows = stmt.query_map([], map_peer_row)?;
+            Ok(rows.collect::<Result<Vec<_>, _>>()?)
+        })
+    }
+
+    pub fn get_sync_peer(&self, device_id: &str) -> Result<Option<SyncPeer>> {
+        self.read(|conn| load_peer(conn, device_id))
+    }
+
+    /// Stores a newly paired device. Pairing again replaces the key and
+    /// restarts incremental sync from scratch.
+    pub fn save_sync_peer(
+        &self,
+        device_id: &str,
+        name: &str,
+        public_key: &[u8; 32],
+        address: Option<&str>,
+    ) -> Result<SyncPeer> {
+        let (device_id, name, public_key, address) = (
+            device_id.to_owned(),
+            name.to_owned(),
+            *public_key,
+            address.map(str::to_owned),
+        );
+        self.write(move |conn| {
+            conn.execute(
+                "INSERT INTO sync_peers (device_id, name, public_key, last_address, paired_at) VALUES (?1, ?2, ?3, ?4, ?5) \
+                 ON CONFLICT(device_id) DO UPDATE SET name = excluded.name, public_key = excluded.public_key, \
+                 last_address = COALESCE(excluded.last_address, last_address), pulled_cursor = 0, pushed_cursor = 0, paired_at = excluded.paired_at",
+                params![
+                    device_id,
+                    name,
+                    public_key.as_slice(),
+                    address,
+                    datetime_to_timestamp(Utc::now())
+                ],
+            )?;
+            load_peer(conn, &device_id)?.ok_or_else(|| anyhow::anyhow!("failed to load sync peer"))
+        })
+    }
+
+    pub fn delete_sync_peer(&self, device_id: &str) -> Result<()> {
+        let device_id = device_id.to_owned();
+        self.write(move |conn| {
+            conn.execute(
+                "DELETE FROM sync_peers WHERE device_id = ?1",
+                params![device_id],
+            )?;
+            Ok(())
+        })
+    }
+
+    pub fn record_sync_progress(
+        &self,
+        device_id: &str,
+        address: Option<&str>,
+        pulled_cursor: Option<i64>,
+        pushed_cursor: Option<i64>,
+    ) -> Result<()> {
+        let (device_id, address) = (device_id.to_owned(), address.map(str::to_owned));
+        self.write(move |conn| {
+            conn.execute(
+                "UPDATE sync_peers SET last_address = COALESCE(?2, last_address), \
+                 pulled_cursor = COALESCE(?3, pulled_cursor), pushed_cursor = COALESCE(?4, pushed_cursor), \
+                 last_synced_at = ?5 WHERE device_id = ?1",
+                params![
+                    device_id,
+                    address,
+                    pulled_cursor,
+                    pushed_cursor,
+                    datetime_to_timestamp(Utc::now())
+                ],
+            )?;
+            Ok(())
+        })
+    }
+
+    /// Clips changed after `since` (local clock) and the cursor to pass next
+    /// time. Rows touched within the current second are included again on
+    /// the next round, which the merge tolerates.
+    pub fn sync_changes_since(&self, since: i64) -> Result<(Vec<SyncClip>, i64)> {
+        let (clips, cursor, _) = self.sync_changes_batch(since, usize::MAX)?;
+        Ok((clips, cursor))
+    }
+
+    /// Like `sync_changes_since`, but stops once the clips add up to about
+    /// `max_bytes`. Clips sharing the last `updated_at` travel together so
+    /// the cursor never skips one; the flag tells whether more remain.
+    pub fn sync_changes_batch(
+        &self,
+        since: i64,
+        max_bytes: usize,
+    ) -> Result<(Vec<SyncClip>, i64, bool)> {
+        let now_cursor = datetime_to_timestamp(Utc::now()) - 1;
+        self.read(|conn| {
+            let mut stmt = conn.prepare(&format!(
+                "SELECT {SYNC_CLIP_COLUMNS} FROM clips WHERE updated_at > ?1 ORDER BY updated_at ASC, id ASC"
+            ))?;
+            let rows = stmt.query_map(params![since], map_sync_clip_row)?;
+            let mut clips: Vec<SyncClip> = Vec::new();
+            let mut bytes = 0usize;
+            for clip in rows {
+                let clip = clip?;
+                if bytes >= max_bytes
+                    && clips.last().is_some_and(|last| last.updated_at != clip.updated_at)
+                {
+                    // Stop before this clip; a cursor that has not reached
+                    // the present yet is safe to resume from.
+                    let cursor = clips.last().map_or(since, |last| last.updated_at);
+                    let cursor = cursor.min(now_cursor);
+                    if cursor > since {
+                        return Ok((clips, cursor, true));
+                    }
+                }
+                bytes = bytes.saturating_add(sync_clip_size(&clip));
+                clips.push(clip);
+            }
+            Ok((clips, now_cursor.max(since), false))
+        })
+    }
+
+    /// Merges clips received from a peer. `unsynced_since` is the local
+    /// cursor up to which the peer has already seen our changes; local edits
+    /// after it that collide with remote edits count as conflicts.
+    ///
+    /// Rows the merge inserts or changes get the local time as `updated_at`,
+    /// so they show up in our own change feed and travel on to devices that
+    /// only sync with us. `created_at` and the edit timestamps stay the
+    /// peer's.
+    pub fn apply_sync_clips(
+        &self,
+        clips: Vec<SyncClip>,
+        unsynced_since: i64,
+    ) -> Result<MergeStats> {
+        self.write(move |conn| {
+            let tx = conn.transaction()?;
+            let now = datetime_to_timestamp(Utc::now());
+            let mut stats = MergeStats::default();
+            for remote in clips {
+                if compute_content_hash(remote.kind, &remote.content) != remote.content_hash {
+                    tracing::warn!(
+                        "skipping synced clip {} with mismatched hash",
+                        remote.sync_id
+                    );
+                    continue;
+                }
+                let local = tx
+                    .query_row(
+                        &format!(
+                            "SELECT id, {SYNC_CLIP_COLUMNS} FROM clips \
+                             WHERE COALESCE(sync_id, content_hash) = ?1 OR content_hash = ?2 \
+                             ORDER BY COALESCE(sync_id, content_hash) = ?1 DESC LIMIT 1"
+                        ),
+                        params![remote.sync_id, remote.content_hash],
+                        |row| Ok((row.get::<_, i64>(0)?, map_sync_clip_columns(row, 1)?)),
+                    )
+                    .optional()?;
+
+                let Some((id, local)) = local else {
+                    insert_sync_clip(&tx, &remote, Some(&remote.sync_id), now)?;
+                    stats.inserted += 1;
+                    continue;
+                };
+
+                let mut changed = false;
+                let (mut pinned, mut favorite, mut flags_at) =
+                    (local.is_pinned, local.is_favorite, local.flags_modified_at);
+                if remote.flags_modified_at > local.flags_modified_at {
+                    pinned = remote.is_pinned;
+                    favorite = remote.is_favorite;
+                    flags_at = remote.flags_modified_at;
+                } else if remote.flags_modified_at == 0 && local.flags_modified_at == 0 {
+                    // Never toggled on either side: keep a pin or star from either.
+                    pinned |= remote.is_pinned;
+                    favorite |= remote.is_favorite;
+                }
+                if (pinned, favorite) != (local.is_pinned, local.is_favorite) {
+                    changed = true;
+                }
+
+                let mut content = None;
+                if remote.content_hash != local.content_hash {
+                    let local_edit_unseen = local.content_modified_at > unsynced_since;
+                    if remote.content_modified_at > local.content_modified_at {
+                        if local_edit_unseen {
+                            // Both sides edited: the newer edit wins, ours is kept aside.
+                            insert_conflict_copy(&tx, &local, id, now)?;
+                            stats.conflicts += 1;
+                        }
+                        content = Some(&remote);
+                    } else if remote.content_modified_at > 0 && local_edit_unseen {
+                        insert_conflict_copy(&tx, &remote, id, now)?;
+                        stats.conflicts += 1;
+                    }
+                }
+
+                match content {
+                    Some(winner) => {
+                        tx.execute(
+                            "UPDATE clips SET sync_id = ?1, content = ?2, content_hash = ?3, preview = ?4, extra = ?5, \
+                             content_modified_at = ?6, language = ?7, is_pinned = ?8, is_favorite = ?9, \
+                             flags_modified_at = ?10, updated_at = ?11 WHERE id = ?12",
+                            params![
+                                local.sync_id,
+                                winner.content,
+                                winner.content_hash,
+                                winner.preview,
+                                winner.extra,
+                                winner.content_modified_at,
+                                winner.language,
+                                pinned as i64,
+                                favorite as i64,
+                                flags_at,
+                                now,
+                                id
+                            ],
+                        )?;
+                        clip_events::record(
+                            &tx,
+                            ClipOp::UpdateContent,
+                            Some(id),
+                            Some(&winner.content_hash),
+                            ClipOrigin::Sync,
+                            Some(serde_json::json!({ "previousHash": local.content_hash })),
+                        )?;
+                        stats.updated += 1;
+                    }
+                    None if changed || flags_at != local.flags_modified_at => {
+                        tx.execute(
+                            "UPDATE clips SET is_pinned = ?1, is_favorite = ?2, flags_modified_at = ?3, updated_at = ?4 WHERE id = ?5",
+                            params![pinned as i64, favorite as i64, flags_at, now, id],
+                        )?;
+                        clip_events::record(
+                            &tx,
+                            ClipOp::UpdateFlags,
+                            Some(id),
+                            Some(&local.content_hash),
+                            ClipOrigin::Sync,
+                            Some(serde_json::json!({ "pinned": pinned, "favorite": favorite })),
+                        )?;
+                        stats.updated += 1;
+                    }
+                    None => {}
+                }
+            }
+            tx.commit()?;
+            Ok(stats)
+        })
+    }
+}
+
+/// Inserts a clip from a peer, stamped with the local `updated_at`.
+fn insert_sync_clip(
+    conn: &Connection,
+    clip: &SyncClip,
+    sync_id: Option<&str>,
+    updated_at: i64,
+) -> Result<()> {
+    conn.execute(
+        "INSERT INTO clips (kind, content, content_hash, preview, extra, is_pinned, is_favorite, created_at, updated_at, language, sync_id, flags_modified_at, content_modified_at) \
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13)",
+        params![
+            i64::from(clip.kind),
+            clip.content,
+            clip.content_hash,
+            clip.preview,
+            clip.extra,
+            clip.is_pinned as i64,
+            clip.is_favorite as i64,
+            clip.created_at,
+            updated_at,
+            clip.language,
+            sync_id.filter(|id| *id != clip.content_hash),
+            clip.flags_modified_at,
+            clip.content_modified_at
+        ],
+    )?;
+    clip_events::record(
+        conn,
+        ClipOp::Insert,
+        Some(conn.last_insert_rowid()),
+        Some(&clip.content_hash),
+        ClipOrigin::Sync,
+        None,
+    )?;
+    Ok(())
+}
+
+/// Saves the losing side of an edit conflict as its own clip, unless that
+/// content is already stored in a row other than `merged_id`, the one the
+/// winner is about to overwrite.
+fn insert_conflict_copy(
+    conn: &Connection,
+    clip: &SyncClip,
+    merged_id: i64,
+    now: i64,
+) -> Result<()> {
+    let exists: Option<i64> = conn
+        .query_row(
+            "SELECT id FROM clips WHERE content_hash = ?1 AND id != ?2 LIMIT 1",
+            params![clip.content_hash, merged_id],
+            |row| row.get(0),
+        )
+        .optional()?;
+    if exists.is_some() {
+        return Ok(());
+    }
+    let copy = SyncClip {
+        content_modified_at: now,
+        ..clip.clone()
+    };
+    insert_sync_clip(conn, &copy, None, now)
+}
+
+fn load_peer(conn: &Connection, device_id: &str) -> Result<Option<SyncPeer>> {
+    Ok(conn
+        .query_row(
+            "SELECT device_id, name, public_key, last_address, paired_at, last_synced_at, pulled_cursor, pushed_cursor FROM sync_peers WHERE device_id = ?1",
+            params![device_id],
+            map_peer_row,
+        )
+        .optional()?)
+}
+
+fn map_peer_row(row: &Row<'_>) -> rusqlite::Result<SyncPeer> {
+    let key: Vec<u8> = row.get(2)?;
+    let mut public_key = [0u8; 32];
+    if key.len() == 32 {
+        public_key.copy_from_slice(&key);
+    }
+    Ok(SyncPeer {
+        device_id: row.get(0)?,
+        name: row.get(1)?,
+        public_key,
+        last_address: row.get(3)?,
+        paired_at: timestamp_to_datetime(row.get(4)?),
+        last_synced_at: row.get::<_, Option<i64>>(5)?.map(timestamp_to_datetime),
+        pulled_cursor: row.get(6)?,
+        pushed_cursor: row.get(7)?,
+    })
+}
+
+/// Rough serialized size of a clip, for batching.
+fn sync_clip_size(clip: &SyncClip) -> usize {
+    clip.content.len()
+        + clip.preview.as_ref().map_or(0, String::len)
+        + clip.extra.as_ref().map_or(0, String::len)
+        + 512
+}
+
+fn map_sync_clip_row(row: &Row<'_>) -> rusqlite::Result<SyncClip> {
+    map_sync_clip_columns(row, 0)
+}
+
+fn map_sync_clip_columns(row: &Row<'_>, offset: usize) -> rusqlite::Result<SyncClip> {
+    Ok(SyncClip {
+        sync_id: row.get(offset)?,
+        kind: row.get(offset + 1)?,
+        content: row.get(offset + 2)?,
+        content_hash: row.get(offset + 3)?,
+        preview: row.get(offset + 4)?,
+        extra: row.get(offset + 5)?,
+        is_pinned: row.get::<_, i64>(offset + 6)? == 1,
+        is_favorite: row.get::<_, i64>(offset + 7)? == 1,
+        created_at: row.get(offset + 8)?,
+        updated_at: row.get(offset + 9)?,
+        flags_modified_at: row.get(offset + 10)?,
+        content_modified_at: row.get(offset + 11)?,
+        language: row.get(offset + 12)?,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clipboard::finalize_payload;
+    use crate::db::{ClipPayload, TestDb};
+
+    /// A timestamp from the peer's clock, well in the past.
+    const PEER_TIME: i64 = 1_600_000_000;
+
+    fn remote(content: &str) -> SyncClip {
+        let content_hash = compute_content_hash(ClipKind::Text, content);
+        SyncClip {
+            sync_id: content_hash.clone(),
+            kind: ClipKind::Text,
+            content: content.to_string(),
+            content_hash,
+            preview: None,
+            extra: None,
+            is_pinned: false,
+            is_favorite: false,
+            created_at: PEER_TIME,
+            updated_at: PEER_TIME,
+            flags_modified_at: 0,
+            content_modified_at: 0,
+            language: None,
+        }
+    }
+
+    fn add_local(db: &DbState, content: &str) -> i64 {
+        let payload = finalize_payload(ClipPayload {
+            kind: ClipKind::Text,
+            content: content.to_string(),
+            preview: None,
+            extra: None,
+            content_hash: None,
+            is_pinned: false,
+            is_favorite: false,
+            source_app: None,
+        });
+        db.upsert(payload, ClipOrigin::Ui).unwrap().id
+    }
+
+    /// Backdates a local row and sets its merge timestamps.
+    fn set_local(db: &DbState, id: i64, pinned: bool, flags_at: i64, content_at: i64) {
+        db.write(move |conn| {
+            conn.execute(
+                "UPDATE clips SET is_pinned = ?1, flags_modified_at = ?2, content_modified_at = ?3, updated_at = ?4 WHERE id = ?5",
+                params![pinned as i64, flags_at, content_at, PEER_TIME, id],
+            )?;
+            Ok(())
+        })
+        .unwrap();
+    }
+
+    fn local_row(db: &DbState, sync_id: &str) -> SyncClip {
+        let (clips, _) = db.sync_changes_since(0).unwrap();
+        clips
+            .into_iter()
+            .find(|clip| clip.sync_id == sync_id)
+            .expect("clip is stored")
+    }
+
+    #[test]
+    fn inserted_clip_keeps_created_at_and_gets_local_updated_at() {
+        let db = TestDb::new();
+        let before = datetime_to_timestamp(Utc::now());
+        let clip = remote("from a peer");
+        let stats = db.apply_sync_clips(vec![clip.clone()], 0).unwrap();
+        assert_eq!((stats.inserted, stats.updated, stats.conflicts), (1, 0, 0));
+
+        let stored = local_row(&db, &clip.sync_id);
+        assert_eq!(stored.created_at, PEER_TIME);
+        assert!(stored.updated_at >= before);
+
+        // Receiving the same version again changes nothing.
+        let stats = db.apply_sync_clips(vec![clip], 0).unwrap();
+        assert!(!stats.changed());
+    }
+
+    #[test]
+    fn updated_flags_bump_updated_at() {
+        let db = TestDb::new();
+        let id = add_local(&db, "shared");
+        set_local(&db, id, false, 0, 0);
+        let before = datetime_to_timestamp(Utc::now());
+
+        let mut clip = remote("shared");
+        clip.is_pinned = true;
+        clip.flags_modified_at = PEER_TIME + 10;
+        let stats = db.apply_sync_clips(vec![clip.clone()], 0).unwrap();
+        assert_eq!((stats.inserted, stats.updated), (0, 1));
+
+        let stored = local_row(&db, &clip.sync_id);
+        assert!(stored.is_pinned);
+        assert_eq!(stored.flags_modified_at, PEER_TIME + 10);
+        assert!(stored.updated_at >= before);
+    }
+
+    #[test]
+    fn flag_conflict_keeps_the_newer_toggle() {
+        let db = TestDb::new();
+        let id = add_local(&db, "flags");
+        set_local(&db, id, true, PEER_TIME + 20, 0);
+
+        // Older remote toggle loses and leaves the row alone.
+        let mut clip = remote("flags");
+        clip.flags_modified_at = PEER_TIME + 10;
+        let stats = db.apply_sync_clips(vec![clip.clone()], 0).unwrap();
+        assert!(!stats.changed());
+        let stored = local_row(&db, &clip.sync_id);
+        assert!(stored.is_pinned);
+        assert_eq!(stored.updated_at, PEER_TIME);
+
+        // Newer remote toggle wins.
+        clip.flags_modified_at = PEER_TIME + 30;
+        let stats = db.apply_sync_clips(vec![clip.clone()], 0).unwrap();
+        assert_eq!(stats.updated, 1);
+        let stored = local_row(&db, &clip.sync_id);
+        assert!(!stored.is_pinned);
+        assert!(stored.updated_at > PEER_TIME);
+    }
+
+    #[test]
+    fn edit_conflict_keeps_both_versions() {
+        let db = TestDb::new();
+        let original = remote("draft");
+        db.apply_sync_clips(vec![original.clone()], 0).unwrap();
+        let id = db
+            .list(None, None, 0, false)
+            .unwrap()
+            .into_iter()
+            .find(|clip| clip.content == "draft")
+            .unwrap()
+            .id;
+        db.update_content(id, "local edit".to_string(), None, ClipOrigin::Ui)
+            .unwrap();
+        let local_edit = local_row(&db, &original.sync_id).content_modified_at;
+
+        let mut clip = remote("remote edit");
+        clip.sync_id = original.sync_id.clone();
+        clip.content_modified_at = local_edit + 5;
+        let stats = db.apply_sync_clips(vec![clip], local_edit - 1).unwrap();
+        assert_eq!((stats.conflicts, stats.updated), (1, 1));
+
+        let merged = local_row(&db, &original.sync_id);
+        assert_eq!(merged.content, "remote edit");
+        let contents: Vec<String> = db
+            .list(None, None, 0, false)
+            .unwrap()
+            .into_iter()
+            .map(|clip| clip.content)
+            .collect();
+        assert!(contents.contains(&"local edit".to_string()));
+    }
+
+    #[test]
+    fn merged_clips_are_relayed_to_other_peers() {
+        // B merges a clip from A after C has already pulled from B. C's next
+        // pull from B must still see it, although A's timestamps are older
+        // than C's cursor.
+        let b = TestDb::new();
+        add_local(&b, "already on b");
+        let (_, c_cursor) = b.sync_changes_since(0).unwrap();
+        std::thread::sleep(std::time::Duration::from_millis(1100));
+
+        let from_a = remote("written on a");
+        b.apply_sync_clips(vec![from_a.clone()], 0).unwrap();
+
+        let (changes, _) = b.sync_changes_since(c_cursor).unwrap();
+        assert!(changes.iter().any(|clip| clip.sync_id == from_a.sync_id));
+
+        let c = TestDb::new();
+        c.apply_sync_clips(changes, 0).unwrap();
+        assert_eq!(local_row(&c, &from_a.sync_id).created_at, PEER_TIME);
+    }
+}
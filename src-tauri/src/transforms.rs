@@ -0,0 +1,666 @@
+//! Mechanical text transforms that run locally, without `ai_client`.
+
+use anyhow::Context;
+use base64::engine::general_purpose::STANDARD as BASE64_STANDARD;
+use base64::Engine;
+use serde::{Deserialize, Serialize};
+
+use crate::clip_events::ClipOrigin;
+use crate::clipboard::finalize_payload;
+use crate::db::{ClipKind, ClipPayload, DbState, DerivedClip, NewProvenance};
+use crate::runtime_config::RuntimePreferences;
+
+#[derive(Debug, Clone, Copy, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum TextTransform {
+    CamelCase,
+    SnakeCase,
+    TitleCase,
+    UpperCase,
+    LowerCase,
+    TrimLines,
+    DedupeLines,
+    SortLines,
+    JsonPretty,
+    JsonMinify,
+    UrlEncode,
+    UrlDecode,
+    Base64Encode,
+    Base64Decode,
+    StripHtml,
+    StripMarkdown,
+    Count,
+}
+
+impl TextTransform {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TextTransform::CamelCase => "camelCase",
+            TextTransform::SnakeCase => "snakeCase",
+            TextTransform::TitleCase => "titleCase",
+            TextTransform::UpperCase => "upperCase",
+            TextTransform::LowerCase => "lowerCase",
+            TextTransform::TrimLines => "trimLines",
+            TextTransform::DedupeLines => "dedupeLines",
+            TextTransform::SortLines => "sortLines",
+            TextTransform::JsonPretty => "jsonPretty",
+            TextTransform::JsonMinify => "jsonMinify",
+            TextTransform::UrlEncode => "urlEncode",
+            TextTransform::UrlDecode => "urlDecode",
+            TextTransform::Base64Encode => "base64Encode",
+            TextTransform::Base64Decode => "base64Decode",
+            TextTransform::StripHtml => "stripHtml",
+            TextTransform::StripMarkdown => "stripMarkdown",
+            TextTransform::Count => "count",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TransformRequest {
+    pub transform: TextTransform,
+    pub input: String,
+    /// Only used by `sortLines`.
+    #[serde(default)]
+    pub descending: bool,
+    #[serde(default)]
+    pub source_clip_id: Option<i64>,
+    #[serde(default)]
+    pub save_result: bool,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct TextStats {
+    pub characters: usize,
+    pub characters_without_spaces: usize,
+    pub words: usize,
+    pub lines: usize,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct TransformResponse {
+    pub transform: TextTransform,
+    pub result: String,
+    pub stats: Option<TextStats>,
+    pub saved_clip: Option<DerivedClip>,
+}
+
+/// Applies the transform and, when requested, stores the result as a clip
+/// derived from the source. `count` only reports statistics and never saves.
+pub fn run(
+    db: &DbState,
+    prefs: &RuntimePreferences,
+    request: TransformRequest,
+) -> anyhow::Result<TransformResponse> {
+    let transform = request.transform;
+    let result = apply(transform, &request.input, request.descending)?;
+    let stats = matches!(transform, TextTransform::Count).then(|| count(&request.input));
+
+    let mut saved_clip = None;
+    if request.save_result && stats.is_none() && !result.is_empty() {
+        let payload = finalize_payload(ClipPayload {
+            kind: ClipKind::Text,
+            content: result.clone(),
+            preview: Some(result.chars().take(120).collect()),
+            extra: None,
+            content_hash: None,
+            is_pinned: false,
+            is_favorite: false,
+            source_app: None,
+        });
+        let provenance = NewProvenance {
+            derived_from: request.source_clip_id,
+            action: format!("transform:{}", transform.as_str()),
+            model: None,
+            prompt: None,
+        };
+        let derived = db.insert_derived(payload, provenance, ClipOrigin::Ui)?;
+        db.apply_retention(prefs)?;
+        saved_clip = Some(derived);
+    }
+
+    Ok(TransformResponse {
+        transform,
+        result,
+        stats,
+        saved_clip,
+    })
+}
+
+pub fn apply(transform: TextTransform, input: &str, descending: bool) -> anyhow::Result<String> {
+    let output = match transform {
+        TextTransform::CamelCase => map_lines(input, |line| {
+            split_words(line)
+                .iter()
+                .enumerate()
+                .map(|(index, word)| {
+                    if index == 0 {
+                        word.to_lowercase()
+                    } else {
+                        capitalize(word)
+                    }
+                })
+                .collect()
+        }),
+        TextTransform::SnakeCase => map_lines(input, |line| {
+            split_words(line)
+                .iter()
+                .map(|word| word.to_lowercase())
+                .collect::<Vec<_>>()
+                .join("_")
+        }),
+        TextTransform::TitleCase => map_lines(input, |line| {
+            line.split(' ')
+                .map(title_word)
+                .collect::<Vec<_>>()
+                .join(" ")
+        }),
+        TextTransform::UpperCase => input.to_uppercase(),
+        TextTransform::LowerCase => input.to_lowercase(),
+        TextTransform::TrimLines => input
+            .lines()
+            .map(str::trim)
+            .collect::<Vec<_>>()
+            .join("\n")
+            .trim()
+            .to_string(),
+        TextTransform::DedupeLines => {
+            let mut seen = std::collections::HashSet::new();
+            input
+                .lines()
+                .filter(|line| seen.insert(*line))
+                .collect::<Vec<_>>()
+                .join("\n")
+        }
+        TextTransform::SortLines => {
+            let mut lines: Vec<&str> = input.lines().collect();
+            lines.sort_by(|a, b| {
+                a.to_lowercase()
+                    .cmp(&b.to_lowercase())
+                    .then_with(|| a.cmp(b))
+            });
+            if descending {
+                lines.reverse();
+            }
+            lines.join("\n")
+        }
+        TextTransform::JsonPretty => {
+            let value: serde_json::Value =
+                serde_json::from_str(input.trim()).context("输入不是有效的 JSON")?;
+            serde_json::to_string_pretty(&value)?
+        }
+        TextTransform::JsonMinify => {
+            let value: serde_json::Value =
+                serde_json::from_str(input.trim()).context("输入不是有效的 JSON")?;
+            serde_json::to_string(&value)?
+        }
+        TextTransform::UrlEncode => url_encode(input),
+        TextTransform::UrlDecode => url_decode(input)?,
+        TextTransform::Base64Encode => BASE64_STANDARD.encode(input.as_bytes()),
+        TextTransform::Base64Decode => {
+            let compact: String = input.split_whitespace().collect();
+            let bytes = BASE64_STANDARD
+                .decode(compact.as_bytes())
+                .context("输入不是有效的 Base64")?;
+            String::from_utf8(bytes).context("Base64 内容不是 UTF-8 文本")?
+        }
+        TextTransform::StripHtml => strip_html(input),
+        TextTransform::StripMarkdown => strip_markdown(input),
+        TextTransform::Count => {
+            let stats = count(input);
+            format!(
+                "字符：{}\n字符（不含空白）：{}\n词：{}\n行：{}",
+                stats.characters, stats.characters_without_spaces, stats.words, stats.lines
+            )
+        }
+    };
+    Ok(output)
+}
+
+pub fn count(input: &str) -> TextStats {
+    let mut words = 0usize;
+    let mut in_word = false;
+    for ch in input.chars() {
+        if is_cjk(ch) {
+            // CJK text has no spaces; every ideograph counts as a word.
+            words += 1;
+            in_word = false;
+        } else if ch.is_alphanumeric() {
+            if !in_word {
+                words += 1;
+                in_word = true;
+            }
+        } else if ch != '\'' && ch != '-' {
+            in_word = false;
+        }
+    }
+    TextStats {
+        characters: input.chars().count(),
+        characters_without_spaces: input.chars().filter(|ch| !ch.is_whitespace()).count(),
+        words,
+        lines: if input.is_empty() {
+            0
+        } else {
+            input.lines().count()
+        },
+    }
+}
+
+fn is_cjk(ch: char) -> bool {
+    matches!(ch as u32, 0x4E00..=0x9FFF | 0x3400..=0x4DBF | 0x3040..=0x30FF | 0xAC00..=0xD7AF)
+}
+
+fn map_lines(input: &str, f: impl Fn(&str) -> String) -> String {
+    input.lines().map(f).collect::<Vec<_>>().join("\n")
+}
+
+/// Splits on separators and on lower-to-upper boundaries, so `fooBar-baz`
+/// and `FOO_BAR baz` both yield three words.
+fn split_words(line: &str) -> Vec<String> {
+    let mut words = Vec::new();
+    let mut current = String::new();
+    let mut prev: Option<char> = None;
+    for ch in line.chars() {
+        if !ch.is_alphanumeric() {
+            if !current.is_empty() {
+                words.push(std::mem::take(&mut current));
+            }
+            prev = None;
+            continue;
+        }
+        if let Some(previous) = prev {
+            if ch.is_uppercase() && (previous.is_lowercase() || previous.is_numeric()) {
+                words.push(std::mem::take(&mut current));
+            } else if ch.is_lowercase() && previous.is_uppercase() && current.chars().count() > 1 {
+                // The last capital of an acronym starts the next word: HTTPServer.
+                current.pop();
+                words.push(std::mem::replace(&mut current, previous.to_string()));
+            }
+        }
+        current.push(ch);
+        prev = Some(ch);
+    }
+    if !current.is_empty() {
+        words.push(current);
+    }
+    words
+}
+
+fn capitalize(word: &str) -> String {
+    let mut chars = word.chars();
+    match chars.next() {
+        Some(first) => first
+            .to_uppercase()
+            .chain(chars.flat_map(char::to_lowercase))
+            .collect(),
+        None => String::new(),
+    }
+}
+
+/// Capitalizes the first letter only. Words that already have capitals
+/// after the first letter (`iPhone`, `NASA`, `McDonald`) are left alone.
+fn title_word(word: &str) -> String {
+    let mut chars = word.chars();
+    match chars.next() {
+        Some(_) if chars.clone().any(char::is_uppercase) => word.to_string(),
+        Some(first) => first.to_uppercase().chain(chars).collect(),
+        None => String::new(),
+    }
+}
+
+fn url_encode(input: &str) -> String {
+    let mut encoded = String::with_capacity(input.len());
+    for byte in input.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                encoded.push(byte as char)
+            }
+            _ => encoded.push_str(&format!("%{byte:02X}")),
+        }
+    }
+    encoded
+}
+
+pub(crate) fn url_decode(input: &str) -> anyhow::Result<String> {
+    let bytes = input.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut index = 0;
+    while index < bytes.len() {
+        match bytes[index] {
+            b'%' => {
+                let hex = bytes
+                    .get(index + 1..index + 3)
+                    .and_then(|pair| std::str::from_utf8(pair).ok())
+                    .and_then(|pair| u8::from_str_radix(pair, 16).ok())
+                    .with_context(|| format!("位置 {} 的百分号编码无效", index))?;
+                decoded.push(hex);
+                index += 3;
+            }
+            b'+' => {
+                decoded.push(b' ');
+                index += 1;
+            }
+            byte => {
+                decoded.push(byte);
+                index += 1;
+            }
+        }
+    }
+    String::from_utf8(decoded).context("解码结果不是 UTF-8 文本")
+}
+
+fn strip_html(input: &str) -> String {
+    let mut text = String::with_capacity(input.len());
+    let mut rest = input;
+    while let Some(start) = rest.find('<') {
+        text.push_str(&rest[..start]);
+        let after = &rest[start..];
+        let Some(end) = after.find('>') else {
+            text.push_str(after);
+            rest = "";
+            break;
+        };
+        let tag = after[1..end].trim().to_ascii_lowercase();
+        let name: String = tag
+            .trim_start_matches('/')
+            .chars()
+            .take_while(|ch| ch.is_ascii_alphanumeric())
+            .collect();
+        rest = &after[end + 1..];
+        if !tag.starts_with('/') && (name == "script" || name == "style") {
+            let closing = format!("</{name}");
+            rest = match rest.to_ascii_lowercase().find(&closing) {
+                Some(pos) => rest[pos..].find('>').map_or("", |gt| &rest[pos + gt + 1..]),
+                None => "",
+            };
+            continue;
+        }
+        if matches!(
+            name.as_str(),
+            "br" | "p" | "div" | "li" | "tr" | "h1" | "h2" | "h3" | "h4" | "h5" | "h6"
+        ) {
+            text.push('\n');
+        }
+    }
+    text.push_str(rest);
+    collapse_blank_lines(&decode_entities(&text))
+}
+
+fn decode_entities(input: &str) -> String {
+    let mut output = String::with_capacity(input.len());
+    let mut rest = input;
+    while let Some(start) = rest.find('&') {
+        output.push_str(&rest[..start]);
+        let after = &rest[start..];
+        let decoded = after.find(';').filter(|end| *end <= 10).and_then(|end| {
+            let entity = &after[1..end];
+            let ch = match entity {
+                "amp" => Some('&'),
+                "lt" => Some('<'),
+                "gt" => Some('>'),
+                "quot" => Some('"'),
+                "apos" => Some('\''),
+                "nbsp" => Some(' '),
+                _ => entity
+                    .strip_prefix("#x")
+                    .or_else(|| entity.strip_prefix("#X"))
+                    .and_then(|hex| u32::from_str_radix(hex, 16).ok())
+                    .or_else(|| entity.strip_prefix('#').and_then(|dec| dec.parse().ok()))
+                    .and_then(char::from_u32),
+            };
+            ch.map(|ch| (ch, end))
+        });
+        match decoded {
+            Some((ch, end)) => {
+                output.push(ch);
+                rest = &after[end + 1..];
+            }
+            None => {
+                output.push('&');
+                rest = &after[1..];
+            }
+        }
+    }
+    output.push_str(rest);
+    output
+}
+
+fn strip_markdown(input: &str) -> String {
+    let mut lines = Vec::new();
+    let mut in_fence = false;
+    for line in input.lines() {
+        let trimmed = line.trim_start();
+        if trimmed.starts_with("```") || trimmed.starts_with("~~~") {
+            in_fence = !in_fence;
+            continue;
+        }
+        if in_fence {
+            lines.push(line.to_string());
+            continue;
+        }
+        if matches!(trimmed, "---" | "***" | "___") {
+            continue;
+        }
+        let mut body = if trimmed.starts_with('#') {
+            trimmed.trim_start_matches('#').trim_start()
+        } else {
+            trimmed
+        };
+        while let Some(quoted) = body.strip_prefix('>') {
+            body = quoted.trim_start();
+        }
+        for marker in ["- [ ] ", "- [x] ", "- ", "* ", "+ "] {
+            if let Some(item) = body.strip_prefix(marker) {
+                body = item;
+                break;
+            }
+        }
+        if let Some(pos) = body.find(". ") {
+            if pos > 0 && body[..pos].chars().all(|ch| ch.is_ascii_digit()) {
+                body = &body[pos + 2..];
+            }
+        }
+        lines.push(strip_inline_markdown(body));
+    }
+    collapse_blank_lines(&lines.join("\n"))
+}
+
+/// Removes emphasis and code markers and reduces links and images to their text.
+fn strip_inline_markdown(line: &str) -> String {
+    let chars: Vec<char> = line.chars().collect();
+    let emphasis = emphasis_markers(&chars);
+    let mut output = String::with_capacity(line.len());
+    let mut index = 0;
+    while index < chars.len() {
+        let ch = chars[index];
+        let is_image = ch == '!' && chars.get(index + 1) == Some(&'[');
+        if ch == '[' || is_image {
+            let open = if is_image { index + 1 } else { index };
+            if let Some(close) = find_char(&chars, open + 1, ']') {
+                if chars.get(close + 1) == Some(&'(') {
+                    if let Some(paren) = find_char(&chars, close + 2, ')') {
+                        output.extend(&chars[open + 1..close]);
+                        index = paren + 1;
+                        continue;
+                    }
+                }
+            }
+        }
+        match ch {
+            '*' if emphasis[index] => {}
+            '`' => {}
+            '_' if chars.get(index + 1) == Some(&'_') => index += 1,
+            '~' if chars.get(index + 1) == Some(&'~') => index += 1,
+            _ => output.push(ch),
+        }
+        index += 1;
+    }
+    output
+}
+
+/// Marks the `*` runs that open and close emphasis. An opening run is
+/// followed by a non-space and closed by a later run of the same length
+/// that follows a non-space, so the `*` in `2 * 3` is kept.
+fn emphasis_markers(chars: &[char]) -> Vec<bool> {
+    let mut runs = Vec::new();
+    let mut index = 0;
+    while index < chars.len() {
+        if chars[index] == '*' {
+            let len = chars[index..].iter().take_while(|ch| **ch == '*').count();
+            runs.push((index, len));
+            index += len;
+        } else {
+            index += 1;
+        }
+    }
+
+    let mut marked = vec![false; chars.len()];
+    let mut paired = vec![false; runs.len()];
+    for (open, &(start, len)) in runs.iter().enumerate() {
+        let opens = chars.get(start + len).is_some_and(|ch| !ch.is_whitespace());
+        if paired[open] || !opens {
+            continue;
+        }
+        let close = (open + 1..runs.len()).find(|&candidate| {
+            let (close_start, close_len) = runs[candidate];
+            !paired[candidate] && close_len == len && !chars[close_start - 1].is_whitespace()
+        });
+        if let Some(close) = close {
+            paired[open] = true;
+            paired[close] = true;
+            for (run_start, run_len) in [runs[open], runs[close]] {
+                marked[run_start..run_start + run_len].fill(true);
+            }
+        }
+    }
+    marked
+}
+
+fn find_char(chars: &[char], from: usize, target: char) -> Option<usize> {
+    chars
+        .iter()
+        .skip(from)
+        .position(|ch| *ch == target)
+        .map(|pos| pos + from)
+}
+
+fn collapse_blank_lines(input: &str) -> String {
+    let mut output = Vec::new();
+    let mut blank = false;
+    for line in input.lines().map(str::trim_end) {
+        if line.trim().is_empty() {
+            if !blank && !output.is_empty() {
+                output.push("");
+            }
+            blank = true;
+        } else {
+            output.push(line);
+            blank = false;
+        }
+    }
+    output.join("\n").trim().to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn apply_transforms() {
+        let cases: &[(TextTransform, &str, &str)] = &[
+            (TextTransform::CamelCase, "foo bar-baz", "fooBarBaz"),
+            (
+                TextTransform::CamelCase,
+                "HTTPServer error",
+                "httpServerError",
+            ),
+            (TextTransform::SnakeCase, "fooBar baz", "foo_bar_baz"),
+            (TextTransform::SnakeCase, "FOO_BAR", "foo_bar"),
+            (TextTransform::TitleCase, "the new iPhone", "The New iPhone"),
+            (
+                TextTransform::TitleCase,
+                "NASA launch report",
+                "NASA Launch Report",
+            ),
+            (TextTransform::TitleCase, "über  café", "Über  Café"),
+            (TextTransform::UpperCase, "straße", "STRASSE"),
+            (TextTransform::TrimLines, "  a  \n b \n\n", "a\nb"),
+            (TextTransform::DedupeLines, "a\nb\na\nb", "a\nb"),
+            (TextTransform::SortLines, "b\na\nA", "A\na\nb"),
+            (TextTransform::JsonPretty, "{\"a\":1}", "{\n  \"a\": 1\n}"),
+            (
+                TextTransform::JsonMinify,
+                "{ \"a\": [1, 2] }",
+                "{\"a\":[1,2]}",
+            ),
+            (TextTransform::UrlEncode, "a b&c/é", "a%20b%26c%2F%C3%A9"),
+            (TextTransform::UrlDecode, "a%20b+c%C3%A9", "a b cé"),
+            (TextTransform::Base64Encode, "hi", "aGk="),
+            (TextTransform::Base64Decode, "aG\nk=", "hi"),
+            (
+                TextTransform::StripHtml,
+                "<p>a &amp; b</p><script>x()</script>c&#x21;",
+                "a & b\nc!",
+            ),
+            (TextTransform::StripHtml, "1 < 2", "1 < 2"),
+            (
+                TextTransform::StripMarkdown,
+                "# Title\n\n**bold** and *it*",
+                "Title\n\nbold and it",
+            ),
+            (TextTransform::StripMarkdown, "2 * 3 = 6", "2 * 3 = 6"),
+            (TextTransform::StripMarkdown, "a * b and *c*", "a * b and c"),
+            (TextTransform::StripMarkdown, "**not closed", "**not closed"),
+            (
+                TextTransform::StripMarkdown,
+                "- see [docs](https://x.y) and `code`",
+                "see docs and code",
+            ),
+            (
+                TextTransform::StripMarkdown,
+                "```\n*kept*\n```\n1. __one__",
+                "*kept*\none",
+            ),
+        ];
+        for (transform, input, expected) in cases {
+            let output = apply(*transform, input, false).unwrap();
+            assert_eq!(output, *expected, "{} of {input:?}", transform.as_str());
+        }
+    }
+
+    #[test]
+    fn sort_lines_descending() {
+        let output = apply(TextTransform::SortLines, "a\nc\nb", true).unwrap();
+        assert_eq!(output, "c\nb\na");
+    }
+
+    #[test]
+    fn reject_invalid_input() {
+        let cases: &[(TextTransform, &str)] = &[
+            (TextTransform::JsonPretty, "{"),
+            (TextTransform::JsonMinify, "nope"),
+            (TextTransform::UrlDecode, "%zz"),
+            (TextTransform::UrlDecode, "%C3"),
+            (TextTransform::Base64Decode, "***"),
+            (TextTransform::Base64Decode, "/w=="),
+        ];
+        for (transform, input) in cases {
+            assert!(
+                apply(*transform, input, false).is_err(),
+                "{} of {input:?}",
+                transform.as_str()
+            );
+        }
+    }
+
+    #[test]
+    fn count_text() {
+        let stats = count("你好 world\nfoo-bar it's");
+        assert_eq!(stats.characters, 21);
+        assert_eq!(stats.characters_without_spaces, 18);
+        assert_eq!(stats.words, 5);
+        assert_eq!(stats.lines, 2);
+        assert_eq!(count("").lines, 0);
+    }
+}
@@ -3,10 +3,32 @@ use tauri::{
     include_image,
     menu::{Menu, MenuItem},
     tray::{MouseButton, MouseButtonState, TrayIconBuilder, TrayIconEvent},
-    AppHandle, Manager,
+    AppHandle, Manager, Wry,
 };
 
-use crate::{db::DbState, state::AppStatus};
+use crate::{clip_events::ClipOrigin, state::AppStatus};
+
+/// Menu items whose label follows state changed outside the tray.
+struct TrayItems {
+    listening: MenuItem<Wry>,
+}
+
+/// Pauses or resumes capturing and keeps the tray label in step. Every path
+/// that changes listening (tray, settings, remote control) goes through here.
+pub fn set_listening(app: &AppHandle, listening: bool) {
+    app.state::<AppStatus>().set_listening(listening);
+    update_listening_label(app, listening);
+}
+
+fn update_listening_label(app: &AppHandle, listening: bool) {
+    if let Some(items) = app.try_state::<TrayItems>() {
+        let _ = items.listening.set_text(if listening {
+            "暂停监听"
+        } else {
+            "恢复监听"
+        });
+    }
+}
 
 pub fn create_tray(app: &AppHandle) -> tauri::Result<()> {
     let quick_panel_item = MenuItem::with_id(app, "quick-panel", "快捷面板", true, None::<&str>)?;
@@ -33,7 +55,9 @@ pub fn create_tray(app: &AppHandle) -> tauri::Result<()> {
         ],
     )?;
 
-    let listening_menu_item = listening_item.clone();
+    app.manage(TrayItems {
+        listening: listening_item.clone(),
+    });
     let offline_menu_item = offline_item.clone();
 
     TrayIconBuilder::with_id("main")
@@ -66,13 +90,8 @@ pub fn create_tray(app: &AppHandle) -> tauri::Result<()> {
                 }
             }
             "toggle-listener" => {
-                let status = app.state::<AppStatus>();
-                let is_listening = status.toggle_listening();
-                let _ = listening_menu_item.set_text(if is_listening {
-                    "暂停监听"
-                } else {
-                    "恢复监听"
-                });
+                let is_listening = app.state::<AppStatus>().toggle_listening();
+                update_listening_label(app, is_listening);
             }
             "toggle-offline" => {
                 let status = app.state::<AppStatus>();
@@ -84,9 +103,9 @@ pub fn create_tray(app: &AppHandle) -> tauri::Result<()> {
                 });
             }
             "clear-history" => {
-                let db_state = app.state::<DbState>().clone_for_thread();
+                let app = app.clone();
                 tauri::async_runtime::spawn_blocking(move || {
-                    if let Err(err) = db_state.clear() {
+                    if let Err(err) = crate::clear_clips(&app, ClipOrigin::Tray) {
                         tracing::error!("failed to clear history from tray: {err:?}");
                     }
                 });
@@ -0,0 +1,361 @@
+//! Undo/redo for destructive history operations.
+//!
+//! Commands that delete or edit clips push an entry with what they changed:
+//! full snapshots for deletions, before/after values for edits. The stack is
+//! kept in memory for the lifetime of the app, bounded by entry count and by
+//! the size of the clips it holds.
+
+use std::collections::VecDeque;
+use std::sync::Mutex;
+
+use rusqlite::{params, Connection, OptionalExtension, Row, ToSql};
+use serde::Serialize;
+
+use crate::clip_events::{self, ClipOp, ClipOrigin};
+use crate::db::{
+    datetime_to_timestamp, map_clip_row, map_provenance_columns, ClipItem, ClipProvenance, DbState,
+};
+
+/// Upper bound for the clip data held by undo and redo together; deleting a
+/// history full of images would otherwise pin all of it in memory.
+const MAX_UNDO_BYTES: usize = 64 * 1024 * 1024;
+
+/// A deleted clip with everything needed to put it back.
+#[derive(Debug, Clone)]
+pub struct ClipSnapshot {
+    pub clip: ClipItem,
+    sync_id: Option<String>,
+    flags_modified_at: i64,
+    content_modified_at: i64,
+    provenance: Option<ClipProvenance>,
+}
+
+/// Snapshots the clips matching `condition` (a SQL expression over `clips`),
+/// typically right before deleting them in the same transaction.
+pub(crate) fn snapshot_where(
+    conn: &Connection,
+    condition: &str,
+    params: &[&dyn ToSql],
+) -> anyhow::Result<Vec<ClipSnapshot>> {
+    let mut stmt = conn.prepare(&format!(
+        "SELECT c.id, c.kind, c.content, c.content_hash, c.preview, c.extra, c.is_pinned, c.is_favorite, \
+            c.created_at, c.updated_at, c.language, c.source_app, c.sync_id, c.flags_modified_at, c.content_modified_at, \
+            p.clip_id, p.derived_from, p.action, p.model, p.prompt, p.created_at \
+         FROM (SELECT * FROM clips WHERE {condition}) c \
+         LEFT JOIN clip_provenance p ON p.clip_id = c.id \
+         ORDER BY c.id"
+    ))?;
+    let rows = stmt.query_map(params, map_snapshot_row)?;
+    Ok(rows.collect::<Result<Vec<_>, _>>()?)
+}
+
+fn map_snapshot_row(row: &Row<'_>) -> rusqlite::Result<ClipSnapshot> {
+    let provenance = match row.get::<_, Option<i64>>(15)? {
+        Some(_) => Some(map_provenance_columns(row, 15)?),
+        None => None,
+    };
+    Ok(ClipSnapshot {
+        clip: map_clip_row(row)?,
+        sync_id: row.get(12)?,
+        flags_modified_at: row.get(13)?,
+        content_modified_at: row.get(14)?,
+        provenance,
+    })
+}
+
+impl DbState {
+    /// Puts deleted clips back with their original ids. Clips whose content
+    /// was captured again in the meantime are skipped. Returns the restored
+    /// clips as they are stored now.
+    pub fn restore_clips(
+        &self,
+        snapshots: &[ClipSnapshot],
+        origin: ClipOrigin,
+    ) -> anyhow::Result<Vec<ClipSnapshot>> {
+        let snapshots = snapshots.to_vec();
+        self.write(move |conn| {
+            let tx = conn.transaction()?;
+            let mut restored = Vec::with_capacity(snapshots.len());
+            for snapshot in &snapshots {
+                let clip = &snapshot.clip;
+                let duplicate: Option<i64> = tx
+                    .query_row(
+                        "SELECT id FROM clips WHERE content_hash = ?1 LIMIT 1",
+                        params![clip.content_hash],
+                        |row| row.get(0),
+                    )
+                    .optional()?;
+                if duplicate.is_some() {
+                    continue;
+                }
+                let id_taken: Option<i64> = tx
+                    .query_row(
+                        "SELECT id FROM clips WHERE id = ?1",
+                        params![clip.id],
+                        |row| row.get(0),
+                    )
+                    .optional()?;
+                tx.execute(
+                    "INSERT INTO clips (id, kind, content, content_hash, preview, extra, is_pinned, is_favorite, created_at, updated_at, language, source_app, sync_id, flags_modified_at, content_modified_at) \
+                     VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15)",
+                    params![
+                        id_taken.is_none().then_some(clip.id),
+                        i64::from(clip.kind),
+                        clip.content,
+                        clip.content_hash,
+                        clip.preview,
+                        clip.extra,
+                        clip.is_pinned as i64,
+                        clip.is_favorite as i64,
+                        datetime_to_timestamp(clip.created_at),
+                        datetime_to_timestamp(clip.updated_at),
+                        clip.language,
+                        clip.source_app,
+                        snapshot.sync_id,
+                        snapshot.flags_modified_at,
+                        snapshot.content_modified_at
+                    ],
+                )?;
+                let id = tx.last_insert_rowid();
+                clip_events::record(
+                    &tx,
+                    ClipOp::Insert,
+                    Some(id),
+                    Some(&clip.content_hash),
+                    origin,
+                    Some(serde_json::json!({ "restored": clip.id })),
+                )?;
+                restored.push((id, snapshot));
+            }
+            // Restore provenance once all clips are back, so derivations between
+            // restored clips keep their link.
+            for (id, snapshot) in &restored {
+                let Some(provenance) = &snapshot.provenance else {
+                    continue;
+                };
+                let source: Option<i64> = match provenance.derived_from {
+                    Some(source) => tx
+                        .query_row(
+                            "SELECT id FROM clips WHERE id = ?1",
+                            params![source],
+                            |row| row.get(0),
+                        )
+                        .optional()?,
+                    None => None,
+                };
+                tx.execute(
+                    "INSERT OR REPLACE INTO clip_provenance (clip_id, derived_from, action, model, prompt, created_at) VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+                    params![
+                        id,
+                        source,
+                        provenance.action,
+                        provenance.model,
+                        provenance.prompt,
+                        datetime_to_timestamp(provenance.created_at)
+                    ],
+                )?;
+            }
+            tx.commit()?;
+            Ok(restored
+                .into_iter()
+                .map(|(id, snapshot)| {
+                    let mut snapshot = snapshot.clone();
+                    snapshot.clip.id = id;
+                    snapshot
+                })
+                .collect())
+        })
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum UndoChange {
+    Removed(Vec<ClipSnapshot>),
+    Content {
+        id: i64,
+        before: (String, Option<String>),
+        after: (String, Option<String>),
+    },
+    Flags {
+        id: i64,
+        before: (bool, bool),
+        after: (bool, bool),
+    },
+}
+
+#[derive(Debug, Clone)]
+pub struct UndoEntry {
+    pub label: String,
+    pub change: UndoChange,
+}
+
+impl UndoEntry {
+    /// Approximate memory held by the entry's clip data.
+    fn size(&self) -> usize {
+        let text = |value: &Option<String>| value.as_ref().map_or(0, String::len);
+        match &self.change {
+            UndoChange::Removed(snapshots) => snapshots
+                .iter()
+                .map(|snapshot| {
+                    let clip = &snapshot.clip;
+                    clip.content.len() + text(&clip.preview) + text(&clip.extra)
+                })
+                .sum(),
+            UndoChange::Content { before, after, .. } => {
+                before.0.len() + text(&before.1) + after.0.len() + text(&after.1)
+            }
+            UndoChange::Flags { .. } => 0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct UndoStatus {
+    pub can_undo: bool,
+    pub can_redo: bool,
+    pub undo_label: Option<String>,
+    pub redo_label: Option<String>,
+}
+
+#[derive(Default)]
+struct UndoHistory {
+    undo: VecDeque<UndoEntry>,
+    redo: Vec<UndoEntry>,
+}
+
+#[derive(Default)]
+pub struct UndoState {
+    history: Mutex<UndoHistory>,
+}
+
+impl UndoState {
+    /// Records a new operation, dropping the redo branch and the oldest
+    /// entries beyond `depth` or `MAX_UNDO_BYTES`. A depth of 0 turns undo
+    /// off; an entry larger than the byte limit on its own is skipped, and
+    /// the older entries stay.
+    pub fn push(&self, entry: UndoEntry, depth: usize) {
+        let Ok(mut history) = self.history.lock() else {
+            return;
+        };
+        history.redo.clear();
+        if depth == 0 {
+            history.undo.clear();
+            return;
+        }
+        if let UndoChange::Removed(snapshots) = &entry.change {
+            if snapshots.is_empty() {
+                return;
+            }
+        }
+        let size = entry.size();
+        if size > MAX_UNDO_BYTES {
+            tracing::warn!(
+                "undo entry {:?} is too large to keep ({size} bytes)",
+                entry.label
+            );
+            return;
+        }
+        history.undo.push_back(entry);
+        let mut total: usize = history.undo.iter().map(UndoEntry::size).sum();
+        while history.undo.len() > depth || total > MAX_UNDO_BYTES {
+            match history.undo.pop_front() {
+                Some(dropped) => total -= dropped.size(),
+                None => break,
+            }
+        }
+    }
+
+    /// Forgets all entries, e.g. after the database was replaced.
+    pub fn clear(&self) {
+        if let Ok(mut history) = self.history.lock() {
+            history.undo.clear();
+            history.redo.clear();
+        }
+    }
+
+    pub fn status(&self) -> UndoStatus {
+        match self.history.lock() {
+            Ok(history) => UndoStatus {
+                can_undo: !history.undo.is_empty(),
+                can_redo: !history.redo.is_empty(),
+                undo_label: history.undo.back().map(|entry| entry.label.clone()),
+                redo_label: history.redo.last().map(|entry| entry.label.clone()),
+            },
+            Err(_) => UndoStatus::default(),
+        }
+    }
+
+    /// Reverts the newest operation. Returns `None` when there is nothing to
+    /// undo. A failed revert drops the entry instead of retrying it forever.
+    pub fn undo(&self, db: &DbState) -> anyhow::Result<Option<String>> {
+        let Some(entry) = self.lock()?.undo.pop_back() else {
+            return Ok(None);
+        };
+        let label = entry.label.clone();
+        let entry = revert(db, entry)?;
+        self.lock()?.redo.push(entry);
+        Ok(Some(label))
+    }
+
+    /// Re-applies the most recently undone operation.
+    pub fn redo(&self, db: &DbState) -> anyhow::Result<Option<String>> {
+        let Some(entry) = self.lock()?.redo.pop() else {
+            return Ok(None);
+        };
+        let label = entry.label.clone();
+        let entry = reapply(db, entry)?;
+        self.lock()?.undo.push_back(entry);
+        Ok(Some(label))
+    }
+
+    fn lock(&self) -> anyhow::Result<std::sync::MutexGuard<'_, UndoHistory>> {
+        self.history
+            .lock()
+            .map_err(|_| anyhow::anyhow!("undo history lock poisoned"))
+    }
+}
+
+fn revert(db: &DbState, entry: UndoEntry) -> anyhow::Result<UndoEntry> {
+    let change = match entry.change {
+        UndoChange::Removed(snapshots) => {
+            // Redo deletes exactly the clips that came back.
+            UndoChange::Removed(db.restore_clips(&snapshots, ClipOrigin::Undo)?)
+        }
+        UndoChange::Content { id, before, after } => {
+            db.update_content(id, before.0.clone(), before.1.clone(), ClipOrigin::Undo)?;
+            UndoChange::Content { id, before, after }
+        }
+        UndoChange::Flags { id, before, after } => {
+            db.update_flags(id, Some(before.0), Some(before.1), ClipOrigin::Undo)?;
+            UndoChange::Flags { id, before, after }
+        }
+    };
+    Ok(UndoEntry {
+        label: entry.label,
+        change,
+    })
+}
+
+fn reapply(db: &DbState, entry: UndoEntry) -> anyhow::Result<UndoEntry> {
+    let change = match entry.change {
+        UndoChange::Removed(snapshots) => {
+            let mut removed = Vec::with_capacity(snapshots.len());
+            for snapshot in &snapshots {
+                removed.extend(db.delete(snapshot.clip.id, ClipOrigin::Undo)?);
+            }
+            UndoChange::Removed(removed)
+        }
+        UndoChange::Content { id, before, after } => {
+            db.update_content(id, after.0.clone(), after.1.clone(), ClipOrigin::Undo)?;
+            UndoChange::Content { id, before, after }
+        }
+        UndoChange::Flags { id, before, after } => {
+            db.update_flags(id, Some(after.0), Some(after.1), ClipOrigin::Undo)?;
+            UndoChange::Flags { id, before, after }
+        }
+    };
+    Ok(UndoEntry {
+        label: entry.label,
+        change,
+    })
+}
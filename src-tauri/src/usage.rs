@@ -0,0 +1,152 @@
+use chrono::{DateTime, Datelike, TimeZone, Utc};
+use rusqlite::{params, Connection};
+use serde::{Deserialize, Serialize};
+
+use crate::ai_client::{AiRoute, TokenUsage};
+use crate::db::{datetime_to_timestamp, DbState};
+use crate::runtime_config::AiPricing;
+
+#[derive(Debug, Clone)]
+pub struct UsageRecord {
+    pub provider: String,
+    pub model: String,
+    pub action: String,
+    pub route: AiRoute,
+    pub usage: TokenUsage,
+    pub cost_usd: Option<f64>,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum UsageGrouping {
+    Day,
+    Model,
+    Action,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct UsageSummaryRow {
+    pub key: String,
+    pub calls: u64,
+    pub prompt_tokens: u64,
+    pub completion_tokens: u64,
+    pub cost_usd: f64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct BudgetStatus {
+    pub month_cost_usd: f64,
+    pub monthly_budget_usd: Option<f64>,
+    pub remaining_usd: Option<f64>,
+    pub exceeded: bool,
+}
+
+pub(crate) fn migrate(conn: &Connection) -> anyhow::Result<()> {
+    conn.execute_batch(
+        r#"
+        CREATE TABLE IF NOT EXISTS ai_usage_log (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            created_at INTEGER NOT NULL,
+            provider TEXT NOT NULL,
+            model TEXT NOT NULL,
+            action TEXT NOT NULL,
+            route TEXT NOT NULL,
+            prompt_tokens INTEGER NOT NULL DEFAULT 0,
+            completion_tokens INTEGER NOT NULL DEFAULT 0,
+            cost_usd REAL
+        );
+        CREATE INDEX IF NOT EXISTS idx_ai_usage_created_at ON ai_usage_log(created_at DESC);
+        "#,
+    )?;
+    Ok(())
+}
+
+pub fn estimate_cost(pricing: &AiPricing, model: &str, usage: &TokenUsage) -> Option<f64> {
+    let price = pricing.price_for(model)?;
+    Some(
+        (usage.prompt_tokens as f64 * price.prompt_per_million
+            + usage.completion_tokens as f64 * price.completion_per_million)
+            / 1_000_000.0,
+    )
+}
+
+fn month_start(now: DateTime<Utc>) -> DateTime<Utc> {
+    Utc.with_ymd_and_hms(now.year(), now.month(), 1, 0, 0, 0)
+        .single()
+        .unwrap_or(now)
+}
+
+impl DbState {
+    pub fn record_ai_usage(&self, record: &UsageRecord) -> anyhow::Result<()> {
+        let record = record.clone();
+        self.write(move |conn| {
+            let route = match record.route {
+                AiRoute::Remote => "remote",
+                AiRoute::Local => "local",
+                AiRoute::Cache => "cache",
+            };
+            conn.execute(
+                "INSERT INTO ai_usage_log (created_at, provider, model, action, route, prompt_tokens, completion_tokens, cost_usd) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+                params![
+                    datetime_to_timestamp(Utc::now()),
+                    &record.provider,
+                    &record.model,
+                    &record.action,
+                    route,
+                    record.usage.prompt_tokens as i64,
+                    record.usage.completion_tokens as i64,
+                    record.cost_usd
+                ],
+            )?;
+            Ok(())
+        })
+    }
+
+    /// Aggregates logged calls since `since` (all time when `None`).
+    pub fn usage_summary(
+        &self,
+        grouping: UsageGrouping,
+        since: Option<DateTime<Utc>>,
+    ) -> anyhow::Result<Vec<UsageSummaryRow>> {
+        let key = match grouping {
+            UsageGrouping::Day => "strftime('%Y-%m-%d', created_at, 'unixepoch')",
+            UsageGrouping::Model => "model",
+            UsageGrouping::Action => "action",
+        };
+        let sql = format!(
+            "SELECT {key} AS bucket, COUNT(*), SUM(prompt_tokens), SUM(completion_tokens), COALESCE(SUM(cost_usd), 0) \
+            FROM ai_usage_log WHERE created_at >= ?1 GROUP BY bucket ORDER BY bucket DESC"
+        );
+        self.read(|conn| {
+            let mut stmt = conn.prepare(&sql)?;
+            let since_ts = since.map(datetime_to_timestamp).unwrap_or(i64::MIN);
+            let rows = stmt.query_map(params![since_ts], |row| {
+                Ok(UsageSummaryRow {
+                    key: row.get(0)?,
+                    calls: row.get::<_, i64>(1)? as u64,
+                    prompt_tokens: row.get::<_, i64>(2)? as u64,
+                    completion_tokens: row.get::<_, i64>(3)? as u64,
+                    cost_usd: row.get(4)?,
+                })
+            })?;
+            Ok(rows.collect::<Result<Vec<_>, _>>()?)
+        })
+    }
+
+    pub fn budget_status(&self, pricing: &AiPricing) -> anyhow::Result<BudgetStatus> {
+        self.read(|conn| {
+            let month_cost_usd: f64 = conn.query_row(
+                "SELECT COALESCE(SUM(cost_usd), 0) FROM ai_usage_log WHERE created_at >= ?1",
+                params![datetime_to_timestamp(month_start(Utc::now()))],
+                |row| row.get(0),
+            )?;
+            let budget = pricing.monthly_budget_usd.filter(|budget| *budget > 0.0);
+            Ok(BudgetStatus {
+                month_cost_usd,
+                monthly_budget_usd: budget,
+                remaining_usd: budget.map(|budget| (budget - month_cost_usd).max(0.0)),
+                exceeded: budget.is_some_and(|budget| month_cost_usd >= budget),
+            })
+        })
+    }
+}
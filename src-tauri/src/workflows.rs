@@ -0,0 +1,335 @@
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use rusqlite::{params, Connection, OptionalExtension, Row};
+use serde::{Deserialize, Serialize};
+
+use crate::ai_client::{AiActionKind, AiActionRequest};
+use crate::ai_service::{self, AiContext};
+use crate::db::{datetime_to_timestamp, timestamp_to_datetime, ClipItem, DbState};
+
+/// One step of a workflow: either a built-in action or a stored template.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct WorkflowStep {
+    #[serde(default)]
+    pub action: Option<AiActionKind>,
+    #[serde(default)]
+    pub template_id: Option<i64>,
+    #[serde(default)]
+    pub language: Option<String>,
+    #[serde(default)]
+    pub custom_prompt: Option<String>,
+    #[serde(default)]
+    pub model: Option<String>,
+    #[serde(default)]
+    pub temperature: Option<f32>,
+    #[serde(default)]
+    pub json_schema: Option<serde_json::Value>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Workflow {
+    pub id: i64,
+    pub name: String,
+    pub steps: Vec<WorkflowStep>,
+    pub provider_id: Option<i64>,
+    pub shortcut: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct WorkflowDraft {
+    pub name: String,
+    pub steps: Vec<WorkflowStep>,
+    #[serde(default)]
+    pub provider_id: Option<i64>,
+    #[serde(default)]
+    pub shortcut: Option<String>,
+}
+
+/// Connection settings for a run. A connection either names a provider profile
+/// or brings its own endpoint and key; with neither, the profile stored on the
+/// workflow is used.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct WorkflowConnection {
+    #[serde(default)]
+    pub provider_id: Option<i64>,
+    #[serde(default)]
+    pub api_key: Option<String>,
+    #[serde(default)]
+    pub base_url: Option<String>,
+    #[serde(default)]
+    pub model: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "status", rename_all = "camelCase")]
+pub enum WorkflowProgress {
+    Started {
+        workflow_id: i64,
+        index: usize,
+        total: usize,
+        label: String,
+    },
+    Finished {
+        workflow_id: i64,
+        index: usize,
+        total: usize,
+        label: String,
+        saved_clip: Option<ClipItem>,
+        preview: String,
+    },
+    Failed {
+        workflow_id: i64,
+        index: usize,
+        total: usize,
+        label: String,
+        error: String,
+    },
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct WorkflowStepResult {
+    pub index: usize,
+    pub label: String,
+    pub result: String,
+    pub clip_id: Option<i64>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct WorkflowRun {
+    pub workflow_id: i64,
+    pub steps: Vec<WorkflowStepResult>,
+    pub output: String,
+    pub output_clip_id: Option<i64>,
+}
+
+pub(crate) fn migrate(conn: &Connection) -> Result<()> {
+    conn.execute_batch(
+        r#"
+        CREATE TABLE IF NOT EXISTS ai_workflows (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            name TEXT NOT NULL UNIQUE,
+            steps TEXT NOT NULL,
+            provider_id INTEGER REFERENCES ai_providers(id) ON DELETE SET NULL,
+            shortcut TEXT,
+            created_at INTEGER NOT NULL,
+            updated_at INTEGER NOT NULL
+        );
+        "#,
+    )?;
+    Ok(())
+}
+
+impl WorkflowStep {
+    fn label(&self) -> String {
+        match (self.template_id, &self.action) {
+            (Some(id), _) => format!("template:{id}"),
+            (None, Some(action)) => action.as_str().to_string(),
+            (None, None) => "unknown".to_string(),
+        }
+    }
+}
+
+impl WorkflowDraft {
+    fn validate(&self) -> Result<()> {
+        if self.name.trim().is_empty() {
+            anyhow::bail!("workflow name must not be empty");
+        }
+        if self.steps.is_empty() {
+            anyhow::bail!("workflow `{}` has no steps", self.name);
+        }
+        for (index, step) in self.steps.iter().enumerate() {
+            if step.action.is_none() && step.template_id.is_none() {
+                anyhow::bail!("step {} needs an action or a template", index + 1);
+            }
+            if index > 0
+                && step
+                    .action
+                    .as_ref()
+                    .is_some_and(|action| action.is_vision())
+            {
+                anyhow::bail!("step {}: image actions can only run first", index + 1);
+            }
+        }
+        Ok(())
+    }
+}
+
+impl DbState {
+    pub fn list_workflows(&self) -> Result<Vec<Workflow>> {
+        self.read(|conn| {
+            let mut stmt = conn.prepare(
+                "SELECT id, name, steps, provider_id, shortcut, created_at, updated_at FROM ai_workflows ORDER BY name COLLATE NOCASE",
+            )?;
+            let rows = stmt.query_map([], map_workflow_row)?;
+            Ok(rows.collect::<Result<Vec<_>, _>>()?)
+        })
+    }
+
+    pub fn get_workflow(&self, id: i64) -> Result<Option<Workflow>> {
+        self.read(|conn| load_workflow(conn, id))
+    }
+
+    /// Creates a workflow, or updates workflow `id` when given.
+    pub fn save_workflow(&self, id: Option<i64>, draft: WorkflowDraft) -> Result<Workflow> {
+        draft.validate()?;
+        let steps = serde_json::to_string(&draft.steps)?;
+        let now = datetime_to_timestamp(Utc::now());
+        self.write(move |conn| {
+            let shortcut = draft
+                .shortcut
+                .as_deref()
+                .map(str::trim)
+                .filter(|shortcut| !shortcut.is_empty());
+            let id = match id {
+                Some(id) => {
+                    let updated = conn.execute(
+                        "UPDATE ai_workflows SET name = ?1, steps = ?2, provider_id = ?3, shortcut = ?4, updated_at = ?5 WHERE id = ?6",
+                        params![draft.name.trim(), steps, draft.provider_id, shortcut, now, id],
+                    )?;
+                    if updated == 0 {
+                        anyhow::bail!("workflow {} does not exist", id);
+                    }
+                    id
+                }
+                None => {
+                    conn.execute(
+                        "INSERT INTO ai_workflows (name, steps, provider_id, shortcut, created_at, updated_at) VALUES (?1, ?2, ?3, ?4, ?5, ?5)",
+                        params![draft.name.trim(), steps, draft.provider_id, shortcut, now],
+                    )
+                    .with_context(|| format!("failed to create workflow `{}`", draft.name))?;
+                    conn.last_insert_rowid()
+                }
+            };
+            load_workflow(conn, id)?.context("failed to load saved workflow")
+        })
+    }
+
+    pub fn delete_workflow(&self, id: i64) -> Result<()> {
+        self.write(move |conn| {
+            conn.execute("DELETE FROM ai_workflows WHERE id = ?1", params![id])?;
+            Ok(())
+        })
+    }
+}
+
+/// Runs the steps in order, feeding each result into the next step. Every
+/// intermediate result is saved as a clip derived from the previous one.
+pub async fn run(
+    ctx: &AiContext,
+    workflow: &Workflow,
+    input: String,
+    source_clip_id: Option<i64>,
+    connection: WorkflowConnection,
+    mut on_progress: impl FnMut(WorkflowProgress),
+) -> Result<WorkflowRun> {
+    let total = workflow.steps.len();
+    let mut current_input = input;
+    let mut current_clip = source_clip_id;
+    let mut results = Vec::with_capacity(total);
+    let has_endpoint = connection
+        .base_url
+        .as_deref()
+        .is_some_and(|url| !url.trim().is_empty());
+    let provider_id = match connection.provider_id {
+        Some(provider_id) => Some(provider_id),
+        None if has_endpoint => None,
+        None => workflow.provider_id,
+    };
+
+    for (index, step) in workflow.steps.iter().enumerate() {
+        let label = step.label();
+        on_progress(WorkflowProgress::Started {
+            workflow_id: workflow.id,
+            index,
+            total,
+            label: label.clone(),
+        });
+        let request = AiActionRequest {
+            action: step.action.clone().unwrap_or(AiActionKind::Custom),
+            input: current_input.clone(),
+            language: step.language.clone(),
+            custom_prompt: step.custom_prompt.clone(),
+            provider_id,
+            api_key: connection.api_key.clone().unwrap_or_default(),
+            base_url: connection.base_url.clone().unwrap_or_default(),
+            model: step.model.clone().or_else(|| connection.model.clone()),
+            temperature: step.temperature,
+            source_clip_id: current_clip,
+            save_result: true,
+            template_id: step.template_id,
+            json_schema: step.json_schema.clone(),
+            ..Default::default()
+        };
+        let response = match ai_service::run(ctx, request).await {
+            Ok(response) => response,
+            Err(err) => {
+                on_progress(WorkflowProgress::Failed {
+                    workflow_id: workflow.id,
+                    index,
+                    total,
+                    label: label.clone(),
+                    error: err.to_string(),
+                });
+                return Err(err.context(format!("workflow step {} ({}) failed", index + 1, label)));
+            }
+        };
+        let saved_clip = response.saved_clip.map(|derived| derived.clip);
+        let clip_id = saved_clip.as_ref().map(|clip| clip.id);
+        on_progress(WorkflowProgress::Finished {
+            workflow_id: workflow.id,
+            index,
+            total,
+            label: label.clone(),
+            saved_clip,
+            preview: response.result.chars().take(120).collect(),
+        });
+        current_input = response.result.clone();
+        current_clip = clip_id.or(current_clip);
+        results.push(WorkflowStepResult {
+            index,
+            label,
+            result: response.result,
+            clip_id,
+        });
+    }
+
+    Ok(WorkflowRun {
+        workflow_id: workflow.id,
+        output_clip_id: results.last().and_then(|step| step.clip_id),
+        steps: results,
+        output: current_input,
+    })
+}
+
+fn load_workflow(conn: &Connection, id: i64) -> Result<Option<Workflow>> {
+    Ok(conn
+        .query_row(
+            "SELECT id, name, steps, provider_id, shortcut, created_at, updated_at FROM ai_workflows WHERE id = ?1",
+            params![id],
+            map_workflow_row,
+        )
+        .optional()?)
+}
+
+fn map_workflow_row(row: &Row<'_>) -> rusqlite::Result<Workflow> {
+    let steps_json: String = row.get(2)?;
+    let steps = serde_json::from_str(&steps_json).map_err(|err| {
+        rusqlite::Error::FromSqlConversionFailure(2, rusqlite::types::Type::Text, Box::new(err))
+    })?;
+    let created_at_ts: i64 = row.get(5)?;
+    let updated_at_ts: i64 = row.get(6)?;
+    Ok(Workflow {
+        id: row.get(0)?,
+        name: row.get(1)?,
+        steps,
+        provider_id: row.get(3)?,
+        shortcut: row.get(4)?,
+        created_at: timestamp_to_datetime(created_at_ts),
+        updated_at: timestamp_to_datetime(updated_at_ts),
+    })
+}
@@ -32,7 +32,32 @@ export function useWindowSync() {
             await history.refresh();
         });
 
-        unlisteners = [unlistenInsert, unlistenUpdate, unlistenRemove];
+        // 监听局域网或远程同步合并事件
+        const unlistenSync = await listen("sync://merged", async (event) => {
+            console.log("[WindowSync] Received sync://merged event:", event.payload);
+            await history.refresh();
+        });
+
+        // 监听撤销/重做事件
+        const unlistenUndo = await listen("undo://applied", async (event) => {
+            console.log("[WindowSync] Received undo://applied event:", event.payload);
+            await history.refresh();
+        });
+
+        // 监听从备份恢复数据库事件
+        const unlistenRestore = await listen("backup://restored", async (event) => {
+            console.log("[WindowSync] Received backup://restored event:", event.payload);
+            await history.refresh();
+        });
+
+        unlisteners = [
+            unlistenInsert,
+            unlistenUpdate,
+            unlistenRemove,
+            unlistenSync,
+            unlistenUndo,
+            unlistenRestore,
+        ];
     });
 
     onUnmounted(() => {
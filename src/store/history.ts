@@ -3,24 +3,38 @@ import { computed, ref, watch } from "vue";
 import { listen, type UnlistenFn } from "@tauri-apps/api/event";
 import { writeText } from "@tauri-apps/plugin-clipboard-manager";
 import { open, save } from "@tauri-apps/plugin-dialog";
-import { readTextFile, writeTextFile } from "@tauri-apps/plugin-fs";
 import type {
   AiActionRequest,
   AiActionResponse,
   ClipItem,
   ClipKind,
   ClipboardDraftPayload,
+  ExportFilter,
+  ExportFormat,
+  ExportSummary,
   HistoryExportPayload,
   HistoryFilter,
+  ImportOptions,
+  ImportReport,
+  RecoveryReport,
 } from "@/types/history";
 import { ClipKind as ClipKindEnum } from "@/types/history";
 import { useSettingsStore } from "./settings";
 import { safeInvoke, isTauriRuntime, TauriUnavailableError, explainTauriFallback } from "@/libs/tauri";
 import { clipMatchesFilter } from "@/utils/content-inspector";
+import { notifyWarning } from "@/utils/notifier";
 import { offlineTranslate, getTranslationQuality } from "@/utils/offline-translator";
 
 const HISTORY_LIMIT = 200;
 
+const EXPORT_EXTENSIONS: Record<ExportFormat, string> = {
+  json: "json",
+  markdown: "md",
+  csv: "csv",
+  html: "html",
+  zip: "zip",
+};
+
 const PREVIEW_DEMO_ITEMS: ClipItem[] = [
   {
     id: 1001,
@@ -85,6 +99,8 @@ function normalizeClip(raw: any): ClipItem {
     isFavorite: Boolean(raw.is_favorite ?? raw.isFavorite),
     createdAt: raw.created_at ?? raw.createdAt ?? new Date().toISOString(),
     updatedAt: raw.updated_at ?? raw.updatedAt ?? new Date().toISOString(),
+    language: raw.language ?? null,
+    sourceApp: raw.source_app ?? raw.sourceApp ?? null,
   };
 
 }
@@ -123,21 +139,6 @@ function buildAiPrompts(request: AiActionRequest): { system: string; user: strin
 }
 
 
-function serializeClip(item: ClipItem) {
-  return {
-    id: item.id,
-    kind: item.kind,
-    content: item.content,
-    content_hash: item.contentHash,
-    preview: item.preview ?? null,
-    extra: item.extra ?? null,
-    is_pinned: item.isPinned,
-    is_favorite: item.isFavorite,
-    created_at: item.createdAt,
-    updated_at: item.updatedAt,
-  };
-}
-
 export const useHistoryStore = defineStore("history", () => {
   const items = ref<ClipItem[]>([]);
   const filter = ref<HistoryFilter>("all");
@@ -305,6 +306,30 @@ export const useHistoryStore = defineStore("history", () => {
     } catch (error) {
       raise("无法同步应用状态", error);
     }
+    await reportRecovery();
+  }
+
+  function describeRecovery(report: RecoveryReport) {
+    if (report.outcome === "locked") {
+      return "数据库被其他程序占用，历史记录暂时可能无法读写";
+    }
+    const source =
+      report.outcome === "restoredBackup"
+        ? `已从备份 ${report.backup} 恢复`
+        : "已新建空数据库";
+    return `数据库已损坏，${source}，并从损坏文件中找回 ${report.salvaged_clips} 条记录。损坏文件已移至 ${report.quarantined}`;
+  }
+
+  /** 启动时数据库若经过自动修复，提示一次 */
+  async function reportRecovery() {
+    try {
+      const report = await safeInvoke<RecoveryReport | null>("take_recovery_report");
+      if (report) {
+        notifyWarning(describeRecovery(report), 15000);
+      }
+    } catch (error) {
+      console.error("无法获取数据库恢复信息", error);
+    }
   }
 
   async function setListening(value: boolean) {
@@ -444,7 +469,7 @@ export const useHistoryStore = defineStore("history", () => {
     }
   }
 
-  async function exportHistory() {
+  async function exportHistory(format: ExportFormat = "json", filter?: ExportFilter) {
     try {
       isExporting.value = true;
       if (!isTauriRuntime()) {
@@ -468,15 +493,17 @@ export const useHistoryStore = defineStore("history", () => {
         }
         return;
       }
-      const payload = await safeInvoke<HistoryExportPayload>("export_history");
+      const extension = EXPORT_EXTENSIONS[format];
       const target = await save({
         title: "导出剪贴板历史",
         defaultPath: `vibeclip-pro-history-${new Date()
           .toISOString()
-          .replace(/[:.]/g, "-")}.json`,
+          .replace(/[:.]/g, "-")}.${extension}`,
+        filters: [{ name: format.toUpperCase(), extensions: [extension] }],
       });
       if (!target) return;
-      await writeTextFile(target, JSON.stringify(payload, null, 2));
+      // 由后端按格式渲染并写入文件
+      await safeInvoke<ExportSummary>("export_history_to", { path: target, format, filter });
     } catch (error) {
       raise("导出历史记录失败", error);
     } finally {
@@ -484,10 +511,10 @@ export const useHistoryStore = defineStore("history", () => {
     }
   }
 
-  async function importHistory() {
+  async function importHistory(options: ImportOptions = { strategy: "skip" }): Promise<ImportReport | null> {
     try {
       if (!isTauriRuntime()) {
-        if (typeof document === "undefined") return;
+        if (typeof document === "undefined") return null;
         const input = document.createElement("input");
         input.type = "file";
         input.accept = "application/json";
@@ -498,7 +525,7 @@ export const useHistoryStore = defineStore("history", () => {
           };
           input.click();
         });
-        if (!file) return;
+        if (!file) return null;
         const content = await file.text();
         const payload = JSON.parse(content) as HistoryExportPayload;
         const normalized = payload.items.map(normalizeClip);
@@ -507,21 +534,30 @@ export const useHistoryStore = defineStore("history", () => {
         nextOffset.value = items.value.length;
         hasMore.value = false;
         lastError.value = explainTauriFallback();
-        return;
+        return null;
       }
       const file = await open({
         title: "导入剪贴板历史",
         multiple: false,
-        filters: [{ name: "JSON", extensions: ["json"] }],
+        filters: [
+          { name: "JSON", extensions: ["json", "jsonl", "zip"] },
+          // CopyQ、Ditto、Maccy、GPaste 的历史文件
+          { name: "其他剪贴板管理器", extensions: ["dat", "db", "sqlite", "store", "xml"] },
+        ],
+      });
+      if (!file) return null;
+      // 后端自动识别文件格式，并按内容哈希处理重复条目
+      const report = await safeInvoke<ImportReport>("import_history_file", {
+        path: file as string,
+        options,
       });
-      if (!file) return;
-      const content = await readTextFile(file as string);
-      const payload = JSON.parse(content) as HistoryExportPayload;
-      const normalized = payload.items.map(serializeClip);
-      await safeInvoke("import_history", { items: normalized });
-      await refresh();
+      if (!report.dry_run) {
+        await refresh();
+      }
+      return report;
     } catch (error) {
       raise("导入历史记录失败", error);
+      return null;
     }
   }
 
@@ -4,7 +4,7 @@ import type { GlobalThemeOverrides } from "naive-ui";
 import { disable as disableAutoLaunch, enable as enableAutoLaunch, isEnabled as isAutoLaunchEnabled } from "@tauri-apps/plugin-autostart";
 import { notifyError } from "@/utils/notifier";
 import { safeInvoke, isTauriRuntime, explainTauriFallback, TauriUnavailableError } from "@/libs/tauri";
-import type { AiActionKind } from "@/types/history";
+import type { AiActionKind, RuntimePreferencesUpdate } from "@/types/history";
 
 const STORAGE_KEY = "vibeclip.settings";
 const LOCAL_STORAGE_KEY = "vibeclip.settings.preview";
@@ -657,24 +657,24 @@ export const useSettingsStore = defineStore("settings", () => {
       .map(value => value.trim())
       .filter(value => value.length > 0);
     const retentionDays = stateRefs.historyRetentionDays.value;
+    // Only the fields this store owns; the backend keeps the rest as saved.
+    const preferences: RuntimePreferencesUpdate = {
+      dedupeEnabled: stateRefs.dedupeEnabled.value,
+      debounceIntervalMs: 320,
+      ignoreSelfCopies: stateRefs.ignoreSelfCopies.value,
+      ignoredKeywords: ignored,
+      logLevel: stateRefs.logLevel.value,
+      retention: {
+        maxEntries: stateRefs.historyLimit.value > 0 ? stateRefs.historyLimit.value : null,
+        maxAgeDays:
+          typeof retentionDays === "number" && retentionDays > 0
+            ? Math.trunc(retentionDays)
+            : null,
+        vacuumOnStart: true,
+      },
+    };
     try {
-      await safeInvoke("update_runtime_preferences", {
-        preferences: {
-          dedupeEnabled: stateRefs.dedupeEnabled.value,
-          debounceIntervalMs: 320,
-          ignoreSelfCopies: stateRefs.ignoreSelfCopies.value,
-          ignoredKeywords: ignored,
-          logLevel: stateRefs.logLevel.value,
-          retention: {
-            maxEntries: stateRefs.historyLimit.value > 0 ? stateRefs.historyLimit.value : null,
-            maxAgeDays:
-              typeof retentionDays === "number" && retentionDays > 0
-                ? Math.trunc(retentionDays)
-                : null,
-            vacuumOnStart: true,
-          },
-        },
-      });
+      await safeInvoke("update_runtime_preferences", { preferences });
     } catch (error) {
       recordError("同步运行偏好失败", error, true);
     }
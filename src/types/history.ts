@@ -15,6 +15,8 @@ export interface ClipItem {
   isFavorite: boolean;
   createdAt: string;
   updatedAt: string;
+  language?: string | null;
+  sourceApp?: string | null;
 }
 
 export interface ClipboardDraftPayload {
@@ -45,26 +47,506 @@ export type AiActionKind =
   | "summarize"
   | "polish"
   | "jsonify"
-  | "custom";
+  | "custom"
+  | "ocr"
+  | "describe";
 
 export interface AiActionRequest {
   action: AiActionKind;
   input: string;
   language?: string;
   customPrompt?: string;
+  providerId?: number;
   apiKey: string;
   baseUrl: string;
   model?: string;
   temperature?: number;
+  sourceClipId?: number;
+  saveResult?: boolean;
+  bypassCache?: boolean;
+  templateId?: number;
+  jsonSchema?: Record<string, unknown>;
+}
+
+export interface TokenUsage {
+  prompt_tokens: number;
+  completion_tokens: number;
+}
+
+export type AiRoute = "remote" | "local" | "cache";
+
+export type AiCacheStatus = "hit" | "miss" | "bypassed" | "disabled";
+
+export interface ClipProvenance {
+  clip_id: number;
+  derived_from: number | null;
+  action: string;
+  model: string | null;
+  prompt: string | null;
+  created_at: string;
+}
+
+export interface DerivedClip {
+  clip: ClipItem;
+  provenance: ClipProvenance;
 }
 
 export interface AiActionResponse {
   result: string;
   used_prompt: string;
+  model?: string;
   finished_at: string;
+  saved_clip?: DerivedClip | null;
+  cache?: AiCacheStatus;
+  json_valid?: boolean | null;
+  json_errors?: string[];
+  route?: AiRoute;
+  usage?: TokenUsage | null;
+  cost_usd?: number | null;
 }
 
 export interface HistoryExportPayload {
   exported_at: number;
   items: ClipItem[];
 }
+
+export interface PromptTemplate {
+  id: number;
+  name: string;
+  description: string | null;
+  system_prompt: string;
+  user_prompt: string;
+  created_at: string;
+  updated_at: string;
+}
+
+export interface PromptTemplateDraft {
+  name: string;
+  description?: string;
+  systemPrompt?: string;
+  userPrompt: string;
+}
+
+export type TemplateFormat = "json" | "toml";
+
+export interface LocalEndpointProbe {
+  reachable: boolean;
+  base_url: string;
+  latency_ms: number;
+  models: string[];
+  error: string | null;
+}
+
+export interface ProviderProfile {
+  id: number;
+  name: string;
+  base_url: string;
+  model: string | null;
+  has_api_key: boolean;
+  created_at: string;
+  updated_at: string;
+}
+
+export interface ProviderProfileDraft {
+  name: string;
+  baseUrl: string;
+  model?: string;
+  /** Omit to keep the stored key, pass an empty string to remove it. */
+  apiKey?: string;
+}
+
+/** Secrets the backend keeps encrypted; they can be set or cleared, never read back. */
+export type SecretName =
+  | "localAiApiKey"
+  | "remoteSyncPassphrase"
+  | "webDavPassword"
+  | "s3SecretAccessKey";
+
+export type UsageGrouping = "day" | "model" | "action";
+
+export interface UsageSummaryRow {
+  key: string;
+  calls: number;
+  prompt_tokens: number;
+  completion_tokens: number;
+  cost_usd: number;
+}
+
+export interface BudgetStatus {
+  month_cost_usd: number;
+  monthly_budget_usd: number | null;
+  remaining_usd: number | null;
+  exceeded: boolean;
+}
+
+export interface WorkflowStep {
+  action?: AiActionKind;
+  templateId?: number;
+  language?: string;
+  customPrompt?: string;
+  model?: string;
+  temperature?: number;
+  jsonSchema?: unknown;
+}
+
+export interface Workflow {
+  id: number;
+  name: string;
+  steps: WorkflowStep[];
+  provider_id: number | null;
+  shortcut: string | null;
+  created_at: string;
+  updated_at: string;
+}
+
+export interface WorkflowDraft {
+  name: string;
+  steps: WorkflowStep[];
+  providerId?: number;
+  shortcut?: string;
+}
+
+export interface WorkflowConnection {
+  providerId?: number;
+  apiKey?: string;
+  baseUrl?: string;
+  model?: string;
+}
+
+export type WorkflowProgress =
+  | { status: "started"; workflow_id: number; index: number; total: number; label: string }
+  | {
+      status: "finished";
+      workflow_id: number;
+      index: number;
+      total: number;
+      label: string;
+      saved_clip: ClipItem | null;
+      preview: string;
+    }
+  | { status: "failed"; workflow_id: number; index: number; total: number; label: string; error: string };
+
+export interface WorkflowStepResult {
+  index: number;
+  label: string;
+  result: string;
+  clip_id: number | null;
+}
+
+export interface WorkflowRun {
+  workflow_id: number;
+  steps: WorkflowStepResult[];
+  output: string;
+  output_clip_id: number | null;
+}
+
+export interface AiBatchRequest extends Omit<AiActionRequest, "input" | "sourceClipId" | "saveResult"> {
+  batchId: string;
+  clipIds: number[];
+}
+
+export interface AiBatchProgress {
+  batch_id: string;
+  clip_id: number;
+  completed: number;
+  total: number;
+  ok: boolean;
+  error: string | null;
+}
+
+export interface AiBatchItemResult {
+  clip_id: number;
+  ok: boolean;
+  result: string | null;
+  saved_clip: DerivedClip | null;
+  error: string | null;
+}
+
+export interface AiBatchReport {
+  batch_id: string;
+  cancelled: boolean;
+  succeeded: number;
+  failed: number;
+  items: AiBatchItemResult[];
+}
+
+export type TextTransform =
+  | "camelCase"
+  | "snakeCase"
+  | "titleCase"
+  | "upperCase"
+  | "lowerCase"
+  | "trimLines"
+  | "dedupeLines"
+  | "sortLines"
+  | "jsonPretty"
+  | "jsonMinify"
+  | "urlEncode"
+  | "urlDecode"
+  | "base64Encode"
+  | "base64Decode"
+  | "stripHtml"
+  | "stripMarkdown"
+  | "count";
+
+export interface TransformRequest {
+  transform: TextTransform;
+  input: string;
+  descending?: boolean;
+  sourceClipId?: number;
+  saveResult?: boolean;
+}
+
+export interface TextStats {
+  characters: number;
+  characters_without_spaces: number;
+  words: number;
+  lines: number;
+}
+
+export interface TransformResponse {
+  transform: TextTransform;
+  result: string;
+  stats: TextStats | null;
+  saved_clip: DerivedClip | null;
+}
+
+export interface LocalApiStatus {
+  running: boolean;
+  url: string | null;
+  token: string;
+}
+
+export interface SyncPeer {
+  device_id: string;
+  name: string;
+  last_address: string | null;
+  paired_at: string;
+  last_synced_at: string | null;
+}
+
+export interface DiscoveredPeer {
+  device_id: string;
+  name: string;
+  addresses: string[];
+  paired: boolean;
+}
+
+export interface LanSyncStatus {
+  device_id: string;
+  device_name: string;
+  port: number | null;
+  pairing_code: string | null;
+  pairing_expires_at: string | null;
+  peers: SyncPeer[];
+  discovered: DiscoveredPeer[];
+}
+
+export interface MergeStats {
+  inserted: number;
+  updated: number;
+  conflicts: number;
+}
+
+export interface SyncReport {
+  device_id: string;
+  name: string;
+  received: MergeStats;
+  sent: number;
+  remote: MergeStats;
+}
+
+export interface RemoteSyncReport {
+  received: MergeStats;
+  sent: number;
+  devices: number;
+}
+
+export interface RemoteSyncStatus {
+  enabled: boolean;
+  syncing: boolean;
+  last_synced_at: string | null;
+  last_report: RemoteSyncReport | null;
+  last_error: string | null;
+}
+
+export type ClipOp =
+  | "insert"
+  | "recapture"
+  | "updateFlags"
+  | "updateContent"
+  | "delete"
+  | "import";
+
+export type ClipOrigin =
+  | "watcher"
+  | "ui"
+  | "tray"
+  | "import"
+  | "retention"
+  | "ai"
+  | "sync"
+  | "cli"
+  | "api"
+  | "ipc"
+  | "undo";
+
+export interface ClipEvent {
+  id: number;
+  op: ClipOp;
+  clip_id: number | null;
+  content_hash: string | null;
+  origin: ClipOrigin;
+  detail: Record<string, unknown> | null;
+  created_at: string;
+}
+
+export interface ClipEventQuery {
+  clipId?: number;
+  origin?: ClipOrigin;
+  op?: ClipOp;
+  since?: number;
+  limit?: number;
+  offset?: number;
+}
+
+export interface UndoStatus {
+  can_undo: boolean;
+  can_redo: boolean;
+  undo_label: string | null;
+  redo_label: string | null;
+}
+
+export interface BackupInfo {
+  file_name: string;
+  created_at: string;
+  size: number;
+}
+
+export interface BackupRestoreResult {
+  file_name: string;
+  safety_copy: BackupInfo | null;
+}
+
+export type RecoveryOutcome = "locked" | "restoredBackup" | "fresh";
+
+export interface RecoveryReport {
+  outcome: RecoveryOutcome;
+  reason: string;
+  quarantined: string | null;
+  backup: string | null;
+  salvaged_clips: number;
+  salvaged_rows: number;
+}
+
+export type ImportStrategy = "skip" | "overwrite" | "keepBoth" | "mergeFlags";
+
+export type ImportFormat =
+  | "vibeClip"
+  | "clipArray"
+  | "jsonLines"
+  | "archive"
+  | "copyq"
+  | "ditto"
+  | "maccy"
+  | "gpaste"
+  | "clipman";
+
+export interface ImportOptions {
+  strategy?: ImportStrategy;
+  dryRun?: boolean;
+}
+
+export interface ImportItemError {
+  index: number;
+  message: string;
+}
+
+export interface ImportReport {
+  format: ImportFormat;
+  strategy: ImportStrategy;
+  dry_run: boolean;
+  total: number;
+  inserted: number;
+  skipped: number;
+  overwritten: number;
+  kept_both: number;
+  merged: number;
+  failed: number;
+  errors: ImportItemError[];
+}
+
+export type ExportFormat = "json" | "markdown" | "csv" | "html" | "zip";
+
+export interface ExportFilter {
+  query?: string;
+  favoritesFirst?: boolean;
+  limit?: number;
+  offset?: number;
+}
+
+export interface ExportSummary {
+  format: ExportFormat;
+  path: string;
+  clips: number;
+  bytes: number;
+}
+
+/** Backend runtime preferences. Updates are partial: omitted fields keep their saved value. */
+export interface RuntimePreferences {
+  dedupeEnabled: boolean;
+  debounceIntervalMs: number;
+  ignoreSelfCopies: boolean;
+  ignoredKeywords: string[];
+  retention: {
+    maxEntries: number | null;
+    maxAgeDays: number | null;
+    vacuumOnStart: boolean;
+  };
+  logLevel: string;
+  aiCache: { enabled: boolean; ttlHours: number; maxEntries: number };
+  localAi: { enabled: boolean; baseUrl: string; model: string };
+  aiPricing: {
+    models: { model: string; promptPerMillion: number; completionPerMillion: number }[];
+    monthlyBudgetUsd: number | null;
+  };
+  aiBatch: { concurrency: number; requestsPerMinute: number };
+  translationPair: { primary: string; secondary: string };
+  localApi: { enabled: boolean; port: number };
+  lanSync: {
+    enabled: boolean;
+    port: number;
+    deviceName: string | null;
+    intervalMinutes: number;
+  };
+  remoteSync: {
+    enabled: boolean;
+    backend:
+      | { type: "webdav"; url: string; username: string }
+      | {
+          type: "s3";
+          endpoint: string;
+          region: string;
+          bucket: string;
+          prefix: string;
+          accessKeyId: string;
+          pathStyle: boolean;
+        };
+    intervalMinutes: number;
+  };
+  undoDepth: number;
+  backup: {
+    enabled: boolean;
+    intervalHours: number;
+    keepDaily: number;
+    keepWeekly: number;
+  };
+}
+
+export type RuntimePreferencesUpdate = {
+  [K in keyof RuntimePreferences]?: RuntimePreferences[K] extends unknown[]
+    ? RuntimePreferences[K]
+    : RuntimePreferences[K] extends object
+      ? Partial<RuntimePreferences[K]>
+      : RuntimePreferences[K];
+};